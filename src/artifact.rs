@@ -2,61 +2,598 @@
 //!
 //! Artifacts are the cross-domain work products (API contracts, type defs,
 //! specs) that flow between agents. The filesystem implementation stores
-//! them in each domain's `.orchestrator/artifacts/` directory.
+//! them in each domain's `.orchestrator/artifacts/` directory, keeping
+//! every stored version under `.orchestrator/artifact_versions/` and a
+//! checksummed record of each in `.orchestrator/manifest.json`.
+//!
+//! Content is streamed through staging files rather than buffered, so
+//! large fixtures and datasets route without being loaded into memory.
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::bundle::{self, BundleVersion};
+use crate::cas::CasArtifactStore;
+use crate::config::{ArtifactBackend, DomainConfig, ProjectConfig};
+use crate::git_store::GitArtifactStore;
+use crate::manifest::{content_type_for, IntegrityIssue, Manifest, ManifestEntry, Provenance};
+
+/// Buffer size for streaming artifact content.
+const STREAM_BUF_BYTES: usize = 64 * 1024;
+
+/// One immutable stored version of an artifact.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactVersion {
+    /// Sequential version number, starting at 1.
+    pub version: u32,
+    /// Hex-encoded SHA-256 of the content.
+    pub hash: String,
+    pub size_bytes: u64,
+    pub stored_at: DateTime<Utc>,
+    /// Domain that produced this content.
+    pub producer: String,
+    /// Task this content was routed for.
+    #[serde(default)]
+    pub task: String,
+    /// Set when this version was created by rolling back to an earlier one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rolled_back_from: Option<u32>,
+    /// Commit holding this version, for backends that record one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+/// An artifact name that would resolve outside the artifacts tree.
+///
+/// Returned (wrapped in `anyhow::Error`) by [`validate_name`] and by
+/// stores that detect symlink escapes, so callers can downcast and
+/// report the attempt.
+#[derive(Debug, Clone)]
+pub struct UnsafeArtifactName {
+    pub name: String,
+    pub reason: &'static str,
+}
+
+impl std::fmt::Display for UnsafeArtifactName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unsafe artifact name `{}`: {}", self.name, self.reason)
+    }
+}
+
+impl std::error::Error for UnsafeArtifactName {}
+
+/// Check that an artifact name is a relative path confined to the
+/// artifacts tree. Nested names like `api/users.yaml` are allowed;
+/// absolute paths, `.`/`..` components, and empty segments are not.
+pub fn validate_name(name: &str) -> Result<()> {
+    let reject = |reason| {
+        Err(UnsafeArtifactName {
+            name: name.to_string(),
+            reason,
+        }
+        .into())
+    };
+
+    if name.is_empty() {
+        return reject("name is empty");
+    }
+    if name.contains('\0') {
+        return reject("name contains a NUL byte");
+    }
+    if name.contains('\\') {
+        return reject("name contains a backslash");
+    }
+    if Path::new(name).has_root() || name.starts_with('/') {
+        return reject("name is absolute");
+    }
+    for segment in name.split('/') {
+        match segment {
+            "" => return reject("name has an empty path segment"),
+            "." => return reject("name contains `.`"),
+            ".." => return reject("name contains `..`"),
+            _ => {}
+        }
+    }
+    if Path::new(name)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return reject("name is not a plain relative path");
+    }
+    Ok(())
+}
+
+/// Which configured limit an artifact would exceed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaLimit {
+    /// `max_artifact_bytes`: the size of a single artifact.
+    ArtifactSize,
+    /// `artifact_quota_bytes`: the total size of a domain's artifacts.
+    DomainTotal,
+}
+
+/// An artifact rejected because it would exceed a domain's size limits.
+///
+/// Returned (wrapped in `anyhow::Error`) by [`ArtifactStore::check_limits`]
+/// and [`ArtifactStore::store`], so callers can downcast and report it.
+#[derive(Debug, Clone)]
+pub struct QuotaExceeded {
+    pub domain: String,
+    pub name: String,
+    pub limit: QuotaLimit,
+    pub limit_bytes: u64,
+    /// Artifact size, or the domain total the store would have reached.
+    /// A stream is read only until it passes `max_artifact_bytes`, so
+    /// for an oversized one this is a lower bound.
+    pub requested_bytes: u64,
+}
+
+impl std::fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.limit {
+            QuotaLimit::ArtifactSize => write!(
+                f,
+                "artifact `{}` (at least {} bytes) is over domain `{}`'s max_artifact_bytes of {}",
+                self.name, self.requested_bytes, self.domain, self.limit_bytes
+            ),
+            QuotaLimit::DomainTotal => write!(
+                f,
+                "storing artifact `{}` would bring domain `{}` to {} bytes, over its artifact_quota_bytes of {}",
+                self.name, self.domain, self.requested_bytes, self.limit_bytes
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+/// Size limits a domain places on the artifacts stored into it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ArtifactLimits {
+    pub max_artifact_bytes: Option<u64>,
+    pub quota_bytes: Option<u64>,
+}
+
+impl ArtifactLimits {
+    pub fn from_config(config: &DomainConfig) -> Self {
+        Self {
+            max_artifact_bytes: config.max_artifact_bytes,
+            quota_bytes: config.artifact_quota_bytes,
+        }
+    }
+
+    /// Limits for every domain in a project config, keyed by domain name.
+    pub fn for_project(config: &ProjectConfig) -> HashMap<String, Self> {
+        config
+            .domains
+            .iter()
+            .map(|(id, dc)| (id.as_str().to_owned(), Self::from_config(dc)))
+            .collect()
+    }
+
+    /// Check `incoming` (name, size) pairs against these limits.
+    ///
+    /// The quota counts the current files under `artifacts_dir`; an
+    /// incoming artifact replaces any existing file of the same name.
+    /// Stored version history is not counted.
+    pub fn check(
+        &self,
+        domain: &str,
+        artifacts_dir: &Path,
+        incoming: &[(&str, u64)],
+    ) -> Result<()> {
+        if let Some(max) = self.max_artifact_bytes {
+            if let Some(&(name, size)) = incoming.iter().find(|(_, size)| *size > max) {
+                return Err(QuotaExceeded {
+                    domain: domain.to_string(),
+                    name: name.to_string(),
+                    limit: QuotaLimit::ArtifactSize,
+                    limit_bytes: max,
+                    requested_bytes: size,
+                }
+                .into());
+            }
+        }
+
+        let Some(quota) = self.quota_bytes else {
+            return Ok(());
+        };
+        let mut usage = HashMap::new();
+        if artifacts_dir.exists() {
+            let mut names = Vec::new();
+            collect_names(artifacts_dir, "", &mut names)?;
+            for name in names {
+                let size = std::fs::metadata(artifacts_dir.join(&name))?.len();
+                usage.insert(name, size);
+            }
+        }
+        for &(name, size) in incoming {
+            usage.insert(name.to_string(), size);
+            let total: u64 = usage.values().sum();
+            if total > quota {
+                return Err(QuotaExceeded {
+                    domain: domain.to_string(),
+                    name: name.to_string(),
+                    limit: QuotaLimit::DomainTotal,
+                    limit_bytes: quota,
+                    requested_bytes: total,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
 
-use anyhow::Result;
+impl ArtifactVersion {
+    /// The version following `history`, which must be oldest first.
+    pub(crate) fn next(
+        history: &[ArtifactVersion],
+        hash: String,
+        size_bytes: u64,
+        provenance: &Provenance,
+        rolled_back_from: Option<u32>,
+    ) -> Self {
+        Self {
+            version: history.last().map_or(1, |v| v.version + 1),
+            hash,
+            size_bytes,
+            stored_at: Utc::now(),
+            producer: provenance.producer.clone(),
+            task: provenance.task.clone(),
+            rolled_back_from,
+            commit: None,
+        }
+    }
+}
 
 /// Trait for artifact storage backends.
 pub trait ArtifactStore: Send + Sync {
     /// Store an artifact, making it available to the target domain.
-    fn store(&self, domain: &str, name: &str, content: &[u8]) -> Result<()>;
+    ///
+    /// Records a new version unless the content matches the current one,
+    /// in which case the current copy and manifest entry are restored.
+    /// Fails with [`QuotaExceeded`] if the domain's size limits forbid it.
+    fn store(
+        &self,
+        domain: &str,
+        name: &str,
+        content: &[u8],
+        provenance: &Provenance,
+    ) -> Result<ArtifactVersion> {
+        self.store_reader(domain, name, &mut &content[..], provenance)
+    }
+
+    /// Store an artifact read from a stream, without holding it in memory.
+    fn store_reader(
+        &self,
+        domain: &str,
+        name: &str,
+        reader: &mut dyn Read,
+        provenance: &Provenance,
+    ) -> Result<ArtifactVersion>;
+
+    /// Copy the current version of an artifact from one domain into another.
+    ///
+    /// The content is never buffered: it is cloned (reflinked) where the
+    /// filesystem allows and streamed otherwise. The source is checked
+    /// against its manifest checksum, as by [`ArtifactStore::retrieve`].
+    fn copy(
+        &self,
+        from: &str,
+        to: &str,
+        name: &str,
+        provenance: &Provenance,
+    ) -> Result<ArtifactVersion>;
 
-    /// Retrieve an artifact by domain and name.
+    /// Copy several artifacts from one domain into another as one bundle.
+    ///
+    /// Every member is staged and checked (integrity and size limits)
+    /// before any is stored, and if storing one fails the members already
+    /// stored are put back as they were, so the target receives all of
+    /// them or none. The set is recorded as the next version of `bundle`.
+    fn copy_bundle(
+        &self,
+        from: &str,
+        to: &str,
+        bundle: &str,
+        names: &[String],
+        provenance: &Provenance,
+    ) -> Result<BundleVersion>;
+
+    /// List stored versions of a bundle, oldest first.
+    fn bundle_versions(&self, domain: &str, bundle: &str) -> Result<Vec<BundleVersion>>;
+
+    /// Retrieve the current version of an artifact by domain and name.
+    ///
+    /// Fails with an [`IntegrityIssue`] if the content no longer matches
+    /// the checksum recorded when it was stored.
     fn retrieve(&self, domain: &str, name: &str) -> Result<Vec<u8>>;
 
+    /// Open the current version of an artifact as a stream.
+    ///
+    /// The checksum is verified as the content is read; a mismatch fails
+    /// the read at end of stream.
+    fn open(&self, domain: &str, name: &str) -> Result<Box<dyn Read + Send>>;
+
     /// List all artifacts available in a domain.
     fn list(&self, domain: &str) -> Result<Vec<String>>;
+
+    /// List stored versions of an artifact, oldest first.
+    fn versions(&self, domain: &str, name: &str) -> Result<Vec<ArtifactVersion>>;
+
+    /// Retrieve a specific stored version of an artifact.
+    fn retrieve_version(&self, domain: &str, name: &str, version: u32) -> Result<Vec<u8>>;
+
+    /// Make an earlier version current again, recorded as a new version.
+    fn rollback(&self, domain: &str, name: &str, version: u32) -> Result<ArtifactVersion>;
+
+    /// Read the domain's artifact manifest.
+    fn manifest(&self, domain: &str) -> Result<Manifest>;
+
+    /// Check every manifest entry against the files on disk.
+    fn verify(&self, domain: &str) -> Result<Vec<IntegrityIssue>>;
+
+    /// Check whether `incoming` (name, size) artifacts fit within the
+    /// domain's size limits, failing with [`QuotaExceeded`] if not.
+    fn check_limits(&self, domain: &str, incoming: &[(&str, u64)]) -> Result<()>;
+}
+
+/// Open the artifact store selected by the project config.
+pub fn open_store(config: &ProjectConfig, state_dir: &Path) -> Arc<dyn ArtifactStore> {
+    match config.artifacts.backend {
+        ArtifactBackend::Fs => Arc::new(FsArtifactStore::from_config(config)),
+        ArtifactBackend::Cas => Arc::new(CasArtifactStore::from_config(
+            config,
+            state_dir.to_path_buf(),
+        )),
+        ArtifactBackend::Git => Arc::new(GitArtifactStore::from_config(
+            config,
+            state_dir.join("artifact-repo"),
+        )),
+    }
 }
 
 /// Filesystem-backed artifact store.
 ///
-/// Artifacts are stored under `<domain_root>/.orchestrator/artifacts/<name>`.
+/// The current content of an artifact lives at
+/// `<domain_root>/.orchestrator/artifacts/<name>`. Every version is kept
+/// under `<domain_root>/.orchestrator/artifact_versions/<name>/`, as
+/// `<hash>` blobs plus a `versions.json` history. Blobs are read-only,
+/// and the current file is a hard link to its blob where possible.
 pub struct FsArtifactStore {
     /// Map of domain name -> domain root path.
     roots: HashMap<String, PathBuf>,
+    /// Size limits per domain; domains without an entry are unlimited.
+    limits: HashMap<String, ArtifactLimits>,
 }
 
 impl FsArtifactStore {
     pub fn new(roots: HashMap<String, PathBuf>) -> Self {
-        Self { roots }
+        Self {
+            roots,
+            limits: HashMap::new(),
+        }
     }
 
-    fn artifacts_dir(&self, domain: &str) -> Result<PathBuf> {
-        let root = self
-            .roots
+    /// Build a store over every domain in a project config.
+    pub fn from_config(config: &ProjectConfig) -> Self {
+        let roots = config
+            .domains
+            .iter()
+            .map(|(id, dc)| (id.as_str().to_owned(), dc.path.clone()))
+            .collect();
+        Self {
+            limits: ArtifactLimits::for_project(config),
+            ..Self::new(roots)
+        }
+    }
+
+    fn root(&self, domain: &str) -> Result<&PathBuf> {
+        self.roots
             .get(domain)
-            .ok_or_else(|| anyhow::anyhow!("unknown domain: {}", domain))?;
-        Ok(root.join(".orchestrator/artifacts"))
+            .ok_or_else(|| anyhow::anyhow!("unknown domain: {}", domain))
+    }
+
+    fn artifacts_dir(&self, domain: &str) -> Result<PathBuf> {
+        Ok(self.root(domain)?.join(".orchestrator/artifacts"))
+    }
+
+    fn manifest_path(&self, domain: &str) -> Result<PathBuf> {
+        Ok(self.root(domain)?.join(".orchestrator/manifest.json"))
+    }
+
+    fn bundles_path(&self, domain: &str) -> Result<PathBuf> {
+        Ok(self.root(domain)?.join(".orchestrator/bundles.json"))
+    }
+
+    /// Resolve the current path of an artifact, rejecting unsafe names.
+    fn artifact_path(&self, domain: &str, name: &str) -> Result<PathBuf> {
+        confine(&self.artifacts_dir(domain)?, name)
+    }
+
+    fn versions_dir(&self, domain: &str, name: &str) -> Result<PathBuf> {
+        confine(
+            &self.root(domain)?.join(".orchestrator/artifact_versions"),
+            name,
+        )
+    }
+
+    fn read_history(dir: &Path) -> Result<Vec<ArtifactVersion>> {
+        match std::fs::read_to_string(dir.join("versions.json")) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_history(dir: &Path, history: &[ArtifactVersion]) -> Result<()> {
+        write_atomic(
+            &dir.join("versions.json"),
+            serde_json::to_string_pretty(history)?.as_bytes(),
+        )
+    }
+
+    /// Move staged content into the version blobs, unless an identical
+    /// blob is already there.
+    fn put_blob(versions_dir: &Path, staged: Staged) -> Result<()> {
+        let blob = versions_dir.join(&staged.hash);
+        if !blob.exists() {
+            staged.commit(&blob)?;
+        }
+        Ok(())
+    }
+
+    /// Append a version to the history and make it current. The blob
+    /// for `hash` must already exist.
+    fn record(
+        &self,
+        domain: &str,
+        name: &str,
+        hash: String,
+        size_bytes: u64,
+        provenance: &Provenance,
+        rolled_back_from: Option<u32>,
+    ) -> Result<ArtifactVersion> {
+        let versions_dir = self.versions_dir(domain, name)?;
+        let mut history = Self::read_history(&versions_dir)?;
+
+        if rolled_back_from.is_none() {
+            if let Some(latest) = history.last() {
+                if latest.hash == hash {
+                    // Same content: repair the current copy and manifest
+                    // entry in case either was removed or edited.
+                    self.install_current(domain, name, &versions_dir, latest)?;
+                    return Ok(latest.clone());
+                }
+            }
+        }
+
+        let version =
+            ArtifactVersion::next(&history, hash, size_bytes, provenance, rolled_back_from);
+        history.push(version.clone());
+        Self::write_history(&versions_dir, &history)?;
+        self.install_current(domain, name, &versions_dir, &version)?;
+
+        Ok(version)
+    }
+
+    /// Make `version` the current file and manifest entry for `name`.
+    fn install_current(
+        &self,
+        domain: &str,
+        name: &str,
+        versions_dir: &Path,
+        version: &ArtifactVersion,
+    ) -> Result<()> {
+        let current = self.artifact_path(domain, name)?;
+        if let Some(parent) = current.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let blob = versions_dir.join(&version.hash);
+        make_readonly(&blob)?;
+        install_link(&blob, &current)?;
+
+        update_manifest(&self.manifest_path(domain)?, name, version)
     }
 }
 
 impl ArtifactStore for FsArtifactStore {
-    fn store(&self, domain: &str, name: &str, content: &[u8]) -> Result<()> {
-        let dir = self.artifacts_dir(domain)?;
-        std::fs::create_dir_all(&dir)?;
-        std::fs::write(dir.join(name), content)?;
-        Ok(())
+    fn store_reader(
+        &self,
+        domain: &str,
+        name: &str,
+        reader: &mut dyn Read,
+        provenance: &Provenance,
+    ) -> Result<ArtifactVersion> {
+        let versions_dir = self.versions_dir(domain, name)?;
+        let max_bytes = self.limits.get(domain).and_then(|l| l.max_artifact_bytes);
+        let staged = Staged::from_reader(&versions_dir, reader, max_bytes)?;
+        self.check_limits(domain, &[(name, staged.size_bytes)])?;
+
+        let (hash, size_bytes) = (staged.hash.clone(), staged.size_bytes);
+        Self::put_blob(&versions_dir, staged)?;
+        self.record(domain, name, hash, size_bytes, provenance, None)
+    }
+
+    fn copy(
+        &self,
+        from: &str,
+        to: &str,
+        name: &str,
+        provenance: &Provenance,
+    ) -> Result<ArtifactVersion> {
+        let source = self.artifact_path(from, name)?;
+        let versions_dir = self.versions_dir(to, name)?;
+        let staged = Staged::from_file(&versions_dir, &source)?;
+        check_checksum(&self.manifest(from)?, name, &staged.hash)?;
+        self.check_limits(to, &[(name, staged.size_bytes)])?;
+
+        let (hash, size_bytes) = (staged.hash.clone(), staged.size_bytes);
+        Self::put_blob(&versions_dir, staged)?;
+        self.record(to, name, hash, size_bytes, provenance, None)
+    }
+
+    fn copy_bundle(
+        &self,
+        from: &str,
+        to: &str,
+        bundle: &str,
+        names: &[String],
+        provenance: &Provenance,
+    ) -> Result<BundleVersion> {
+        let staged = stage_bundle(self, from, to, &self.artifacts_dir(from)?, names, |name| {
+            self.versions_dir(to, name)
+        })?;
+
+        let (artifacts_dir, manifest_path) = (self.artifacts_dir(to)?, self.manifest_path(to)?);
+        let previous = current_versions(self, to, names)?;
+        let mut stored = Vec::with_capacity(staged.len());
+        let result = (|| {
+            for (name, staged) in staged {
+                let (hash, size_bytes) = (staged.hash.clone(), staged.size_bytes);
+                Self::put_blob(&self.versions_dir(to, &name)?, staged)?;
+                let version = self.record(to, &name, hash, size_bytes, provenance, None)?;
+                stored.push((name, version));
+            }
+            bundle::record(&self.bundles_path(to)?, bundle, &stored, provenance)
+        })();
+        result.inspect_err(|_| {
+            undo_bundle(self, to, &artifacts_dir, &manifest_path, &stored, &previous);
+        })
+    }
+
+    fn bundle_versions(&self, domain: &str, bundle: &str) -> Result<Vec<BundleVersion>> {
+        Ok(bundle::load(&self.bundles_path(domain)?)?
+            .remove(bundle)
+            .unwrap_or_default())
     }
 
     fn retrieve(&self, domain: &str, name: &str) -> Result<Vec<u8>> {
-        let dir = self.artifacts_dir(domain)?;
-        let data = std::fs::read(dir.join(name))?;
-        Ok(data)
+        read_verified(
+            &self.artifact_path(domain, name)?,
+            &self.manifest(domain)?,
+            name,
+        )
+    }
+
+    fn open(&self, domain: &str, name: &str) -> Result<Box<dyn Read + Send>> {
+        open_verified(
+            &self.artifact_path(domain, name)?,
+            &self.manifest(domain)?,
+            name,
+        )
     }
 
+    /// Lists nested artifacts by their `/`-separated relative names.
     fn list(&self, domain: &str) -> Result<Vec<String>> {
         let dir = self.artifacts_dir(domain)?;
         if !dir.exists() {
@@ -64,15 +601,605 @@ impl ArtifactStore for FsArtifactStore {
         }
 
         let mut names = Vec::new();
-        for entry in std::fs::read_dir(dir)? {
-            let entry = entry?;
-            if entry.file_type()?.is_file() {
-                if let Some(name) = entry.file_name().to_str() {
-                    names.push(name.to_string());
+        collect_names(&dir, "", &mut names)?;
+        names.sort();
+        Ok(names)
+    }
+
+    fn versions(&self, domain: &str, name: &str) -> Result<Vec<ArtifactVersion>> {
+        Self::read_history(&self.versions_dir(domain, name)?)
+    }
+
+    fn retrieve_version(&self, domain: &str, name: &str, version: u32) -> Result<Vec<u8>> {
+        let versions_dir = self.versions_dir(domain, name)?;
+        let history = Self::read_history(&versions_dir)?;
+        let Some(entry) = history.iter().find(|v| v.version == version) else {
+            bail!(
+                "artifact `{}` in domain `{}` has no version {}",
+                name,
+                domain,
+                version
+            );
+        };
+        std::fs::read(versions_dir.join(&entry.hash))
+            .with_context(|| format!("reading version {} of artifact `{}`", version, name))
+    }
+
+    fn rollback(&self, domain: &str, name: &str, version: u32) -> Result<ArtifactVersion> {
+        let Some(target) = self
+            .versions(domain, name)?
+            .into_iter()
+            .find(|v| v.version == version)
+        else {
+            bail!(
+                "artifact `{}` in domain `{}` has no version {}",
+                name,
+                domain,
+                version
+            );
+        };
+        if !self.versions_dir(domain, name)?.join(&target.hash).exists() {
+            bail!(
+                "blob for version {} of artifact `{}` is missing",
+                version,
+                name
+            );
+        }
+        let provenance = Provenance::new(target.producer, target.task);
+        self.record(
+            domain,
+            name,
+            target.hash,
+            target.size_bytes,
+            &provenance,
+            Some(version),
+        )
+    }
+
+    fn manifest(&self, domain: &str) -> Result<Manifest> {
+        Manifest::load(&self.manifest_path(domain)?)
+    }
+
+    fn verify(&self, domain: &str) -> Result<Vec<IntegrityIssue>> {
+        verify_tree(&self.artifacts_dir(domain)?, self.manifest(domain)?)
+    }
+
+    fn check_limits(&self, domain: &str, incoming: &[(&str, u64)]) -> Result<()> {
+        let limits = self.limits.get(domain).copied().unwrap_or_default();
+        limits.check(domain, &self.artifacts_dir(domain)?, incoming)
+    }
+}
+
+/// Record `version` as the current state of `name` in a domain manifest.
+pub(crate) fn update_manifest(path: &Path, name: &str, version: &ArtifactVersion) -> Result<()> {
+    let mut manifest = Manifest::load(path)?;
+    manifest.artifacts.insert(
+        name.to_string(),
+        ManifestEntry {
+            sha256: version.hash.clone(),
+            size_bytes: version.size_bytes,
+            content_type: content_type_for(name).to_string(),
+            producer: version.producer.clone(),
+            task: version.task.clone(),
+            routed_at: version.stored_at,
+            version: version.version,
+        },
+    );
+    manifest.save(path)
+}
+
+/// Read an artifact file, checking it against its manifest entry.
+///
+/// Artifacts a domain produced itself have no manifest entry and are
+/// returned as-is.
+pub(crate) fn read_verified(path: &Path, manifest: &Manifest, name: &str) -> Result<Vec<u8>> {
+    let data = std::fs::read(path)?;
+    check_checksum(manifest, name, &sha256_hex(&data))?;
+    Ok(data)
+}
+
+/// Stage every member of a bundle from `source_dir`, checking each
+/// against the source manifest and the whole set against the target's
+/// limits. The target is untouched until the caller commits the result.
+pub(crate) fn stage_bundle<S: ArtifactStore + ?Sized>(
+    store: &S,
+    from: &str,
+    to: &str,
+    source_dir: &Path,
+    names: &[String],
+    staging_dir: impl Fn(&str) -> Result<PathBuf>,
+) -> Result<Vec<(String, Staged)>> {
+    let manifest = store.manifest(from)?;
+    let mut staged = Vec::with_capacity(names.len());
+    for name in names {
+        let source = confine(source_dir, name)?;
+        let member = Staged::from_file(&staging_dir(name)?, &source)?;
+        check_checksum(&manifest, name, &member.hash)?;
+        staged.push((name.clone(), member));
+    }
+
+    let incoming: Vec<(&str, u64)> = staged
+        .iter()
+        .map(|(name, member)| (name.as_str(), member.size_bytes))
+        .collect();
+    store.check_limits(to, &incoming)?;
+    Ok(staged)
+}
+
+/// The current version number of each of `names` in `domain`, if any.
+pub(crate) fn current_versions<S: ArtifactStore + ?Sized>(
+    store: &S,
+    domain: &str,
+    names: &[String],
+) -> Result<HashMap<String, Option<u32>>> {
+    names
+        .iter()
+        .map(|name| {
+            let current = store.versions(domain, name)?.last().map(|v| v.version);
+            Ok((name.clone(), current))
+        })
+        .collect()
+}
+
+/// Put the members of a bundle that failed partway back as they were
+/// before it: each is rolled back to its `previous` version, or removed
+/// along with its manifest entry if it had none. Failures are logged, as
+/// the caller is already reporting the error that caused the undo.
+pub(crate) fn undo_bundle<S: ArtifactStore + ?Sized>(
+    store: &S,
+    domain: &str,
+    artifacts_dir: &Path,
+    manifest_path: &Path,
+    stored: &[(String, ArtifactVersion)],
+    previous: &HashMap<String, Option<u32>>,
+) {
+    let mut removed = Vec::new();
+    for (name, version) in stored {
+        let result = match previous.get(name).copied().flatten() {
+            Some(prev) if prev == version.version => Ok(()),
+            Some(prev) => store.rollback(domain, name, prev).map(|_| ()),
+            None => confine(artifacts_dir, name)
+                .and_then(|path| Ok(std::fs::remove_file(path)?))
+                .map(|()| removed.push(name.as_str())),
+        };
+        if let Err(e) = result {
+            tracing::error!(domain, artifact = %name, error = %e, "failed to undo bundle member");
+        }
+    }
+    if removed.is_empty() {
+        return;
+    }
+    let result = Manifest::load(manifest_path).and_then(|mut manifest| {
+        for name in &removed {
+            manifest.artifacts.remove(*name);
+        }
+        manifest.save(manifest_path)
+    });
+    if let Err(e) = result {
+        tracing::error!(domain, error = %e, "failed to undo bundle manifest entries");
+    }
+}
+
+/// Fail with [`IntegrityIssue::Tampered`] if `name` has a manifest entry
+/// whose checksum differs from `actual`.
+pub(crate) fn check_checksum(manifest: &Manifest, name: &str, actual: &str) -> Result<()> {
+    match manifest.artifacts.get(name) {
+        Some(entry) if entry.sha256 != actual => Err(IntegrityIssue::Tampered {
+            name: name.to_string(),
+            expected: entry.sha256.clone(),
+            actual: actual.to_string(),
+        }
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Open an artifact file as a stream that is checked against its
+/// manifest entry once fully read.
+pub(crate) fn open_verified(
+    path: &Path,
+    manifest: &Manifest,
+    name: &str,
+) -> Result<Box<dyn Read + Send>> {
+    let file = std::fs::File::open(path).with_context(|| format!("opening artifact `{}`", name))?;
+    Ok(Box::new(VerifiedReader {
+        file,
+        hasher: Sha256::new(),
+        name: name.to_string(),
+        expected: manifest.artifacts.get(name).map(|e| e.sha256.clone()),
+    }))
+}
+
+/// Reader that hashes an artifact as it streams and reports a checksum
+/// mismatch at end of stream.
+struct VerifiedReader {
+    file: std::fs::File,
+    hasher: Sha256,
+    name: String,
+    /// Recorded checksum; `None` once checked or if there is none.
+    expected: Option<String>,
+}
+
+impl Read for VerifiedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.file.read(buf)?;
+        if n > 0 {
+            self.hasher.update(&buf[..n]);
+        } else if let Some(expected) = self.expected.take() {
+            let actual = format!("{:x}", self.hasher.finalize_reset());
+            if actual != expected {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    IntegrityIssue::Tampered {
+                        name: self.name.clone(),
+                        expected,
+                        actual,
+                    },
+                ));
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Artifact content written to a hidden staging file and hashed, waiting
+/// to be moved into place. The file is removed if never committed.
+pub(crate) struct Staged {
+    path: PathBuf,
+    pub hash: String,
+    pub size_bytes: u64,
+}
+
+impl Staged {
+    /// Stream `reader` into a staging file in `dir`, hashing as it goes.
+    ///
+    /// Reading stops one byte past `max_bytes`, so an oversized stream
+    /// is never staged in full; its `size_bytes` then exceeds the limit
+    /// and the caller's limit check rejects it.
+    pub(crate) fn from_reader(
+        dir: &Path,
+        reader: &mut dyn Read,
+        max_bytes: Option<u64>,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let mut staged = Self::empty(dir);
+        let mut file = std::fs::File::create(&staged.path)?;
+        let mut reader = reader.take(max_bytes.map_or(u64::MAX, |max| max.saturating_add(1)));
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0; STREAM_BUF_BYTES];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            hasher.update(&buf[..n]);
+            file.write_all(&buf[..n])?;
+            staged.size_bytes += n as u64;
+        }
+        staged.hash = format!("{:x}", hasher.finalize());
+        Ok(staged)
+    }
+
+    /// Stage a copy of the file at `source`, reflinked where the
+    /// filesystem supports it and copied in-kernel otherwise.
+    ///
+    /// The copy is hashed rather than the source, so a source rewritten
+    /// mid-copy cannot yield a checksum that mismatches the content.
+    pub(crate) fn from_file(dir: &Path, source: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let mut staged = Self::empty(dir);
+        reflink_copy::reflink_or_copy(source, &staged.path)
+            .with_context(|| format!("copying artifact: {}", source.display()))?;
+
+        let mut file = std::fs::File::open(&staged.path)?;
+        let mut hasher = Sha256::new();
+        staged.size_bytes = std::io::copy(&mut file, &mut hasher)?;
+        staged.hash = format!("{:x}", hasher.finalize());
+        Ok(staged)
+    }
+
+    fn empty(dir: &Path) -> Self {
+        Self {
+            path: dir.join(format!(".incoming-{}", uuid::Uuid::new_v4())),
+            hash: String::new(),
+            size_bytes: 0,
+        }
+    }
+
+    /// Move the staged file to `dest`, replacing whatever is there.
+    /// `dest` must be on the same filesystem as the staging directory.
+    pub(crate) fn commit(self, dest: &Path) -> Result<()> {
+        std::fs::rename(&self.path, dest)?;
+        Ok(())
+    }
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        // Already gone once committed.
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Point `dest` at the read-only blob `src`, via a temp name and rename
+/// so readers never see it missing.
+///
+/// A hard link is used when both are on the same filesystem, then a
+/// reflink, then a plain copy.
+pub(crate) fn install_link(src: &Path, dest: &Path) -> Result<()> {
+    let tmp = temp_sibling(dest, "link")?;
+    let _ = std::fs::remove_file(&tmp);
+    if std::fs::hard_link(src, &tmp).is_err() {
+        reflink_copy::reflink_or_copy(src, &tmp)?;
+    }
+    std::fs::rename(&tmp, dest)?;
+    Ok(())
+}
+
+/// Replace `dest` with an independent copy of `src` (reflinked where
+/// supported), via a temp name and rename.
+pub(crate) fn install_copy(src: &Path, dest: &Path) -> Result<()> {
+    let tmp = temp_sibling(dest, "tmp")?;
+    let _ = std::fs::remove_file(&tmp);
+    reflink_copy::reflink_or_copy(src, &tmp)?;
+    std::fs::rename(&tmp, dest)?;
+    Ok(())
+}
+
+/// Clear write permission, so a blob shared by hard links cannot be
+/// edited in place through any of them.
+pub(crate) fn make_readonly(path: &Path) -> Result<()> {
+    let mut perms = std::fs::metadata(path)?.permissions();
+    perms.set_readonly(true);
+    std::fs::set_permissions(path, perms)?;
+    Ok(())
+}
+
+/// Check every manifest entry against the files under `artifacts_dir`.
+pub(crate) fn verify_tree(artifacts_dir: &Path, manifest: Manifest) -> Result<Vec<IntegrityIssue>> {
+    let mut issues = Vec::new();
+    for (name, entry) in manifest.artifacts {
+        let path = confine(artifacts_dir, &name)?;
+        match std::fs::read(&path) {
+            Ok(data) => {
+                let actual = sha256_hex(&data);
+                if actual != entry.sha256 {
+                    issues.push(IntegrityIssue::Tampered {
+                        name,
+                        expected: entry.sha256,
+                        actual,
+                    });
                 }
             }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                issues.push(IntegrityIssue::Missing { name });
+            }
+            Err(e) => return Err(e.into()),
         }
-        names.sort();
-        Ok(names)
+    }
+    Ok(issues)
+}
+
+/// Join a validated name onto `base`, rejecting names whose existing
+/// prefix resolves (through symlinks) outside `base`.
+pub(crate) fn confine(base: &Path, name: &str) -> Result<PathBuf> {
+    validate_name(name)?;
+    let path = base.join(name);
+
+    let Ok(canonical_base) = base.canonicalize() else {
+        // Nothing exists yet, so nothing can be a symlink.
+        return Ok(path);
+    };
+
+    let mut existing = path.as_path();
+    while existing.symlink_metadata().is_err() {
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => return Ok(path),
+        }
+    }
+
+    let resolved = existing.canonicalize().unwrap_or_default();
+    if !resolved.starts_with(&canonical_base) {
+        return Err(UnsafeArtifactName {
+            name: name.to_string(),
+            reason: "name resolves outside the artifacts directory",
+        }
+        .into());
+    }
+    Ok(path)
+}
+
+/// Recursively collect file names under `dir`, prefixed with `prefix`.
+pub(crate) fn collect_names(dir: &Path, prefix: &str, names: &mut Vec<String>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let Some(file_name) = entry.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        if is_temp_name(&file_name) {
+            continue;
+        }
+
+        let name = format!("{}{}", prefix, file_name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_names(&entry.path(), &format!("{}/", name), names)?;
+        } else if file_type.is_file() {
+            names.push(name);
+        }
+    }
+    Ok(())
+}
+
+/// Hex-encoded SHA-256 digest of `content`.
+pub fn sha256_hex(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Write a file via a sibling temp file and rename, so readers never
+/// observe a partially written artifact.
+pub(crate) fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let tmp = temp_sibling(path, "tmp")?;
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Suffixes [`temp_sibling`] is called with.
+const TEMP_SUFFIXES: &[&str] = &["tmp", "link"];
+
+/// Whether `file_name` is an in-flight temp file from [`temp_sibling`].
+pub(crate) fn is_temp_name(file_name: &str) -> bool {
+    file_name.strip_prefix('.').is_some_and(|rest| {
+        TEMP_SUFFIXES.iter().any(|suffix| {
+            rest.strip_suffix(suffix)
+                .is_some_and(|stem| stem.ends_with('.'))
+        })
+    })
+}
+
+/// Hidden sibling of `path` (`.<name>.<suffix>`) used as a rename source.
+fn temp_sibling(path: &Path, suffix: &str) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .and_then(|f| f.to_str())
+        .context("artifact path has no filename")?;
+    Ok(path.with_file_name(format!(".{}.{}", file_name, suffix)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch `artifacts/` directory, removed on drop.
+    struct Fixture {
+        dir: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "comm-node-artifact-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(dir.join("artifacts/api")).unwrap();
+            Self { dir }
+        }
+
+        fn artifacts(&self) -> PathBuf {
+            self.dir.join("artifacts")
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn stops_staging_a_stream_at_the_size_limit() {
+        let fixture = Fixture::new("stream-limit");
+        let roots = HashMap::from([("a".to_string(), fixture.dir.join("a"))]);
+        let limits = ArtifactLimits {
+            max_artifact_bytes: Some(1024),
+            quota_bytes: None,
+        };
+        let store = FsArtifactStore {
+            limits: HashMap::from([("a".to_string(), limits)]),
+            ..FsArtifactStore::new(roots)
+        };
+        let provenance = Provenance::new("a", "t1");
+
+        // An endless stream must be cut off rather than staged in full.
+        let err = store
+            .store_reader("a", "big.bin", &mut std::io::repeat(0), &provenance)
+            .unwrap_err();
+        let exceeded = err.downcast::<QuotaExceeded>().unwrap();
+        assert_eq!(exceeded.limit, QuotaLimit::ArtifactSize);
+        assert_eq!(exceeded.requested_bytes, 1025);
+        assert!(store.versions("a", "big.bin").unwrap().is_empty());
+
+        store
+            .store_reader(
+                "a",
+                "ok.bin",
+                &mut std::io::repeat(0).take(1024),
+                &provenance,
+            )
+            .unwrap();
+    }
+
+    fn rejection(name: &str) -> &'static str {
+        validate_name(name)
+            .unwrap_err()
+            .downcast::<UnsafeArtifactName>()
+            .unwrap()
+            .reason
+    }
+
+    #[test]
+    fn accepts_nested_relative_names() {
+        for name in ["spec.md", "api/users.yaml", "a..b/c", "..hidden"] {
+            validate_name(name).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_names_that_leave_the_artifacts_tree() {
+        assert_eq!(rejection("../x"), "name contains `..`");
+        assert_eq!(rejection("api/../../x"), "name contains `..`");
+        assert_eq!(rejection("api/.."), "name contains `..`");
+        assert_eq!(rejection("/etc/passwd"), "name is absolute");
+        assert_eq!(rejection("./x"), "name contains `.`");
+        assert_eq!(rejection("api//x"), "name has an empty path segment");
+        assert_eq!(rejection("..\\x"), "name contains a backslash");
+        assert_eq!(rejection(""), "name is empty");
+    }
+
+    #[test]
+    fn confine_rejects_unsafe_names() {
+        let fixture = Fixture::new("confine-names");
+        for name in ["../x", "/etc/passwd", "api/../../x"] {
+            let err = confine(&fixture.artifacts(), name).unwrap_err();
+            assert!(
+                err.downcast_ref::<UnsafeArtifactName>().is_some(),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(
+            confine(&fixture.artifacts(), "api/new.yaml").unwrap(),
+            fixture.artifacts().join("api/new.yaml")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn confine_rejects_symlinks_that_point_outside() {
+        let fixture = Fixture::new("confine-symlink");
+        let outside = fixture.dir.join("outside");
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("secret"), "hunter2").unwrap();
+        std::os::unix::fs::symlink(&outside, fixture.artifacts().join("leak")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret"), fixture.artifacts().join("file"))
+            .unwrap();
+        std::os::unix::fs::symlink("api", fixture.artifacts().join("inside")).unwrap();
+
+        for name in ["leak", "leak/secret", "leak/missing/new", "file"] {
+            let err = confine(&fixture.artifacts(), name).unwrap_err();
+            let violation = err.downcast::<UnsafeArtifactName>().unwrap();
+            assert_eq!(
+                violation.reason, "name resolves outside the artifacts directory",
+                "{}",
+                name
+            );
+        }
+        confine(&fixture.artifacts(), "inside/users.yaml").unwrap();
     }
 }
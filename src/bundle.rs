@@ -0,0 +1,210 @@
+//! Artifact bundles.
+//!
+//! A bundle is a set of artifacts routed together as one unit: a whole
+//! directory under `artifacts/` (e.g. a generated client with its types
+//! and schema) or every artifact matching a glob (`proto/*.proto`).
+//! Members are stored individually, each with its own version history,
+//! and `.orchestrator/bundles.json` records which member versions made
+//! up each version of the bundle.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::artifact::{write_atomic, ArtifactVersion};
+use crate::manifest::Provenance;
+use crate::subscription;
+
+/// One stored version of a bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleVersion {
+    /// Sequential version number, starting at 1.
+    pub version: u32,
+    /// Hex-encoded SHA-256 over the sorted member names and hashes.
+    pub hash: String,
+    /// Member artifact name -> the member version in this bundle version.
+    pub members: BTreeMap<String, u32>,
+    /// Total size of all members.
+    pub size_bytes: u64,
+    pub stored_at: DateTime<Utc>,
+    /// Domain that produced this bundle.
+    pub producer: String,
+    /// Task this bundle was routed for.
+    #[serde(default)]
+    pub task: String,
+}
+
+/// Bundle histories for a domain, keyed by bundle name, oldest first.
+pub type BundleHistory = BTreeMap<String, Vec<BundleVersion>>;
+
+/// Whether an artifact reference is a glob pattern rather than a name.
+pub fn is_pattern(reference: &str) -> bool {
+    reference.contains(['*', '?', '['])
+}
+
+/// Expand a bundle reference against the artifacts a domain holds.
+///
+/// A glob selects every matching artifact; a plain name selects every
+/// artifact under that directory. Returns `None` if `reference` names a
+/// single artifact, and fails if a bundle reference matches nothing.
+pub fn expand(reference: &str, available: &[String]) -> Result<Option<Vec<String>>> {
+    let members: Vec<String> = if is_pattern(reference) {
+        let pattern = glob::Pattern::new(reference)
+            .with_context(|| format!("invalid artifact pattern `{}`", reference))?;
+        available
+            .iter()
+            .filter(|name| pattern.matches_with(name, subscription::PATTERN_OPTIONS))
+            .cloned()
+            .collect()
+    } else {
+        if available.iter().any(|name| name == reference) {
+            return Ok(None);
+        }
+        let prefix = format!("{}/", reference);
+        available
+            .iter()
+            .filter(|name| name.starts_with(&prefix))
+            .cloned()
+            .collect()
+    };
+
+    if members.is_empty() {
+        bail!("artifact `{}` matches no artifacts", reference);
+    }
+    Ok(Some(members))
+}
+
+/// Load a domain's bundle histories, treating a missing file as empty.
+pub fn load(path: &Path) -> Result<BundleHistory> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json)
+            .with_context(|| format!("parsing bundle history: {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BundleHistory::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Record the member versions just stored as the next version of
+/// `bundle`, unless they match its current version.
+pub(crate) fn record(
+    path: &Path,
+    bundle: &str,
+    members: &[(String, ArtifactVersion)],
+    provenance: &Provenance,
+) -> Result<BundleVersion> {
+    let mut sorted: Vec<_> = members.iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut hasher = Sha256::new();
+    for (name, version) in &sorted {
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(version.hash.as_bytes());
+        hasher.update([b'\n']);
+    }
+    let hash = format!("{:x}", hasher.finalize());
+
+    let mut histories = load(path)?;
+    let history = histories.entry(bundle.to_string()).or_default();
+    if let Some(latest) = history.last() {
+        if latest.hash == hash {
+            return Ok(latest.clone());
+        }
+    }
+
+    let version = BundleVersion {
+        version: history.last().map_or(1, |v| v.version + 1),
+        hash,
+        members: sorted
+            .iter()
+            .map(|(name, v)| (name.clone(), v.version))
+            .collect(),
+        size_bytes: sorted.iter().map(|(_, v)| v.size_bytes).sum(),
+        stored_at: Utc::now(),
+        producer: provenance.producer.clone(),
+        task: provenance.task.clone(),
+    };
+    history.push(version.clone());
+    write_atomic(path, serde_json::to_string_pretty(&histories)?.as_bytes())?;
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn available() -> Vec<String> {
+        [
+            "openapi.yaml",
+            "proto/users.proto",
+            "proto/orders.proto",
+            "proto/v2/users.proto",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+    }
+
+    fn member(name: &str, content: &str) -> (String, ArtifactVersion) {
+        let hash = crate::artifact::sha256_hex(content.as_bytes());
+        let provenance = Provenance::new("a", "t1");
+        let version = ArtifactVersion::next(&[], hash, content.len() as u64, &provenance, None);
+        (name.to_string(), version)
+    }
+
+    #[test]
+    fn expands_globs_and_directories_within_the_artifacts() {
+        let members = expand("proto/*.proto", &available()).unwrap().unwrap();
+        assert_eq!(members, ["proto/users.proto", "proto/orders.proto"]);
+
+        let members = expand("proto/**/*.proto", &available()).unwrap().unwrap();
+        assert_eq!(members.len(), 3);
+
+        let members = expand("proto", &available()).unwrap().unwrap();
+        assert_eq!(members.len(), 3);
+
+        assert_eq!(expand("openapi.yaml", &available()).unwrap(), None);
+    }
+
+    #[test]
+    fn an_escaping_pattern_selects_nothing() {
+        assert!(crate::artifact::validate_name("../*").is_err());
+        let err = expand("../*", &available()).unwrap_err();
+        assert!(err.to_string().contains("matches no artifacts"), "{}", err);
+    }
+
+    #[test]
+    fn an_empty_expansion_is_an_error() {
+        assert!(expand("proto/*.json", &available()).is_err());
+        assert!(expand("schemas", &available()).is_err());
+    }
+
+    #[test]
+    fn bundle_hash_ignores_member_order() {
+        let dir = std::env::temp_dir().join(format!("comm-node-bundle-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bundles.json");
+        let provenance = Provenance::new("a", "t1");
+        let users = member("proto/users.proto", "message User {}");
+        let orders = member("proto/orders.proto", "message Order {}");
+
+        let first = record(
+            &path,
+            "proto",
+            &[users.clone(), orders.clone()],
+            &provenance,
+        )
+        .unwrap();
+        let second = record(&path, "proto", &[orders, users], &provenance).unwrap();
+        assert_eq!(first.hash, second.hash);
+        assert_eq!(second.version, 1);
+        assert_eq!(load(&path).unwrap()["proto"].len(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,515 @@
+//! Content-addressed artifact store.
+//!
+//! Artifact bytes live once in `<state_dir>/blobs/<hash[..2]>/<hash>`,
+//! however many domains they are routed to. Each domain keeps a
+//! name -> version history in `<state_dir>/refs/<domain>.json`, and its
+//! `.orchestrator/artifacts/<name>` is a hard link to the current blob
+//! (a reflink or copy when the domain lives on another filesystem).
+//! Blobs no history refers to are removed by [`CasArtifactStore::gc`].
+//! Writers and `gc` serialize on `<state_dir>/refs/.lock`, so a `gc`
+//! run from the CLI is safe against a running orchestrator.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context, Result};
+
+use crate::artifact::{
+    check_checksum, collect_names, confine, current_versions, install_link, make_readonly,
+    open_verified, read_verified, sha256_hex, stage_bundle, undo_bundle, update_manifest,
+    verify_tree, write_atomic, ArtifactLimits, ArtifactStore, ArtifactVersion, Staged,
+};
+use crate::bundle::{self, BundleVersion};
+use crate::config::ProjectConfig;
+use crate::manifest::{IntegrityIssue, Manifest, Provenance};
+
+/// Per-domain artifact histories, keyed by artifact name.
+type Refs = BTreeMap<String, Vec<ArtifactVersion>>;
+
+/// Outcome of a garbage collection pass.
+#[derive(Debug, Clone, Default)]
+pub struct GcReport {
+    /// Blobs still referenced by some domain history.
+    pub live: usize,
+    /// Unreferenced blobs deleted.
+    pub removed: usize,
+    pub freed_bytes: u64,
+    /// Unreferenced blobs kept because they are younger than the grace period.
+    pub skipped_recent: usize,
+}
+
+/// Content-addressed, deduplicating artifact store.
+pub struct CasArtifactStore {
+    /// Map of domain name -> domain root path.
+    roots: HashMap<String, PathBuf>,
+    /// Size limits per domain; domains without an entry are unlimited.
+    limits: HashMap<String, ArtifactLimits>,
+    state_dir: PathBuf,
+    /// Serializes read-modify-write of refs files against `gc` within
+    /// this process; [`CasArtifactStore::lock_refs`] adds a file lock
+    /// for other processes.
+    refs_lock: Mutex<()>,
+}
+
+/// Held while refs are read, modified and written, or while `gc` runs.
+struct RefsGuard<'a> {
+    _thread: MutexGuard<'a, ()>,
+    /// Open lock file; closing it releases the `flock`.
+    _file: std::fs::File,
+}
+
+impl CasArtifactStore {
+    pub fn new(roots: HashMap<String, PathBuf>, state_dir: PathBuf) -> Self {
+        Self {
+            roots,
+            limits: HashMap::new(),
+            state_dir,
+            refs_lock: Mutex::new(()),
+        }
+    }
+
+    /// Build a store over every domain in a project config.
+    pub fn from_config(config: &ProjectConfig, state_dir: PathBuf) -> Self {
+        let roots = config
+            .domains
+            .iter()
+            .map(|(id, dc)| (id.as_str().to_owned(), dc.path.clone()))
+            .collect();
+        Self {
+            limits: ArtifactLimits::for_project(config),
+            ..Self::new(roots, state_dir)
+        }
+    }
+
+    fn root(&self, domain: &str) -> Result<&PathBuf> {
+        self.roots
+            .get(domain)
+            .ok_or_else(|| anyhow::anyhow!("unknown domain: {}", domain))
+    }
+
+    fn artifacts_dir(&self, domain: &str) -> Result<PathBuf> {
+        Ok(self.root(domain)?.join(".orchestrator/artifacts"))
+    }
+
+    fn manifest_path(&self, domain: &str) -> Result<PathBuf> {
+        Ok(self.root(domain)?.join(".orchestrator/manifest.json"))
+    }
+
+    fn bundles_path(&self, domain: &str) -> Result<PathBuf> {
+        Ok(self.root(domain)?.join(".orchestrator/bundles.json"))
+    }
+
+    fn blobs_dir(&self) -> PathBuf {
+        self.state_dir.join("blobs")
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.blobs_dir().join(&hash[..2]).join(hash)
+    }
+
+    fn refs_path(&self, domain: &str) -> Result<PathBuf> {
+        self.root(domain)?;
+        Ok(self.state_dir.join("refs").join(format!("{}.json", domain)))
+    }
+
+    /// Take `refs_lock` and an exclusive `flock` on `refs/.lock`,
+    /// blocking until another process (e.g. `comm-node gc`) releases it.
+    fn lock_refs(&self) -> Result<RefsGuard<'_>> {
+        let thread = self.refs_lock.lock().expect("refs lock poisoned");
+        let dir = self.state_dir.join("refs");
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(".lock");
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("opening refs lock: {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            // SAFETY: `file` is open for the duration of the call.
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("locking {}", path.display()));
+            }
+        }
+        Ok(RefsGuard {
+            _thread: thread,
+            _file: file,
+        })
+    }
+
+    fn read_refs(&self, domain: &str) -> Result<Refs> {
+        let path = self.refs_path(domain)?;
+        match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .with_context(|| format!("parsing artifact refs: {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Refs::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_refs(&self, domain: &str, refs: &Refs) -> Result<()> {
+        let path = self.refs_path(domain)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(&path, serde_json::to_string_pretty(refs)?.as_bytes())
+    }
+
+    /// Move staged content into the blob store unless a blob with the
+    /// same hash already exists.
+    ///
+    /// Blobs are made read-only: domains hard-link to them, so an agent
+    /// editing an artifact in place must not corrupt every other copy.
+    fn put_blob(&self, staged: Staged) -> Result<PathBuf> {
+        let path = self.blob_path(&staged.hash);
+        if path.exists() {
+            return Ok(path);
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        staged.commit(&path)?;
+        make_readonly(&path)?;
+        Ok(path)
+    }
+
+    /// Check staged content against a domain's limits, then add it as
+    /// the next version of `name`.
+    fn ingest(
+        &self,
+        domain: &str,
+        name: &str,
+        staged: Staged,
+        provenance: &Provenance,
+    ) -> Result<ArtifactVersion> {
+        self.check_limits(domain, &[(name, staged.size_bytes)])?;
+        let (hash, size_bytes) = (staged.hash.clone(), staged.size_bytes);
+        let _guard = self.lock_refs()?;
+        self.put_blob(staged)?;
+        self.record(domain, name, hash, size_bytes, provenance, None)
+    }
+
+    /// Append a version to a domain's history and make it current.
+    ///
+    /// Callers hold [`CasArtifactStore::lock_refs`] from before the blob is written until
+    /// this returns, so `gc` never sees a blob without its ref.
+    fn record(
+        &self,
+        domain: &str,
+        name: &str,
+        hash: String,
+        size_bytes: u64,
+        provenance: &Provenance,
+        rolled_back_from: Option<u32>,
+    ) -> Result<ArtifactVersion> {
+        let dest = confine(&self.artifacts_dir(domain)?, name)?;
+
+        let mut refs = self.read_refs(domain)?;
+        let history = refs.entry(name.to_string()).or_default();
+
+        if rolled_back_from.is_none() {
+            if let Some(latest) = history.last() {
+                if latest.hash == hash {
+                    // Same content: repair the current copy and manifest
+                    // entry in case either was removed or edited.
+                    self.install_current(domain, name, &dest, latest)?;
+                    return Ok(latest.clone());
+                }
+            }
+        }
+
+        let version =
+            ArtifactVersion::next(history, hash, size_bytes, provenance, rolled_back_from);
+        history.push(version.clone());
+        self.write_refs(domain, &refs)?;
+        self.install_current(domain, name, &dest, &version)?;
+        Ok(version)
+    }
+
+    /// Link `version`'s blob to `dest` and record it in the manifest.
+    fn install_current(
+        &self,
+        domain: &str,
+        name: &str,
+        dest: &Path,
+        version: &ArtifactVersion,
+    ) -> Result<()> {
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        install_link(&self.blob_path(&version.hash), dest)?;
+        update_manifest(&self.manifest_path(domain)?, name, version)
+    }
+
+    /// Number of history entries referring to each blob, across all domains.
+    pub fn reference_counts(&self) -> Result<HashMap<String, usize>> {
+        let mut counts = HashMap::new();
+        for domain in self.roots.keys() {
+            for history in self.read_refs(domain)?.values() {
+                for version in history {
+                    *counts.entry(version.hash.clone()).or_default() += 1;
+                }
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Delete blobs that no domain history refers to.
+    ///
+    /// Only orphaned blobs are freed: every version in a domain's history
+    /// keeps its blob, and there is no history retention, so a blob is
+    /// orphaned only once no history mentions it (e.g. its domain left the
+    /// config). Blobs modified within `grace` are kept as well.
+    ///
+    /// Holds the refs lock throughout, so stores in this or another
+    /// process wait for it rather than racing it.
+    pub fn gc(&self, grace: Duration) -> Result<GcReport> {
+        let _guard = self.lock_refs()?;
+        let counts = self.reference_counts()?;
+        let mut report = GcReport::default();
+
+        let blobs_dir = self.blobs_dir();
+        if !blobs_dir.exists() {
+            return Ok(report);
+        }
+
+        let now = SystemTime::now();
+        for shard in std::fs::read_dir(&blobs_dir)? {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() {
+                continue;
+            }
+            for blob in std::fs::read_dir(shard.path())? {
+                let blob = blob?;
+                let Some(hash) = blob.file_name().to_str().map(str::to_owned) else {
+                    continue;
+                };
+                if hash.starts_with('.') {
+                    continue;
+                }
+                if counts.contains_key(&hash) {
+                    report.live += 1;
+                    continue;
+                }
+
+                let metadata = blob.metadata()?;
+                let age = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|m| now.duration_since(m).ok())
+                    .unwrap_or_default();
+                if age < grace {
+                    report.skipped_recent += 1;
+                    continue;
+                }
+
+                std::fs::remove_file(blob.path())?;
+                report.removed += 1;
+                report.freed_bytes += metadata.len();
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+impl ArtifactStore for CasArtifactStore {
+    fn store_reader(
+        &self,
+        domain: &str,
+        name: &str,
+        reader: &mut dyn Read,
+        provenance: &Provenance,
+    ) -> Result<ArtifactVersion> {
+        self.root(domain)?;
+        let max_bytes = self.limits.get(domain).and_then(|l| l.max_artifact_bytes);
+        let staged = Staged::from_reader(&self.blobs_dir(), reader, max_bytes)?;
+        self.ingest(domain, name, staged, provenance)
+    }
+
+    fn copy(
+        &self,
+        from: &str,
+        to: &str,
+        name: &str,
+        provenance: &Provenance,
+    ) -> Result<ArtifactVersion> {
+        self.root(to)?;
+        let source = confine(&self.artifacts_dir(from)?, name)?;
+        let staged = Staged::from_file(&self.blobs_dir(), &source)?;
+        check_checksum(&self.manifest(from)?, name, &staged.hash)?;
+        self.ingest(to, name, staged, provenance)
+    }
+
+    fn copy_bundle(
+        &self,
+        from: &str,
+        to: &str,
+        bundle: &str,
+        names: &[String],
+        provenance: &Provenance,
+    ) -> Result<BundleVersion> {
+        self.root(to)?;
+        let blobs_dir = self.blobs_dir();
+        let staged = stage_bundle(self, from, to, &self.artifacts_dir(from)?, names, |_| {
+            Ok(blobs_dir.clone())
+        })?;
+
+        let (artifacts_dir, manifest_path) = (self.artifacts_dir(to)?, self.manifest_path(to)?);
+        let previous = current_versions(self, to, names)?;
+        let mut stored = Vec::with_capacity(staged.len());
+        let result = {
+            let _guard = self.lock_refs()?;
+            (|| {
+                for (name, staged) in staged {
+                    let (hash, size_bytes) = (staged.hash.clone(), staged.size_bytes);
+                    self.put_blob(staged)?;
+                    let version = self.record(to, &name, hash, size_bytes, provenance, None)?;
+                    stored.push((name, version));
+                }
+                bundle::record(&self.bundles_path(to)?, bundle, &stored, provenance)
+            })()
+        };
+        // Undone without the refs lock, which `rollback` takes.
+        result.inspect_err(|_| {
+            undo_bundle(self, to, &artifacts_dir, &manifest_path, &stored, &previous);
+        })
+    }
+
+    fn bundle_versions(&self, domain: &str, bundle: &str) -> Result<Vec<BundleVersion>> {
+        Ok(bundle::load(&self.bundles_path(domain)?)?
+            .remove(bundle)
+            .unwrap_or_default())
+    }
+
+    fn retrieve(&self, domain: &str, name: &str) -> Result<Vec<u8>> {
+        read_verified(
+            &confine(&self.artifacts_dir(domain)?, name)?,
+            &self.manifest(domain)?,
+            name,
+        )
+    }
+
+    fn open(&self, domain: &str, name: &str) -> Result<Box<dyn Read + Send>> {
+        open_verified(
+            &confine(&self.artifacts_dir(domain)?, name)?,
+            &self.manifest(domain)?,
+            name,
+        )
+    }
+
+    /// Lists nested artifacts by their `/`-separated relative names.
+    fn list(&self, domain: &str) -> Result<Vec<String>> {
+        let dir = self.artifacts_dir(domain)?;
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut names = Vec::new();
+        collect_names(&dir, "", &mut names)?;
+        names.sort();
+        Ok(names)
+    }
+
+    fn versions(&self, domain: &str, name: &str) -> Result<Vec<ArtifactVersion>> {
+        Ok(self.read_refs(domain)?.remove(name).unwrap_or_default())
+    }
+
+    fn retrieve_version(&self, domain: &str, name: &str, version: u32) -> Result<Vec<u8>> {
+        let history = self.versions(domain, name)?;
+        let Some(entry) = history.iter().find(|v| v.version == version) else {
+            bail!(
+                "artifact `{}` in domain `{}` has no version {}",
+                name,
+                domain,
+                version
+            );
+        };
+        let data = std::fs::read(self.blob_path(&entry.hash))
+            .with_context(|| format!("reading version {} of artifact `{}`", version, name))?;
+        if sha256_hex(&data) != entry.hash {
+            bail!(
+                "blob for version {} of artifact `{}` is corrupt",
+                version,
+                name
+            );
+        }
+        Ok(data)
+    }
+
+    fn rollback(&self, domain: &str, name: &str, version: u32) -> Result<ArtifactVersion> {
+        let Some(target) = self
+            .versions(domain, name)?
+            .into_iter()
+            .find(|v| v.version == version)
+        else {
+            bail!(
+                "artifact `{}` in domain `{}` has no version {}",
+                name,
+                domain,
+                version
+            );
+        };
+        if !self.blob_path(&target.hash).exists() {
+            bail!(
+                "blob for version {} of artifact `{}` is missing",
+                version,
+                name
+            );
+        }
+        let provenance = Provenance::new(target.producer, target.task);
+        let _guard = self.lock_refs()?;
+        self.record(
+            domain,
+            name,
+            target.hash,
+            target.size_bytes,
+            &provenance,
+            Some(version),
+        )
+    }
+
+    fn manifest(&self, domain: &str) -> Result<Manifest> {
+        Manifest::load(&self.manifest_path(domain)?)
+    }
+
+    fn verify(&self, domain: &str) -> Result<Vec<IntegrityIssue>> {
+        verify_tree(&self.artifacts_dir(domain)?, self.manifest(domain)?)
+    }
+
+    fn check_limits(&self, domain: &str, incoming: &[(&str, u64)]) -> Result<()> {
+        let limits = self.limits.get(domain).copied().unwrap_or_default();
+        limits.check(domain, &self.artifacts_dir(domain)?, incoming)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn gc_waits_for_a_refs_lock_held_by_another_store() {
+        let dir = std::env::temp_dir().join(format!("comm-node-cas-gc-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        // Two stores over one state dir stand in for the orchestrator and
+        // a `comm-node gc` process; only the file lock is shared.
+        let orchestrator = CasArtifactStore::new(HashMap::new(), dir.clone());
+        let cli = CasArtifactStore::new(HashMap::new(), dir.clone());
+
+        let guard = orchestrator.lock_refs().unwrap();
+        let gc = std::thread::spawn(move || cli.gc(Duration::ZERO).unwrap());
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!gc.is_finished());
+
+        drop(guard);
+        gc.join().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -16,6 +16,128 @@ use crate::types::DomainId;
 pub struct ProjectConfig {
     /// Domains managed by this comm-node instance.
     pub domains: HashMap<DomainId, DomainConfig>,
+
+    /// Artifact storage settings.
+    #[serde(default)]
+    pub artifacts: ArtifactsConfig,
+
+    /// Event log settings.
+    #[serde(default)]
+    pub events: EventsConfig,
+
+    /// Lock persistence settings.
+    #[serde(default)]
+    pub locks: LocksConfig,
+
+    /// Agent monitoring settings.
+    #[serde(default)]
+    pub agents: AgentsConfig,
+
+    /// Task dispatch settings.
+    #[serde(default)]
+    pub tasks: TasksConfig,
+}
+
+/// Where the event log or lock snapshots are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StateBackend {
+    /// Plain files in the state dir.
+    #[default]
+    File,
+    /// The SQLite database `comm-node.db` in the state dir.
+    Sqlite,
+}
+
+/// Lock persistence settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LocksConfig {
+    /// Where lock snapshots are stored.
+    #[serde(default)]
+    pub backend: StateBackend,
+}
+
+/// Agent monitoring settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AgentsConfig {
+    /// Seconds an agent's `status.json` heartbeat may age before the
+    /// agent is considered stale (never, if unset).
+    #[serde(default)]
+    pub heartbeat_timeout_secs: Option<u64>,
+
+    /// Seconds to wait before restarting an agent process the first time
+    /// (default 1); the delay doubles with each consecutive restart.
+    #[serde(default)]
+    pub restart_backoff_secs: Option<u64>,
+
+    /// Longest delay between restarts, in seconds (default 60). A process
+    /// that stays up this long resets the delay.
+    #[serde(default)]
+    pub restart_backoff_max_secs: Option<u64>,
+}
+
+/// Task dispatch settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TasksConfig {
+    /// Assign ready tasks from the tracker to idle agents.
+    #[serde(default)]
+    pub dispatch: bool,
+
+    /// The beads CLI to query for ready tasks (default `bd`).
+    #[serde(default)]
+    pub command: Option<String>,
+}
+
+/// Event log settings. Indexing, rotation and retention apply to the
+/// file backend.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventsConfig {
+    /// Which `EventLog` implementation to use.
+    #[serde(default)]
+    pub backend: StateBackend,
+
+    /// Maintain a sidecar index so queries skip non-matching events.
+    #[serde(default)]
+    pub index: bool,
+
+    /// Archive the active log once it reaches this many bytes.
+    #[serde(default)]
+    pub rotate_bytes: Option<u64>,
+
+    /// Archive the active log once its oldest event is this many seconds old.
+    #[serde(default)]
+    pub rotate_after_secs: Option<u64>,
+
+    /// Delete archived segments whose newest event is older than this many
+    /// seconds (kept forever if unset).
+    #[serde(default)]
+    pub retention_secs: Option<u64>,
+}
+
+/// Artifact storage settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArtifactsConfig {
+    /// Which `ArtifactStore` implementation to use.
+    #[serde(default)]
+    pub backend: ArtifactBackend,
+
+    /// Seconds a newer artifact version may wait unacknowledged before
+    /// the consumer is notified that it is stale (never, if unset).
+    #[serde(default)]
+    pub stale_after_secs: Option<u64>,
+}
+
+/// Available artifact storage backends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtifactBackend {
+    /// Plain files per domain, with per-domain version history.
+    #[default]
+    Fs,
+    /// Deduplicated blobs in the state dir, hard-linked into domains.
+    Cas,
+    /// One commit per artifact version in a local git repository.
+    Git,
 }
 
 /// Configuration for a single domain.
@@ -31,6 +153,103 @@ pub struct DomainConfig {
     /// Human-readable description of this domain's responsibility.
     #[serde(default)]
     pub description: String,
+
+    /// Rendering used for messages delivered to this domain's inbox.
+    #[serde(default)]
+    pub inbox_format: InboxFormat,
+
+    /// Peer artifacts this domain depends on, pushed to it on every update.
+    #[serde(default)]
+    pub subscriptions: Vec<SubscriptionConfig>,
+
+    /// Largest single artifact this domain accepts, in bytes (unlimited if unset).
+    #[serde(default)]
+    pub max_artifact_bytes: Option<u64>,
+
+    /// Total size of the artifacts this domain may hold, in bytes (unlimited if unset).
+    #[serde(default)]
+    pub artifact_quota_bytes: Option<u64>,
+
+    /// What happens to this domain's locks when its agent goes stale.
+    #[serde(default)]
+    pub lock_policy: LockPolicy,
+
+    /// Program that runs this domain's agent. When set, `comm-node start`
+    /// launches it and keeps it running.
+    #[serde(default)]
+    pub command: Option<String>,
+
+    /// Arguments passed to `command`.
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    /// Environment variables set for `command`.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+
+    /// Directory `command` runs in, relative to `path` (default: `path`).
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+
+    /// When `command` is restarted after it exits.
+    #[serde(default)]
+    pub restart: RestartPolicy,
+}
+
+/// When a supervised agent process is restarted after it exits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Leave it stopped.
+    Never,
+    /// Restart it if it exited unsuccessfully.
+    #[default]
+    OnFailure,
+    /// Restart it whenever it exits.
+    Always,
+}
+
+/// Handling of a stale agent's file locks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockPolicy {
+    /// Release the locks, handing each to the domain waiting longest for it.
+    #[default]
+    Release,
+    /// Keep the locks until the agent resumes.
+    Hold,
+    /// Keep the locks and tell the waiting domains the holder is stale.
+    Escalate,
+}
+
+/// A dependency on another domain's artifact.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionConfig {
+    /// Producing domain.
+    pub domain: DomainId,
+    /// Artifact name or glob pattern within the producer's `artifacts/`.
+    pub artifact: String,
+}
+
+/// How messages are rendered into a domain's inbox.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InboxFormat {
+    /// Markdown with YAML frontmatter (`.md`).
+    #[default]
+    Markdown,
+    /// A single JSON object with the body inline (`.json`).
+    Json,
+}
+
+impl InboxFormat {
+    /// File extension used for messages in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Json => "json",
+        }
+    }
 }
 
 impl ProjectConfig {
@@ -48,6 +267,45 @@ impl ProjectConfig {
             if !dc.path.exists() {
                 tracing::warn!(domain = %id, path = %dc.path.display(), "domain path does not exist");
             }
+
+            if dc.command.as_deref().is_some_and(|c| c.trim().is_empty()) {
+                bail!("domain `{}` has an empty agent command", id);
+            }
+            if dc.command.is_none() && (!dc.args.is_empty() || !dc.env.is_empty()) {
+                tracing::warn!(domain = %id, "agent args or env set without a command");
+            }
+
+            if let (Some(max), Some(quota)) = (dc.max_artifact_bytes, dc.artifact_quota_bytes) {
+                if max > quota {
+                    tracing::warn!(
+                        domain = %id,
+                        max_artifact_bytes = max,
+                        artifact_quota_bytes = quota,
+                        "max artifact size exceeds the domain quota"
+                    );
+                }
+            }
+
+            for sub in &dc.subscriptions {
+                if &sub.domain == id {
+                    bail!("domain `{}` cannot subscribe to its own artifacts", id);
+                }
+                if !self.domains.contains_key(&sub.domain) {
+                    bail!(
+                        "domain `{}` subscribes to unknown domain `{}`",
+                        id,
+                        sub.domain
+                    );
+                }
+                if let Err(e) = glob::Pattern::new(&sub.artifact) {
+                    bail!(
+                        "domain `{}` has invalid subscription pattern `{}`: {}",
+                        id,
+                        sub.artifact,
+                        e
+                    );
+                }
+            }
         }
 
         // Check for overlapping scope patterns across domains.
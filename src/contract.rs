@@ -0,0 +1,455 @@
+//! Structural diffing of contract artifacts.
+//!
+//! When a structured artifact (JSON, YAML, OpenAPI, JSON Schema) is
+//! republished, the router compares it against the version the target
+//! already holds and classifies each change as breaking or
+//! non-breaking, so consumers learn *what* changed rather than just
+//! that the bytes differ.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Maximum number of individual changes listed in a summary.
+const SUMMARY_LIMIT: usize = 20;
+
+/// Detected structured format of an artifact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractKind {
+    Json,
+    Yaml,
+    OpenApi,
+    JsonSchema,
+}
+
+impl std::fmt::Display for ContractKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Json => "JSON",
+            Self::Yaml => "YAML",
+            Self::OpenApi => "OpenAPI",
+            Self::JsonSchema => "JSON Schema",
+        };
+        f.write_str(name)
+    }
+}
+
+/// How a node in the document changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// One structural change, located by JSON pointer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub path: String,
+    pub kind: ChangeKind,
+    pub breaking: bool,
+    /// Short rendering of the old/new value.
+    pub detail: String,
+}
+
+/// Structural diff between two versions of a contract.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractDiff {
+    pub kind: ContractKind,
+    pub changes: Vec<Change>,
+}
+
+impl ContractDiff {
+    pub fn is_breaking(&self) -> bool {
+        self.changes.iter().any(|c| c.breaking)
+    }
+
+    pub fn breaking_count(&self) -> usize {
+        self.changes.iter().filter(|c| c.breaking).count()
+    }
+
+    /// Human-readable classification plus the first few changes.
+    pub fn summary(&self) -> String {
+        let breaking = self.breaking_count();
+        let mut out = format!(
+            "{} contract change: {} breaking, {} non-breaking",
+            self.kind,
+            breaking,
+            self.changes.len() - breaking
+        );
+        for change in self.changes.iter().take(SUMMARY_LIMIT) {
+            let label = if change.breaking {
+                "breaking"
+            } else {
+                "non-breaking"
+            };
+            let verb = match change.kind {
+                ChangeKind::Added => "added",
+                ChangeKind::Removed => "removed",
+                ChangeKind::Modified => "modified",
+            };
+            let _ = write!(
+                out,
+                "\n- [{}] {} `{}` {}",
+                label, verb, change.path, change.detail
+            );
+        }
+        if self.changes.len() > SUMMARY_LIMIT {
+            let _ = write!(out, "\n- ... {} more", self.changes.len() - SUMMARY_LIMIT);
+        }
+        out
+    }
+}
+
+/// Whether an artifact's extension marks it as a possible contract, so
+/// callers can skip reading content that [`detect`] would reject.
+pub fn is_candidate(name: &str) -> bool {
+    matches!(extension(name).as_deref(), Some("json" | "yaml" | "yml"))
+}
+
+fn extension(name: &str) -> Option<String> {
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+}
+
+/// Parse an artifact as a structured contract, judged by its extension
+/// and, for OpenAPI and JSON Schema, by its top-level keys.
+pub fn detect(name: &str, content: &[u8]) -> Option<(ContractKind, Value)> {
+    let (base, value): (ContractKind, Value) = match extension(name).as_deref() {
+        Some("json") => (ContractKind::Json, serde_json::from_slice(content).ok()?),
+        Some("yaml" | "yml") => (ContractKind::Yaml, serde_yaml::from_slice(content).ok()?),
+        _ => return None,
+    };
+
+    let kind = match value.as_object() {
+        Some(obj) if obj.contains_key("openapi") || obj.contains_key("swagger") => {
+            ContractKind::OpenApi
+        }
+        Some(obj)
+            if obj.contains_key("$schema")
+                || (obj.contains_key("type") && obj.contains_key("properties")) =>
+        {
+            ContractKind::JsonSchema
+        }
+        _ => base,
+    };
+    Some((kind, value))
+}
+
+/// Diff two versions of a structured artifact.
+///
+/// Returns `None` if either version is not a recognized structured
+/// format, or if both parse to the same document.
+pub fn diff(name: &str, old: &[u8], new: &[u8]) -> Option<ContractDiff> {
+    let (_, old_value) = detect(name, old)?;
+    let (kind, new_value) = detect(name, new)?;
+
+    let mut changes = Vec::new();
+    diff_values(kind, &mut Vec::new(), &old_value, &new_value, &mut changes);
+    if changes.is_empty() {
+        return None;
+    }
+    Some(ContractDiff { kind, changes })
+}
+
+fn diff_values(
+    kind: ContractKind,
+    path: &mut Vec<String>,
+    old: &Value,
+    new: &Value,
+    out: &mut Vec<Change>,
+) {
+    match (old, new) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (key, old_child) in a {
+                path.push(key.clone());
+                match b.get(key) {
+                    Some(new_child) => diff_values(kind, path, old_child, new_child, out),
+                    None => push(kind, path, ChangeKind::Removed, Some(old_child), None, out),
+                }
+                path.pop();
+            }
+            for (key, new_child) in b {
+                if !a.contains_key(key) {
+                    path.push(key.clone());
+                    push(kind, path, ChangeKind::Added, None, Some(new_child), out);
+                    path.pop();
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => diff_arrays(kind, path, a, b, out),
+        (a, b) if a != b => push(kind, path, ChangeKind::Modified, Some(a), Some(b), out),
+        _ => {}
+    }
+}
+
+/// Arrays of scalars (`required`, `enum`, tags) are compared as sets;
+/// OpenAPI parameter lists are matched by `in` + `name`; anything else
+/// is compared index by index.
+fn diff_arrays(
+    kind: ContractKind,
+    path: &mut Vec<String>,
+    a: &[Value],
+    b: &[Value],
+    out: &mut Vec<Change>,
+) {
+    let is_scalar = |v: &Value| !v.is_object() && !v.is_array();
+    if a.iter().chain(b).all(is_scalar) {
+        for item in a.iter().filter(|v| !b.contains(v)) {
+            push(kind, path, ChangeKind::Removed, Some(item), None, out);
+        }
+        for item in b.iter().filter(|v| !a.contains(v)) {
+            push(kind, path, ChangeKind::Added, None, Some(item), out);
+        }
+        return;
+    }
+
+    if kind == ContractKind::OpenApi && path.last().is_some_and(|k| k == "parameters") {
+        let key = |v: &Value| {
+            format!(
+                "{}:{}",
+                v.get("in").and_then(Value::as_str).unwrap_or(""),
+                v.get("name").and_then(Value::as_str).unwrap_or("")
+            )
+        };
+        for old_item in a {
+            path.push(key(old_item));
+            match b.iter().find(|n| key(n) == key(old_item)) {
+                Some(new_item) => diff_values(kind, path, old_item, new_item, out),
+                None => push(kind, path, ChangeKind::Removed, Some(old_item), None, out),
+            }
+            path.pop();
+        }
+        for new_item in b.iter().filter(|n| !a.iter().any(|o| key(o) == key(n))) {
+            path.push(key(new_item));
+            push(kind, path, ChangeKind::Added, None, Some(new_item), out);
+            path.pop();
+        }
+        return;
+    }
+
+    for i in 0..a.len().max(b.len()) {
+        path.push(i.to_string());
+        match (a.get(i), b.get(i)) {
+            (Some(x), Some(y)) => diff_values(kind, path, x, y, out),
+            (Some(x), None) => push(kind, path, ChangeKind::Removed, Some(x), None, out),
+            (None, Some(y)) => push(kind, path, ChangeKind::Added, None, Some(y), out),
+            (None, None) => {}
+        }
+        path.pop();
+    }
+}
+
+fn push(
+    kind: ContractKind,
+    path: &[String],
+    change: ChangeKind,
+    old: Option<&Value>,
+    new: Option<&Value>,
+    out: &mut Vec<Change>,
+) {
+    let detail = match (old, new) {
+        (Some(o), Some(n)) => format!("({} -> {})", brief(o), brief(n)),
+        (Some(o), None) => format!("(was {})", brief(o)),
+        (None, Some(n)) => format!("({})", brief(n)),
+        (None, None) => String::new(),
+    };
+    out.push(Change {
+        path: pointer(path),
+        kind: change,
+        breaking: is_breaking(kind, path, change, old, new),
+        detail,
+    });
+}
+
+/// Decide whether a change can break an existing consumer.
+fn is_breaking(
+    kind: ContractKind,
+    path: &[String],
+    change: ChangeKind,
+    old: Option<&Value>,
+    new: Option<&Value>,
+) -> bool {
+    let key = path.last().map(String::as_str).unwrap_or("");
+
+    // Documentation never breaks consumers: a doc string as the changed
+    // key, or anything inside an example or extension. Under `properties`
+    // a key names a schema property, so `properties/title` is not one.
+    let names_property = |i: usize| i > 0 && path[i - 1] == "properties";
+    let doc_text = matches!(key, "description" | "summary" | "title")
+        && !names_property(path.len().saturating_sub(1));
+    let doc_tree = path.iter().enumerate().any(|(i, k)| {
+        (matches!(k.as_str(), "example" | "examples" | "externalDocs") || k.starts_with("x-"))
+            && !names_property(i)
+    });
+    let is_doc = doc_text || doc_tree;
+    if is_doc || (kind == ContractKind::OpenApi && path.first().is_some_and(|k| k == "info")) {
+        return false;
+    }
+
+    // Set membership of `required` and `enum`: tightening breaks.
+    if key == "required" && !new.or(old).is_some_and(Value::is_boolean) {
+        return match change {
+            ChangeKind::Added => true,
+            ChangeKind::Removed => false,
+            ChangeKind::Modified => true,
+        };
+    }
+    if key == "enum" {
+        return change != ChangeKind::Added;
+    }
+
+    match change {
+        ChangeKind::Removed => true,
+        ChangeKind::Added => match kind {
+            // A new parameter (or flag) that must be supplied breaks callers.
+            ContractKind::OpenApi => {
+                let required_param = path.iter().any(|k| k == "parameters")
+                    && new
+                        .and_then(|v| v.get("required"))
+                        .is_some_and(|r| r == &Value::Bool(true));
+                (key == "required" && new == Some(&Value::Bool(true))) || required_param
+            }
+            ContractKind::JsonSchema => {
+                key == "additionalProperties" && new == Some(&Value::Bool(false))
+            }
+            ContractKind::Json | ContractKind::Yaml => false,
+        },
+        ChangeKind::Modified => {
+            let type_changed = match (old, new) {
+                (Some(o), Some(n)) => std::mem::discriminant(o) != std::mem::discriminant(n),
+                _ => false,
+            };
+            match kind {
+                ContractKind::Json | ContractKind::Yaml => type_changed,
+                ContractKind::OpenApi | ContractKind::JsonSchema => match key {
+                    "required" => new == Some(&Value::Bool(true)),
+                    "additionalProperties" => new == Some(&Value::Bool(false)),
+                    "deprecated" | "default" => false,
+                    _ => true,
+                },
+            }
+        }
+    }
+}
+
+/// Render a path as an RFC 6901 JSON pointer.
+fn pointer(path: &[String]) -> String {
+    if path.is_empty() {
+        return "/".to_string();
+    }
+    path.iter()
+        .map(|p| format!("/{}", p.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+/// Compact single-line rendering of a value for summaries.
+fn brief(value: &Value) -> String {
+    let s = match value {
+        Value::Object(o) => format!("object with {} keys", o.len()),
+        Value::Array(a) => format!("array of {}", a.len()),
+        other => other.to_string(),
+    };
+    if s.chars().count() > 60 {
+        format!("{}...", s.chars().take(57).collect::<String>())
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema_diff(old: Value, new: Value) -> ContractDiff {
+        diff(
+            "schema.json",
+            old.to_string().as_bytes(),
+            new.to_string().as_bytes(),
+        )
+        .expect("documents differ")
+    }
+
+    fn change<'a>(diff: &'a ContractDiff, path: &str) -> &'a Change {
+        diff.changes
+            .iter()
+            .find(|c| c.path == path)
+            .unwrap_or_else(|| panic!("no change at {}: {:?}", path, diff.changes))
+    }
+
+    #[test]
+    fn removing_a_property_named_like_a_doc_field_breaks() {
+        let old = serde_json::json!({
+            "type": "object",
+            "properties": {"title": {"type": "string"}, "id": {"type": "string"}}
+        });
+        let new = serde_json::json!({
+            "type": "object",
+            "properties": {"id": {"type": "string"}}
+        });
+        let diff = schema_diff(old, new);
+        assert!(change(&diff, "/properties/title").breaking);
+    }
+
+    #[test]
+    fn documentation_changes_do_not_break() {
+        let old = serde_json::json!({
+            "type": "object",
+            "title": "User",
+            "properties": {"id": {"type": "string", "description": "Id"}}
+        });
+        let new = serde_json::json!({
+            "type": "object",
+            "properties": {"id": {"type": "string", "description": "User id"}}
+        });
+        let diff = schema_diff(old, new);
+        assert!(!change(&diff, "/title").breaking);
+        assert!(!change(&diff, "/properties/id/description").breaking);
+        assert!(!diff.is_breaking());
+    }
+
+    #[test]
+    fn tightening_required_breaks_and_loosening_does_not() {
+        let base = serde_json::json!({
+            "type": "object",
+            "properties": {"id": {}, "name": {}},
+            "required": ["id"]
+        });
+        let mut tighter = base.clone();
+        tighter["required"] = serde_json::json!(["id", "name"]);
+
+        let diff = schema_diff(base.clone(), tighter.clone());
+        assert_eq!(change(&diff, "/required").kind, ChangeKind::Added);
+        assert!(diff.is_breaking());
+
+        let diff = schema_diff(tighter, base);
+        assert_eq!(change(&diff, "/required").kind, ChangeKind::Removed);
+        assert!(!diff.is_breaking());
+    }
+
+    #[test]
+    fn narrowing_enum_breaks_and_widening_does_not() {
+        let base = serde_json::json!({
+            "type": "object",
+            "properties": {"role": {"enum": ["admin", "user"]}}
+        });
+        let mut wider = base.clone();
+        wider["properties"]["role"]["enum"] = serde_json::json!(["admin", "user", "guest"]);
+
+        let diff = schema_diff(base.clone(), wider.clone());
+        assert!(!diff.is_breaking());
+
+        let diff = schema_diff(wider, base);
+        assert_eq!(
+            change(&diff, "/properties/role/enum").kind,
+            ChangeKind::Removed
+        );
+        assert!(diff.is_breaking());
+    }
+}
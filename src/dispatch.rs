@@ -0,0 +1,424 @@
+//! Task dispatch from the beads ready queue to idle agents.
+//!
+//! When an agent reports `idle`, and periodically for agents that stay
+//! idle, the orchestrator asks the task tracker for ready work and hands
+//! each idle domain the first task labeled with its name, as a
+//! `type: task_assignment` message in its inbox. Each assignment is
+//! logged as a `task_assigned` event. A task is only assigned once, and
+//! a domain is not offered another until its agent reports a new state,
+//! including across restarts.
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::ProjectConfig;
+use crate::event::{Event, EventLog, EventQuery};
+use crate::event_kind::{EventKind, TaskAssigned};
+use crate::router::{Message, Router};
+use crate::types::DomainId;
+
+/// How long `bd ready` may run before dispatch gives up on it.
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A task with no open blockers, as listed by `bd ready --json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadyTask {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    /// 0 (highest) to 4 (lowest).
+    #[serde(default)]
+    pub priority: Option<u8>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+impl ReadyTask {
+    /// Whether the task is labeled for `domain`.
+    pub fn is_for(&self, domain: &DomainId) -> bool {
+        self.labels.iter().any(|label| label == domain.as_str())
+    }
+
+    /// The message priority matching the task's.
+    fn message_priority(&self) -> &'static str {
+        match self.priority {
+            Some(0 | 1) => "high",
+            Some(3..) => "low",
+            _ => "medium",
+        }
+    }
+}
+
+/// A boxed future returned by [`TaskTracker`] methods.
+pub type TrackerFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// A source of ready tasks.
+pub trait TaskTracker: Send + Sync {
+    /// Tasks ready to be worked on, most important first.
+    fn ready(&self) -> TrackerFuture<'_, Vec<ReadyTask>>;
+}
+
+/// Open the task tracker if dispatch is enabled in `config`.
+pub fn open_tracker(config: &ProjectConfig) -> Option<Arc<dyn TaskTracker>> {
+    if !config.tasks.dispatch {
+        return None;
+    }
+    let program = config
+        .tasks
+        .command
+        .clone()
+        .unwrap_or_else(|| "bd".to_string());
+    Some(Arc::new(BeadsTracker::new(program)))
+}
+
+/// Ready tasks from the beads CLI.
+pub struct BeadsTracker {
+    program: String,
+}
+
+impl BeadsTracker {
+    pub fn new(program: String) -> Self {
+        Self { program }
+    }
+}
+
+impl TaskTracker for BeadsTracker {
+    fn ready(&self) -> TrackerFuture<'_, Vec<ReadyTask>> {
+        Box::pin(async move {
+            let run = tokio::process::Command::new(&self.program)
+                .args(["ready", "--json"])
+                .kill_on_drop(true)
+                .output();
+            let output = tokio::time::timeout(READY_TIMEOUT, run)
+                .await
+                .with_context(|| {
+                    format!(
+                        "`{} ready` did not finish within {}s",
+                        self.program,
+                        READY_TIMEOUT.as_secs()
+                    )
+                })?
+                .with_context(|| format!("failed to execute `{} ready`", self.program))?;
+
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                bail!("{} ready failed: {}", self.program, stderr.trim());
+            }
+            serde_json::from_slice(&output.stdout)
+                .with_context(|| format!("parsing `{} ready --json` output", self.program))
+        })
+    }
+}
+
+/// Assigns ready tasks to idle domains.
+pub struct Dispatcher {
+    tracker: Arc<dyn TaskTracker>,
+    event_log: Arc<dyn EventLog>,
+    /// Ids of the tasks already assigned.
+    assigned: HashSet<String>,
+    /// Domains assigned a task whose agent has not reported a new state
+    /// since.
+    awaiting: HashSet<DomainId>,
+}
+
+impl Dispatcher {
+    /// Create a dispatcher, recalling the assignments already logged and
+    /// which domains have not moved on from theirs.
+    pub fn new(tracker: Arc<dyn TaskTracker>, event_log: Arc<dyn EventLog>) -> Result<Self> {
+        let mut dispatcher = Self {
+            tracker,
+            event_log,
+            assigned: HashSet::new(),
+            awaiting: HashSet::new(),
+        };
+        let query = EventQuery {
+            kinds: ["task_assigned", "agent_state_changed"]
+                .into_iter()
+                .map(str::to_owned)
+                .collect(),
+            ..EventQuery::default()
+        };
+        for logged in dispatcher.event_log.query(&query)? {
+            match logged.event.typed() {
+                Ok(EventKind::TaskAssigned(a)) => {
+                    dispatcher.assigned.insert(a.task);
+                    dispatcher.awaiting.insert(DomainId::new(a.domain));
+                }
+                Ok(EventKind::AgentStateChanged(c)) => {
+                    dispatcher.state_changed(&DomainId::new(c.domain));
+                }
+                _ => {}
+            }
+        }
+        Ok(dispatcher)
+    }
+
+    /// Note that `domain`'s agent reported a new state, so that it can be
+    /// offered a task again once it is idle.
+    pub fn state_changed(&mut self, domain: &DomainId) {
+        self.awaiting.remove(domain);
+    }
+
+    /// Deliver each of the idle `domains` the first unassigned ready task
+    /// labeled for it, skipping domains still holding their last
+    /// assignment. Returns the tasks assigned.
+    pub async fn dispatch(
+        &mut self,
+        router: &Router,
+        domains: &[DomainId],
+    ) -> Result<Vec<(DomainId, ReadyTask)>> {
+        let domains: Vec<&DomainId> = domains
+            .iter()
+            .filter(|domain| !self.awaiting.contains(*domain))
+            .collect();
+        if domains.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ready = self.tracker.ready().await?;
+        let mut dispatched = Vec::new();
+        for domain in domains {
+            let Some(task) = ready
+                .iter()
+                .find(|task| task.is_for(domain) && !self.assigned.contains(&task.id))
+            else {
+                tracing::debug!(domain = %domain, "no ready task for idle agent");
+                continue;
+            };
+            self.assign(router, domain, task)?;
+            dispatched.push((domain.clone(), task.clone()));
+        }
+        Ok(dispatched)
+    }
+
+    /// Deliver `task` to `domain`'s inbox and log the assignment.
+    fn assign(&mut self, router: &Router, domain: &DomainId, task: &ReadyTask) -> Result<()> {
+        let mut body = format!("You have been assigned `{}`: {}", task.id, task.title);
+        if !task.description.is_empty() {
+            body.push_str("\n\n");
+            body.push_str(&task.description);
+        }
+        body.push_str(&format!(
+            "\n\nSet your status to `working` on this task, and write \
+             `completion-{}.md` to your outbox when it is done.",
+            task.id
+        ));
+        let message = Message {
+            from: domain.clone(),
+            to: domain.clone(),
+            msg_type: "task_assignment".to_string(),
+            task: task.id.clone(),
+            priority: task.message_priority().to_string(),
+            artifacts: Vec::new(),
+            body,
+        };
+        router.deliver_notice(&message, &format!("task_assignment-{}", task.id))?;
+        self.assigned.insert(task.id.clone());
+        self.awaiting.insert(domain.clone());
+
+        tracing::info!(domain = %domain, task = %task.id, "assigned ready task");
+        let event = Event::new(EventKind::TaskAssigned(TaskAssigned {
+            domain: domain.to_string(),
+            task: task.id.clone(),
+            title: task.title.clone(),
+            priority: task.priority,
+        }));
+        if let Err(e) = self.event_log.log(&event) {
+            tracing::error!(error = %e, "failed to log task assigned event");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::artifact::FsArtifactStore;
+    use crate::event::FileEventLog;
+    use crate::event_kind::AgentStateChanged;
+    use crate::heartbeat::AgentRegistry;
+    use crate::staleness::AckRegistry;
+    use crate::subscription::SubscriptionRegistry;
+    use crate::types::AgentState;
+
+    /// A fixed list of ready tasks.
+    struct MockTracker {
+        tasks: Vec<ReadyTask>,
+    }
+
+    impl TaskTracker for MockTracker {
+        fn ready(&self) -> TrackerFuture<'_, Vec<ReadyTask>> {
+            Box::pin(async move { Ok(self.tasks.clone()) })
+        }
+    }
+
+    fn task(id: &str, label: &str, priority: Option<u8>) -> ReadyTask {
+        ReadyTask {
+            id: id.to_string(),
+            title: format!("Task {}", id),
+            description: String::new(),
+            priority,
+            labels: vec![label.to_string()],
+        }
+    }
+
+    /// A router over scratch `.orchestrator/` directories for `a` and `b`,
+    /// removed on drop.
+    struct Fixture {
+        dir: PathBuf,
+        router: Router,
+        event_log: Arc<FileEventLog>,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "comm-node-dispatch-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            let mut domains = HashMap::new();
+            for domain in ["a", "b"] {
+                let orch_dir = dir.join(domain).join(".orchestrator");
+                std::fs::create_dir_all(orch_dir.join("inbox")).unwrap();
+                domains.insert(DomainId::new(domain), orch_dir);
+            }
+
+            let config: ProjectConfig = toml::from_str("[domains]").unwrap();
+            let event_log = Arc::new(FileEventLog::new(dir.join("event.log")));
+            let router = Router::new(
+                domains,
+                HashMap::new(),
+                Arc::new(FsArtifactStore::new(HashMap::new())),
+                event_log.clone(),
+                Arc::new(SubscriptionRegistry::load(&config, dir.join("subs.json")).unwrap()),
+                Arc::new(AckRegistry::load(dir.join("acks.json")).unwrap()),
+                Arc::new(AgentRegistry::new()),
+            );
+            Self {
+                dir,
+                router,
+                event_log,
+            }
+        }
+
+        fn dispatcher(&self, tasks: Vec<ReadyTask>) -> Dispatcher {
+            Dispatcher::new(Arc::new(MockTracker { tasks }), self.event_log.clone()).unwrap()
+        }
+
+        async fn dispatch(&self, dispatcher: &mut Dispatcher, domain: &str) -> Vec<String> {
+            dispatcher
+                .dispatch(&self.router, &[DomainId::new(domain)])
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(_, task)| task.id)
+                .collect()
+        }
+
+        fn inbox(&self, domain: &str) -> Vec<String> {
+            let inbox = self.dir.join(domain).join(".orchestrator").join("inbox");
+            let mut messages: Vec<String> = std::fs::read_dir(inbox)
+                .unwrap()
+                .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+                .collect();
+            messages.sort();
+            messages
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[tokio::test]
+    async fn assigns_the_first_task_labeled_for_the_domain() {
+        let fixture = Fixture::new("labels");
+        let mut dispatcher = fixture.dispatcher(vec![
+            task("t1", "b", None),
+            task("t2", "a", Some(1)),
+            task("t3", "a", None),
+        ]);
+
+        assert_eq!(fixture.dispatch(&mut dispatcher, "a").await, ["t2"]);
+        let inbox = fixture.inbox("a");
+        assert_eq!(inbox.len(), 1);
+        assert!(inbox[0].contains("You have been assigned `t2`"));
+        assert!(inbox[0].contains("priority: high"));
+        assert!(fixture.inbox("b").is_empty());
+    }
+
+    #[tokio::test]
+    async fn skips_tasks_already_assigned() {
+        let fixture = Fixture::new("assigned");
+        let mut dispatcher = fixture.dispatcher(vec![task("t1", "a", None), task("t2", "a", None)]);
+
+        assert_eq!(fixture.dispatch(&mut dispatcher, "a").await, ["t1"]);
+        dispatcher.state_changed(&DomainId::new("a"));
+        assert_eq!(fixture.dispatch(&mut dispatcher, "a").await, ["t2"]);
+        dispatcher.state_changed(&DomainId::new("a"));
+        assert!(fixture.dispatch(&mut dispatcher, "a").await.is_empty());
+        assert_eq!(fixture.inbox("a").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn waits_for_a_new_state_before_assigning_again() {
+        let fixture = Fixture::new("awaiting");
+        let mut dispatcher = fixture.dispatcher(vec![task("t1", "a", None), task("t2", "a", None)]);
+
+        assert_eq!(fixture.dispatch(&mut dispatcher, "a").await, ["t1"]);
+        assert!(fixture.dispatch(&mut dispatcher, "a").await.is_empty());
+        assert_eq!(fixture.inbox("a").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn recalls_logged_assignments_after_a_restart() {
+        let fixture = Fixture::new("restart");
+        let tasks = vec![task("t1", "a", None), task("t2", "a", None)];
+        let mut dispatcher = fixture.dispatcher(tasks.clone());
+        assert_eq!(fixture.dispatch(&mut dispatcher, "a").await, ["t1"]);
+
+        // Still holding t1, so nothing new.
+        let mut restarted = fixture.dispatcher(tasks.clone());
+        assert!(fixture.dispatch(&mut restarted, "a").await.is_empty());
+
+        // Once it has moved on, t1 is not handed out again.
+        fixture
+            .event_log
+            .log(&Event::new(EventKind::AgentStateChanged(
+                AgentStateChanged {
+                    domain: "a".to_string(),
+                    previous: Some(AgentState::Idle),
+                    state: AgentState::Working,
+                    current_task: Some("t1".to_string()),
+                },
+            )))
+            .unwrap();
+        let mut restarted = fixture.dispatcher(tasks);
+        assert_eq!(fixture.dispatch(&mut restarted, "a").await, ["t2"]);
+    }
+
+    #[test]
+    fn maps_tracker_priority_to_message_priority() {
+        let priority = |p| task("t", "a", p).message_priority();
+        assert_eq!(priority(Some(0)), "high");
+        assert_eq!(priority(Some(1)), "high");
+        assert_eq!(priority(Some(2)), "medium");
+        assert_eq!(priority(None), "medium");
+        assert_eq!(priority(Some(3)), "low");
+        assert_eq!(priority(Some(4)), "low");
+    }
+}
@@ -1,14 +1,41 @@
 //! Event logging trait and filesystem implementation.
 //!
 //! Events are the comm-node's internal audit trail: messages routed,
-//! locks acquired, agents registered, etc. Append-only log,
-//! queryable with grep/jq in the filesystem implementation.
+//! locks acquired, agents registered, etc. The filesystem log is
+//! append-only JSONL, one record per line carrying a unique id and a
+//! sequence number, so it stays queryable with grep/jq. An optional
+//! sidecar index (`<log>.idx`) records each event's offset and
+//! filterable fields so queries read only the matching lines.
+//!
+//! When the active file grows too large or too old it is rotated into a
+//! gzip-compressed segment under `<log stem>.archive/`, described by
+//! `segments.json` there. Segments past the retention period are
+//! deleted. Queries span the archived segments and the active file.
+//!
+//! Every event records the hash of the one before it, so the log forms
+//! a chain; the hash of the newest event is kept alongside the log
+//! (`<log>.head`) so truncation is detectable too. `EventLog::verify`
+//! walks the chain and reports the first broken link.
 
-use std::path::PathBuf;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::artifact::{sha256_hex, write_atomic};
+use crate::config::{EventsConfig, ProjectConfig, StateBackend};
+use crate::event_kind::EventKind;
+use crate::sqlite::SqliteEventLog;
 
 /// A single logged event.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,60 +45,1217 @@ pub struct Event {
     pub payload: serde_json::Value,
 }
 
+impl Event {
+    /// An event of `kind`, timestamped now.
+    pub fn new(kind: EventKind) -> Self {
+        Self::at(Utc::now(), kind)
+    }
+
+    /// An event of `kind` that happened at `timestamp`.
+    pub fn at(timestamp: DateTime<Utc>, kind: EventKind) -> Self {
+        let name = kind.name();
+        let payload = match serde_json::to_value(kind) {
+            Ok(serde_json::Value::Object(mut record)) => {
+                record.remove("payload").unwrap_or_default()
+            }
+            other => unreachable!("event kind serialized as {:?}", other),
+        };
+        Self {
+            timestamp,
+            kind: name.to_string(),
+            payload,
+        }
+    }
+
+    /// The typed kind and payload. Fails for kinds this build does not
+    /// know and for payloads that do not match their kind.
+    pub fn typed(&self) -> Result<EventKind> {
+        let record = serde_json::json!({ "kind": self.kind, "payload": self.payload });
+        serde_json::from_value(record)
+            .with_context(|| format!("decoding `{}` event payload", self.kind))
+    }
+
+    /// A non-empty string field of the payload.
+    pub(crate) fn field(&self, name: &str) -> Option<&str> {
+        self.payload
+            .get(name)
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+    }
+
+    /// Every domain the payload names, sorted and deduplicated. A field
+    /// may hold one domain, a list of them, or a map to them.
+    pub(crate) fn domains(&self) -> Vec<String> {
+        let mut domains: Vec<String> = DOMAIN_FIELDS
+            .iter()
+            .filter_map(|f| self.payload.get(f))
+            .flat_map(|value| match value {
+                serde_json::Value::Array(items) => items.iter().collect(),
+                serde_json::Value::Object(map) => map.values().collect(),
+                value => vec![value],
+            })
+            .filter_map(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+            .map(str::to_owned)
+            .collect();
+        domains.sort();
+        domains.dedup();
+        domains
+    }
+}
+
+/// An event as stored in the log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggedEvent {
+    /// Position in the log, starting at 1.
+    pub seq: u64,
+    /// Unique event id.
+    pub id: Uuid,
+    /// Hash of the previous event (`GENESIS_HASH` for the first);
+    /// absent for events written before the log was chained.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev_hash: Option<String>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+impl LoggedEvent {
+    /// Hex SHA-256 of the event's JSON record, which the next event
+    /// carries as its `prev_hash`.
+    pub fn hash(&self) -> Result<String> {
+        Ok(sha256_hex(serde_json::to_string(self)?.as_bytes()))
+    }
+}
+
+/// `prev_hash` of the first event in a log.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// The newest event of a chained log, recorded outside the events so
+/// that truncating the log is detectable.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainHead {
+    pub seq: u64,
+    pub hash: String,
+}
+
+/// The events retention has removed from the front of a log; its
+/// remaining chain starts right after them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChainStart {
+    /// Sequence number of the last event removed, or 0.
+    pub expired_through: u64,
+    /// Hash of that event, carried by the next one as its `prev_hash`.
+    pub expired_hash: Option<String>,
+}
+
+/// Result of `EventLog::verify`.
+#[derive(Debug, Clone, Default)]
+pub struct ChainReport {
+    /// Events whose link to the previous event was checked.
+    pub verified: u64,
+    /// Events from before the log was chained, which cannot be checked.
+    pub unchained: u64,
+    /// The last event examined.
+    pub last_seq: Option<u64>,
+    /// The first broken link, if any.
+    pub broken: Option<BrokenLink>,
+}
+
+/// Where and how the chain is broken.
+#[derive(Debug, Clone)]
+pub struct BrokenLink {
+    /// The event at which the chain stops holding.
+    pub seq: u64,
+    pub reason: String,
+}
+
+/// Walk `events` (oldest first) and check each links to the one before
+/// it. The chain must start at `start_seq` with `start_prev_hash`
+/// (earlier events may have been removed by retention) and include the
+/// recorded `head`.
+pub(crate) fn verify_chain(
+    events: &[LoggedEvent],
+    start_seq: u64,
+    start_prev_hash: Option<&str>,
+    head: Option<&ChainHead>,
+) -> Result<ChainReport> {
+    let mut report = ChainReport::default();
+    let broken = |seq: u64, reason: String| Some(BrokenLink { seq, reason });
+
+    let mut prev: Option<(u64, String)> = None;
+    let mut chained = false;
+    for logged in events {
+        report.last_seq = Some(logged.seq);
+        let expected_seq = prev.as_ref().map_or(start_seq, |(seq, _)| seq + 1);
+        if logged.seq != expected_seq {
+            report.broken = broken(
+                logged.seq,
+                if logged.seq == expected_seq + 1 {
+                    format!("event {} is missing", expected_seq)
+                } else if logged.seq > expected_seq {
+                    format!("events {}-{} are missing", expected_seq, logged.seq - 1)
+                } else {
+                    format!(
+                        "event {} is out of order (expected {})",
+                        logged.seq, expected_seq
+                    )
+                },
+            );
+            return Ok(report);
+        }
+
+        let expected_prev = match &prev {
+            Some((_, hash)) => hash.as_str(),
+            None => start_prev_hash.unwrap_or(GENESIS_HASH),
+        };
+        match &logged.prev_hash {
+            Some(actual) if actual == expected_prev => {
+                chained = true;
+                report.verified += 1;
+            }
+            // Events written before chaining may only precede the chain.
+            None if !chained => report.unchained += 1,
+            _ => {
+                let reason = match &prev {
+                    Some((seq, _)) => format!(
+                        "does not carry the hash of event {} (one of them was modified)",
+                        seq
+                    ),
+                    None => "does not link to the last removed event".to_string(),
+                };
+                report.broken = broken(logged.seq, reason);
+                return Ok(report);
+            }
+        }
+        prev = Some((logged.seq, logged.hash()?));
+    }
+
+    if let Some(head) = head {
+        let reason = match &prev {
+            Some((seq, _)) if *seq < head.seq => Some((
+                *seq,
+                format!(
+                    "log ends at event {} but event {} was written (truncated)",
+                    seq, head.seq
+                ),
+            )),
+            None if head.seq >= start_seq => Some((
+                start_seq,
+                format!(
+                    "log is empty but event {} was written (truncated)",
+                    head.seq
+                ),
+            )),
+            _ => events
+                .iter()
+                .find(|e| e.seq == head.seq)
+                .map(|e| e.hash())
+                .transpose()?
+                .filter(|hash| *hash != head.hash)
+                .map(|_| {
+                    (
+                        head.seq,
+                        "does not match the recorded hash of the newest event (modified)"
+                            .to_string(),
+                    )
+                }),
+        };
+        if let Some((seq, reason)) = reason {
+            report.broken = broken(seq, reason);
+        }
+    }
+    Ok(report)
+}
+
+/// Payload fields that name a domain, matched by `EventQuery::domain`.
+const DOMAIN_FIELDS: &[&str] = &[
+    "from",
+    "to",
+    "holder",
+    "domain",
+    "producer",
+    "consumer",
+    "subscriber",
+    "notified",
+    "reassigned",
+];
+
+/// Filter for `EventLog::query`. Unset fields match every event.
+#[derive(Debug, Clone, Default)]
+pub struct EventQuery {
+    /// Match any of these kinds.
+    pub kinds: BTreeSet<String>,
+    /// Only events with a sequence number above this one.
+    pub after_seq: Option<u64>,
+    /// Only events at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only events before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Only events whose payload names this domain (`from`, `to`,
+    /// `holder`, `domain`, `producer`, `consumer`, `subscriber`, a
+    /// `notified` peer or a `reassigned` holder).
+    pub domain: Option<String>,
+    /// Only events for this task id.
+    pub task: Option<String>,
+    /// Only events for this message id.
+    pub message_id: Option<String>,
+    /// Only events whose message `body` contains every one of these
+    /// words (case-insensitive).
+    pub text: Option<String>,
+    /// Return at most this many events.
+    pub limit: Option<usize>,
+    /// Return the newest events first.
+    pub reverse: bool,
+}
+
+impl EventQuery {
+    /// Query for every event of one kind.
+    pub fn kind(kind: impl Into<String>) -> Self {
+        Self {
+            kinds: BTreeSet::from([kind.into()]),
+            ..Self::default()
+        }
+    }
+
+    fn matches(&self, entry: &IndexEntry) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&entry.kind))
+            && self.after_seq.is_none_or(|after| entry.seq > after)
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp < until)
+            && self
+                .domain
+                .as_ref()
+                .is_none_or(|d| entry.domains.contains(d))
+            && self
+                .task
+                .as_ref()
+                .is_none_or(|t| entry.task.as_ref() == Some(t))
+            && self
+                .message_id
+                .as_ref()
+                .is_none_or(|m| entry.message_id.as_ref() == Some(m))
+    }
+
+    /// The `text` filter, which needs the event itself.
+    fn matches_text(&self, logged: &LoggedEvent) -> bool {
+        let Some(text) = &self.text else {
+            return true;
+        };
+        let body = logged.event.field("body").unwrap_or("").to_lowercase();
+        text.split_whitespace()
+            .all(|word| body.contains(&word.to_lowercase()))
+    }
+}
+
 /// Trait for event logging backends.
 pub trait EventLog: Send + Sync {
     /// Append an event to the log.
     fn log(&self, event: &Event) -> Result<()>;
 
-    /// Query events by kind, returning matches in chronological order.
-    fn query(&self, kind: &str) -> Result<Vec<Event>>;
+    /// Events matching `query`, oldest first unless `query.reverse` is set.
+    fn query(&self, query: &EventQuery) -> Result<Vec<LoggedEvent>>;
+
+    /// Check the hash chain over every stored event.
+    fn verify(&self) -> Result<ChainReport>;
+}
+
+/// Open the event log selected by `[events] backend`.
+pub fn open_log(config: &ProjectConfig, state_dir: &Path) -> Result<Arc<dyn EventLog>> {
+    Ok(match config.events.backend {
+        StateBackend::File => Arc::new(FileEventLog::from_config(
+            state_dir.join("event.log"),
+            &config.events,
+        )),
+        StateBackend::Sqlite => Arc::new(SqliteEventLog::open(&state_dir.join("comm-node.db"))?),
+    })
+}
+
+/// Where an event sits in the log file, plus the fields queries filter on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    seq: u64,
+    offset: u64,
+    len: u64,
+    timestamp: DateTime<Utc>,
+    kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    task: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    domains: Vec<String>,
 }
 
-/// Append-only file-based event log (TSV: timestamp + JSON).
+impl IndexEntry {
+    fn new(logged: &LoggedEvent, offset: u64, len: u64) -> Self {
+        let event = &logged.event;
+        Self {
+            seq: logged.seq,
+            offset,
+            len,
+            timestamp: event.timestamp,
+            kind: event.kind.clone(),
+            task: event.field("task").map(str::to_owned),
+            message_id: event.field("message_id").map(str::to_owned),
+            domains: event.domains(),
+        }
+    }
+}
+
+/// A run of consecutive events: an archived segment, or the events in
+/// the active file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Segment {
+    /// Compressed file name within the archive directory.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    file: String,
+    first_seq: u64,
+    last_seq: u64,
+    events: u64,
+    earliest: DateTime<Utc>,
+    latest: DateTime<Utc>,
+    kinds: BTreeSet<String>,
+    /// Hash of the segment's last event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_hash: Option<String>,
+}
+
+impl Segment {
+    fn start(seq: u64, timestamp: DateTime<Utc>, kind: &str) -> Self {
+        Self {
+            file: String::new(),
+            first_seq: seq,
+            last_seq: seq,
+            events: 1,
+            earliest: timestamp,
+            latest: timestamp,
+            kinds: BTreeSet::from([kind.to_string()]),
+            last_hash: None,
+        }
+    }
+
+    /// Add an event to `segment`, starting it if empty.
+    fn include(segment: &mut Option<Self>, seq: u64, timestamp: DateTime<Utc>, kind: &str) {
+        let Some(s) = segment else {
+            *segment = Some(Self::start(seq, timestamp, kind));
+            return;
+        };
+        s.last_seq = s.last_seq.max(seq);
+        s.events += 1;
+        s.earliest = s.earliest.min(timestamp);
+        s.latest = s.latest.max(timestamp);
+        if !s.kinds.contains(kind) {
+            s.kinds.insert(kind.to_string());
+        }
+    }
+
+    /// Whether the segment may hold events matching `query`.
+    fn may_match(&self, query: &EventQuery) -> bool {
+        (query.kinds.is_empty() || !query.kinds.is_disjoint(&self.kinds))
+            && query.after_seq.is_none_or(|after| self.last_seq > after)
+            && query.since.is_none_or(|since| self.latest >= since)
+            && query.until.is_none_or(|until| self.earliest < until)
+    }
+}
+
+/// Archived segments, oldest first (`segments.json`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Archive {
+    /// Sequence number of the last event ever archived.
+    #[serde(default)]
+    archived_through: u64,
+    /// Hash of that event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    archived_hash: Option<String>,
+    /// Sequence number of the last event removed by retention.
+    #[serde(default)]
+    expired_through: u64,
+    /// Hash of that event, where the remaining chain starts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expired_hash: Option<String>,
+    #[serde(default)]
+    segments: Vec<Segment>,
+}
+
+/// What the log has seen of its files so far.
+struct LogState {
+    /// Bytes of the active file already accounted for.
+    len: u64,
+    /// Sequence number of the last event.
+    last_seq: u64,
+    /// Hash of the last event.
+    last_hash: Option<String>,
+    /// Events in the active file.
+    active: Option<Segment>,
+    /// Every active event's index entry, when the sidecar index is enabled.
+    index: Option<Vec<IndexEntry>>,
+    archive: Archive,
+    /// Modification time of `segments.json` when `archive` was read.
+    archive_stamp: Option<SystemTime>,
+}
+
+impl LogState {
+    fn include(&mut self, entry: IndexEntry) {
+        self.last_seq = self.last_seq.max(entry.seq);
+        self.last_hash = None;
+        Segment::include(&mut self.active, entry.seq, entry.timestamp, &entry.kind);
+        if let Some(index) = self.index.as_mut() {
+            index.push(entry);
+        }
+    }
+}
+
+/// Append-only file-based event log (JSONL) with rotation.
 pub struct FileEventLog {
     path: PathBuf,
+    index_path: Option<PathBuf>,
+    archive_dir: PathBuf,
+    /// Rotate once the active file reaches this size.
+    rotate_bytes: Option<u64>,
+    /// Rotate once the oldest active event is this old.
+    rotate_after: Option<chrono::Duration>,
+    /// Delete segments whose newest event is older than this.
+    retention: Option<chrono::Duration>,
+    state: Mutex<Option<LogState>>,
 }
 
 impl FileEventLog {
     pub fn new(path: PathBuf) -> Self {
-        Self { path }
+        let archive_dir = path.with_extension("archive");
+        Self {
+            path,
+            index_path: None,
+            archive_dir,
+            rotate_bytes: None,
+            rotate_after: None,
+            retention: None,
+            state: Mutex::new(None),
+        }
+    }
+
+    /// A log that maintains a sidecar index next to `path`.
+    pub fn with_index(path: PathBuf) -> Self {
+        let mut index_path = path.clone().into_os_string();
+        index_path.push(".idx");
+        Self {
+            index_path: Some(index_path.into()),
+            ..Self::new(path)
+        }
+    }
+
+    /// A log with the index and rotation settings from `config`.
+    pub fn from_config(path: PathBuf, config: &EventsConfig) -> Self {
+        let secs = |s: u64| chrono::Duration::seconds(s.min(i64::MAX as u64) as i64);
+        let log = if config.index {
+            Self::with_index(path)
+        } else {
+            Self::new(path)
+        };
+        Self {
+            rotate_bytes: config.rotate_bytes,
+            rotate_after: config.rotate_after_secs.map(secs),
+            retention: config.retention_secs.map(secs),
+            ..log
+        }
+    }
+
+    /// Where the chain starts after the events retention has removed.
+    pub fn chain_start(&self) -> Result<ChainStart> {
+        let archive = self.load_archive()?;
+        Ok(ChainStart {
+            expired_through: archive.expired_through,
+            expired_hash: archive.expired_hash,
+        })
+    }
+
+    fn segments_path(&self) -> PathBuf {
+        self.archive_dir.join("segments.json")
+    }
+
+    fn head_path(&self) -> PathBuf {
+        let mut head_path = self.path.clone().into_os_string();
+        head_path.push(".head");
+        head_path.into()
+    }
+
+    /// The recorded newest event, if any has been written.
+    fn head(&self) -> Result<Option<ChainHead>> {
+        let path = self.head_path();
+        match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .with_context(|| format!("parsing event chain head: {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Hash of the last event, reading it back if only its index entry
+    /// has been seen.
+    fn last_hash(&self, state: &mut LogState) -> Result<Option<String>> {
+        if state.last_hash.is_none() {
+            state.last_hash = match state.index.as_ref().and_then(|i| i.last()) {
+                Some(entry) => {
+                    let query = EventQuery {
+                        after_seq: Some(entry.seq - 1),
+                        ..EventQuery::default()
+                    };
+                    self.query_active(state, &query)?
+                        .first()
+                        .map(LoggedEvent::hash)
+                        .transpose()?
+                }
+                None => match (&state.archive.archived_hash, state.archive.segments.last()) {
+                    (Some(hash), _) => Some(hash.clone()),
+                    // Archived before segments recorded their last hash.
+                    (None, Some(segment)) => {
+                        let query = EventQuery {
+                            after_seq: Some(segment.last_seq - 1),
+                            ..EventQuery::default()
+                        };
+                        self.query_segment(segment, &query)?
+                            .last()
+                            .map(LoggedEvent::hash)
+                            .transpose()?
+                    }
+                    (None, None) => None,
+                },
+            };
+        }
+        Ok(state.last_hash.clone())
+    }
+
+    /// Bring `state` up to date with the log files, indexing any events
+    /// appended since it was last read (including by another process).
+    fn refresh(&self, slot: &mut Option<LogState>) -> Result<()> {
+        let file_len = match std::fs::metadata(&self.path) {
+            Ok(meta) => meta.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        let stamp = modified(&self.segments_path())?;
+
+        let reusable = match slot.take() {
+            Some(mut s) if s.len <= file_len => {
+                if s.archive_stamp != stamp {
+                    s.archive = self.load_archive()?;
+                    s.archive_stamp = stamp;
+                }
+                // Keep it unless another process rotated the events we hold.
+                let rotated = s
+                    .active
+                    .as_ref()
+                    .is_some_and(|a| a.first_seq <= s.archive.archived_through);
+                (!rotated).then_some(s)
+            }
+            // First use, or the file shrank underneath us: start over.
+            _ => None,
+        };
+        let current = match reusable {
+            Some(s) => slot.insert(s),
+            None => slot.insert(self.initial_state(file_len, stamp)?),
+        };
+        if current.len == file_len {
+            return Ok(());
+        }
+
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        reader.seek(SeekFrom::Start(current.len))?;
+        let mut added = Vec::new();
+        let mut offset = current.len;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)? as u64;
+            // Stop at EOF or at a line still being written.
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+            if let Some(logged) = parse_line(&line, current.last_seq) {
+                // Events left behind by an interrupted rotation are archived.
+                if logged.seq > current.archive.archived_through {
+                    let entry = IndexEntry::new(&logged, offset, read);
+                    if current.index.is_some() {
+                        added.push(entry.clone());
+                    }
+                    current.include(entry);
+                    current.last_hash = Some(logged.hash()?);
+                }
+            }
+            offset += read;
+        }
+        current.len = offset;
+        self.append_index(&added)?;
+        Ok(())
+    }
+
+    /// State for a freshly opened log: the archive, plus the sidecar
+    /// index entries that still match the active file (which is
+    /// otherwise read from the start).
+    fn initial_state(&self, file_len: u64, archive_stamp: Option<SystemTime>) -> Result<LogState> {
+        let archive = self.load_archive()?;
+        let mut state = LogState {
+            len: 0,
+            last_seq: archive.archived_through,
+            last_hash: archive.archived_hash.clone(),
+            active: None,
+            index: None,
+            archive,
+            archive_stamp,
+        };
+        let Some(index_path) = &self.index_path else {
+            return Ok(state);
+        };
+
+        let mut entries: Vec<IndexEntry> = Vec::new();
+        let mut consistent = true;
+        match File::open(index_path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let Ok(entry) = serde_json::from_str::<IndexEntry>(&line?) else {
+                        consistent = false;
+                        break;
+                    };
+                    // Entries written twice by concurrent readers are skipped.
+                    if entry.offset < state.len {
+                        continue;
+                    }
+                    if entry.offset != state.len || entry.offset + entry.len > file_len {
+                        consistent = false;
+                        break;
+                    }
+                    state.len = entry.offset + entry.len;
+                    entries.push(entry);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        // Drop the unusable tail so the events after it are re-indexed once.
+        if !consistent {
+            tracing::warn!(index = %index_path.display(), "event index out of date, reindexing");
+            let mut lines = String::new();
+            for entry in &entries {
+                lines.push_str(&serde_json::to_string(entry)?);
+                lines.push('\n');
+            }
+            write_atomic(index_path, lines.as_bytes())?;
+        }
+
+        state.index = Some(Vec::with_capacity(entries.len()));
+        for entry in entries {
+            if entry.seq > state.archive.archived_through {
+                state.include(entry);
+            }
+        }
+        Ok(state)
+    }
+
+    fn load_archive(&self) -> Result<Archive> {
+        let path = self.segments_path();
+        match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .with_context(|| format!("parsing event segments: {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Archive::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn append_index(&self, entries: &[IndexEntry]) -> Result<()> {
+        let Some(index_path) = &self.index_path else {
+            return Ok(());
+        };
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        for entry in entries {
+            lines.push_str(&serde_json::to_string(entry)?);
+            lines.push('\n');
+        }
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(index_path)
+            .with_context(|| format!("opening event index: {}", index_path.display()))?
+            .write_all(lines.as_bytes())?;
+        Ok(())
+    }
+
+    fn rotation_due(&self, state: &LogState) -> bool {
+        let Some(active) = &state.active else {
+            return false;
+        };
+        self.rotate_bytes.is_some_and(|max| state.len >= max)
+            || self
+                .rotate_after
+                .is_some_and(|age| Utc::now() - active.earliest >= age)
+    }
+
+    /// Compress the active file into a new archived segment, prune
+    /// segments past retention, and start a new active file.
+    fn rotate(&self, state: &mut LogState) -> Result<()> {
+        let Some(mut segment) = state.active.take() else {
+            return Ok(());
+        };
+        segment.file = format!("events-{}-{}.jsonl.gz", segment.first_seq, segment.last_seq);
+        std::fs::create_dir_all(&self.archive_dir)
+            .with_context(|| format!("creating {}", self.archive_dir.display()))?;
+
+        // Archived lines are normalized to JSONL with explicit sequence numbers.
+        let tmp = self.archive_dir.join(format!(".{}.tmp", segment.file));
+        let mut encoder = GzEncoder::new(File::create(&tmp)?, Compression::default());
+        let reader = BufReader::new(File::open(&self.path)?.take(state.len));
+        let mut last_seq = state.archive.archived_through;
+        for line in reader.lines() {
+            let Some(logged) = parse_line(&line?, last_seq) else {
+                continue;
+            };
+            last_seq = logged.seq.max(last_seq);
+            if logged.seq > state.archive.archived_through {
+                serde_json::to_writer(&mut encoder, &logged)?;
+                encoder.write_all(b"\n")?;
+                segment.last_hash = Some(logged.hash()?);
+            }
+        }
+        encoder.finish()?.sync_all()?;
+        std::fs::rename(&tmp, self.archive_dir.join(&segment.file))?;
+
+        tracing::info!(
+            segment = %segment.file,
+            events = segment.events,
+            bytes = state.len,
+            "rotated event log"
+        );
+
+        state.archive.archived_through = segment.last_seq;
+        state.archive.archived_hash = segment.last_hash.clone();
+        state.archive.segments.push(segment);
+        let expired = match self.retention {
+            Some(retention) => {
+                let cutoff = Utc::now() - retention;
+                let (expired, kept): (Vec<Segment>, _) =
+                    std::mem::take(&mut state.archive.segments)
+                        .into_iter()
+                        .partition(|s| s.latest < cutoff);
+                state.archive.segments = kept;
+                if let Some(last) = expired.last() {
+                    state.archive.expired_through = last.last_seq;
+                    state.archive.expired_hash = last.last_hash.clone();
+                }
+                expired
+            }
+            None => Vec::new(),
+        };
+        write_atomic(
+            &self.segments_path(),
+            serde_json::to_string_pretty(&state.archive)?.as_bytes(),
+        )?;
+        state.archive_stamp = modified(&self.segments_path())?;
+
+        for segment in expired {
+            tracing::info!(segment = %segment.file, "removing expired event segment");
+            remove_if_exists(&self.archive_dir.join(&segment.file))?;
+        }
+
+        // Empty the index before the log so it never describes a newer file.
+        if let Some(index_path) = &self.index_path {
+            File::create(index_path)?;
+        }
+        File::create(&self.path)?;
+        state.len = 0;
+        if let Some(index) = state.index.as_mut() {
+            index.clear();
+        }
+        Ok(())
+    }
+
+    /// Matching events in the active file, oldest first.
+    fn query_active(&self, state: &LogState, query: &EventQuery) -> Result<Vec<LoggedEvent>> {
+        let Some(index) = &state.index else {
+            let file = match File::open(&self.path) {
+                Ok(f) => f,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+                Err(e) => return Err(e.into()),
+            };
+            return scan(
+                BufReader::new(file.take(state.len)),
+                state.archive.archived_through,
+                query,
+            );
+        };
+
+        let mut matches: Vec<&IndexEntry> = index.iter().filter(|e| query.matches(e)).collect();
+        // Only the newest events are needed when reading backwards.
+        if let (true, Some(limit), None) = (query.reverse, query.limit, &query.text) {
+            matches.drain(..matches.len().saturating_sub(limit));
+        }
+
+        let mut file = File::open(&self.path)?;
+        let mut events = Vec::with_capacity(matches.len());
+        let mut buf = Vec::new();
+        for entry in matches {
+            file.seek(SeekFrom::Start(entry.offset))?;
+            buf.resize(entry.len as usize, 0);
+            file.read_exact(&mut buf)?;
+            let logged = parse_line(std::str::from_utf8(&buf)?, entry.seq - 1)
+                .filter(|logged| logged.seq == entry.seq)
+                .with_context(|| {
+                    format!(
+                        "event {} at offset {} is unreadable",
+                        entry.seq, entry.offset
+                    )
+                })?;
+            if query.matches_text(&logged) {
+                events.push(logged);
+            }
+        }
+        Ok(events)
+    }
+
+    /// Matching events in an archived segment, oldest first.
+    fn query_segment(&self, segment: &Segment, query: &EventQuery) -> Result<Vec<LoggedEvent>> {
+        let path = self.archive_dir.join(&segment.file);
+        let file = match File::open(&path) {
+            Ok(f) => f,
+            // Removed by retention since the archive was read.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(e).with_context(|| format!("opening {}", path.display()));
+            }
+        };
+        scan(
+            BufReader::new(GzDecoder::new(file)),
+            segment.first_seq - 1,
+            query,
+        )
+        .with_context(|| format!("reading event segment {}", path.display()))
     }
 }
 
 impl EventLog for FileEventLog {
     fn log(&self, event: &Event) -> Result<()> {
-        use std::io::Write;
-        let json = serde_json::to_string(&event.payload)?;
-        let line = format!("{}\t{}\t{}\n", event.timestamp.to_rfc3339(), event.kind, json);
+        let mut guard = self.state.lock().expect("event log lock poisoned");
+        self.refresh(&mut guard)?;
+        let state = guard.as_mut().expect("event log state initialized");
+
+        let logged = LoggedEvent {
+            seq: state.last_seq + 1,
+            id: Uuid::new_v4(),
+            prev_hash: Some(
+                self.last_hash(state)?
+                    .unwrap_or_else(|| GENESIS_HASH.to_string()),
+            ),
+            event: event.clone(),
+        };
+        let mut line = serde_json::to_string(&logged)?;
+        let head = ChainHead {
+            seq: logged.seq,
+            hash: sha256_hex(line.as_bytes()),
+        };
+        line.push('\n');
+
         let mut file = std::fs::OpenOptions::new()
             .create(true)
             .append(true)
             .open(&self.path)?;
         file.write_all(line.as_bytes())?;
+        write_atomic(&self.head_path(), serde_json::to_string(&head)?.as_bytes())?;
+
+        let len = line.len() as u64;
+        let entry = IndexEntry::new(&logged, state.len, len);
+        if state.index.is_some() {
+            self.append_index(std::slice::from_ref(&entry))?;
+        }
+        state.include(entry);
+        state.last_hash = Some(head.hash);
+        state.len += len;
+
+        if self.rotation_due(state) {
+            self.rotate(state).context("rotating event log")?;
+        }
         Ok(())
     }
 
-    fn query(&self, kind: &str) -> Result<Vec<Event>> {
-        let content = match std::fs::read_to_string(&self.path) {
-            Ok(c) => c,
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
-            Err(e) => return Err(e.into()),
-        };
+    fn query(&self, query: &EventQuery) -> Result<Vec<LoggedEvent>> {
+        let mut guard = self.state.lock().expect("event log lock poisoned");
+        self.refresh(&mut guard)?;
+        let state = guard.as_ref().expect("event log state initialized");
+
+        // Sources in log order: archived segments, then the active file.
+        let mut sources: Vec<Option<&Segment>> = state
+            .archive
+            .segments
+            .iter()
+            .filter(|s| s.may_match(query))
+            .map(Some)
+            .collect();
+        sources.push(None);
+        if query.reverse {
+            sources.reverse();
+        }
 
         let mut events = Vec::new();
-        for line in content.lines() {
-            let parts: Vec<&str> = line.splitn(3, '\t').collect();
-            if parts.len() == 3 && parts[1] == kind {
-                let timestamp = parts[0].parse::<DateTime<Utc>>()?;
-                let payload: serde_json::Value = serde_json::from_str(parts[2])?;
-                events.push(Event {
+        for source in sources {
+            let mut found = match source {
+                Some(segment) => self.query_segment(segment, query)?,
+                None => self.query_active(state, query)?,
+            };
+            if query.reverse {
+                found.reverse();
+            }
+            events.extend(found);
+            if query.limit.is_some_and(|limit| events.len() >= limit) {
+                break;
+            }
+        }
+        if let Some(limit) = query.limit {
+            events.truncate(limit);
+        }
+        Ok(events)
+    }
+
+    fn verify(&self) -> Result<ChainReport> {
+        // Read the head first: events written after it only extend the chain.
+        let head = self.head()?;
+        let events = self.query(&EventQuery::default())?;
+        let start = self.chain_start()?;
+        verify_chain(
+            &events,
+            start.expired_through + 1,
+            start.expired_hash.as_deref(),
+            head.as_ref(),
+        )
+    }
+}
+
+/// Matching events read from `reader`, skipping any at or before
+/// `last_seq` (already archived).
+fn scan(reader: impl BufRead, last_seq: u64, query: &EventQuery) -> Result<Vec<LoggedEvent>> {
+    let skip_through = last_seq;
+    let mut last_seq = last_seq;
+    let mut events = Vec::new();
+    for line in reader.lines() {
+        let Some(logged) = parse_line(&line?, last_seq) else {
+            continue;
+        };
+        last_seq = logged.seq.max(last_seq);
+        if logged.seq > skip_through
+            && query.matches(&IndexEntry::new(&logged, 0, 0))
+            && query.matches_text(&logged)
+        {
+            events.push(logged);
+        }
+    }
+    Ok(events)
+}
+
+/// Parse one log line. Lines in the older TSV format (`timestamp`,
+/// `kind`, JSON payload) are read with a nil id and the next sequence
+/// number after `last_seq`; anything else unreadable is skipped.
+fn parse_line(line: &str, last_seq: u64) -> Option<LoggedEvent> {
+    let line = line.trim_end_matches('\n');
+    if line.trim().is_empty() {
+        return None;
+    }
+    if let Ok(logged) = serde_json::from_str::<LoggedEvent>(line) {
+        return Some(logged);
+    }
+
+    let parts: Vec<&str> = line.splitn(3, '\t').collect();
+    let parsed = match parts[..] {
+        [timestamp, kind, payload] => timestamp
+            .parse::<DateTime<Utc>>()
+            .ok()
+            .zip(serde_json::from_str(payload).ok())
+            .map(|(timestamp, payload)| LoggedEvent {
+                seq: last_seq + 1,
+                id: Uuid::nil(),
+                prev_hash: None,
+                event: Event {
                     timestamp,
-                    kind: parts[1].to_string(),
+                    kind: kind.to_string(),
                     payload,
-                });
+                },
+            }),
+        _ => None,
+    };
+    if parsed.is_none() {
+        tracing::warn!(line = %line, "skipping unreadable event log line");
+    }
+    parsed
+}
+
+/// Modification time of `path`, if it exists.
+fn modified(path: &Path) -> Result<Option<SystemTime>> {
+    match std::fs::metadata(path) {
+        Ok(meta) => Ok(Some(meta.modified()?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory holding `event.log`, removed on drop.
+    struct Fixture {
+        dir: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "comm-node-event-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self { dir }
+        }
+
+        fn path(&self) -> PathBuf {
+            self.dir.join("event.log")
+        }
+
+        /// Log `count` events into a plain log and return it.
+        fn log(&self, count: u64) -> FileEventLog {
+            let log = FileEventLog::new(self.path());
+            for n in 1..=count {
+                log.log(&note(Utc::now(), n)).unwrap();
             }
+            log
         }
 
-        Ok(events)
+        /// Rewrite the active file line by line.
+        fn edit_lines(&self, edit: impl Fn(Vec<String>) -> Vec<String>) {
+            let content = std::fs::read_to_string(self.path()).unwrap();
+            let lines = edit(content.lines().map(str::to_owned).collect());
+            std::fs::write(self.path(), lines.join("\n") + "\n").unwrap();
+        }
+
+        /// Verify the log as a fresh process would see it.
+        fn verify(&self) -> ChainReport {
+            FileEventLog::new(self.path()).verify().unwrap()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn note(timestamp: DateTime<Utc>, n: u64) -> Event {
+        Event {
+            timestamp,
+            kind: "note".to_string(),
+            payload: serde_json::json!({ "n": n }),
+        }
+    }
+
+    fn broken(report: &ChainReport) -> (u64, &str) {
+        let link = report.broken.as_ref().expect("chain should be broken");
+        (link.seq, link.reason.as_str())
+    }
+
+    #[test]
+    fn verifies_an_intact_chain() {
+        let fixture = Fixture::new("intact");
+        fixture.log(5);
+        let report = fixture.verify();
+        assert!(report.broken.is_none());
+        assert_eq!((report.verified, report.last_seq), (5, Some(5)));
+    }
+
+    #[test]
+    fn reports_an_edited_payload() {
+        let fixture = Fixture::new("edited");
+        fixture.log(5);
+        fixture.edit_lines(|mut lines| {
+            lines[2] = lines[2].replace(r#""n":3"#, r#""n":30"#);
+            lines
+        });
+        let report = fixture.verify();
+        let (seq, reason) = broken(&report);
+        assert_eq!(seq, 4);
+        assert!(reason.contains("hash of event 3"), "{}", reason);
+    }
+
+    #[test]
+    fn reports_a_deleted_line() {
+        let fixture = Fixture::new("deleted");
+        fixture.log(5);
+        fixture.edit_lines(|mut lines| {
+            lines.remove(2);
+            lines
+        });
+        let report = fixture.verify();
+        assert_eq!(broken(&report), (4, "event 3 is missing"));
+    }
+
+    #[test]
+    fn reports_a_truncated_tail_against_the_head() {
+        let fixture = Fixture::new("truncated");
+        fixture.log(5);
+        fixture.edit_lines(|mut lines| {
+            lines.truncate(3);
+            lines
+        });
+        let report = fixture.verify();
+        let (seq, reason) = broken(&report);
+        assert_eq!(seq, 3);
+        assert!(
+            reason.contains("event 5 was written (truncated)"),
+            "{}",
+            reason
+        );
+    }
+
+    #[test]
+    fn stays_valid_across_rotation_and_retention() {
+        let fixture = Fixture::new("retention");
+        let config: EventsConfig =
+            toml::from_str("rotate_bytes = 1\nretention_secs = 86400").unwrap();
+        let log = FileEventLog::from_config(fixture.path(), &config);
+        // Every event rotates into its own segment; the old ones expire.
+        let old = Utc::now() - chrono::Duration::days(2);
+        for n in 1..=4 {
+            log.log(&note(old, n)).unwrap();
+        }
+        for n in 5..=8 {
+            log.log(&note(Utc::now(), n)).unwrap();
+        }
+
+        let start = log.chain_start().unwrap();
+        assert_eq!(start.expired_through, 4);
+        let report = FileEventLog::from_config(fixture.path(), &config)
+            .verify()
+            .unwrap();
+        assert!(report.broken.is_none(), "{:?}", report.broken);
+        assert_eq!((report.verified, report.last_seq), (4, Some(8)));
+
+        // The first kept event must still link to the last expired one.
+        let segments = fixture.dir.join("event.archive/segments.json");
+        let archive = std::fs::read_to_string(&segments).unwrap();
+        let expired_hash = start.expired_hash.unwrap();
+        std::fs::write(&segments, archive.replace(&expired_hash, GENESIS_HASH)).unwrap();
+        let report = FileEventLog::from_config(fixture.path(), &config)
+            .verify()
+            .unwrap();
+        assert_eq!(
+            broken(&report),
+            (5, "does not link to the last removed event")
+        );
     }
 }
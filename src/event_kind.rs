@@ -0,0 +1,321 @@
+//! Typed event kinds and their payloads.
+//!
+//! Each [`EventKind`] variant names an event and carries a payload
+//! struct; it serializes to the `kind` and `payload` fields of an
+//! [`Event`](crate::event::Event) record, so typed and untyped readers
+//! see the same log. Build events with `Event::new` and read them back
+//! with `Event::typed`.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::artifact::QuotaLimit;
+use crate::contract::{Change, ContractKind};
+use crate::types::{AgentState, MessageId};
+
+/// Every kind of event the comm-node logs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "payload", rename_all = "snake_case")]
+pub enum EventKind {
+    MessageRouted(MessageRouted),
+    MessageRejected(MessageRejected),
+    ArtifactRouted(ArtifactRouted),
+    ArtifactUpdated(ArtifactUpdated),
+    ArtifactAcknowledged(ArtifactAcknowledged),
+    ArtifactStale(ArtifactStale),
+    ContractChanged(ContractChanged),
+    SecurityViolation(SecurityViolation),
+    QuotaExceeded(QuotaExceeded),
+    LockAcquired(LockAcquired),
+    LockReleased(LockReleased),
+    LockExpired(LockExpired),
+    AgentStateChanged(AgentStateChanged),
+    AgentStale(AgentStale),
+    AgentRecovered(AgentRecovered),
+    StatusInvalid(StatusInvalid),
+    LocksReclaimed(LocksReclaimed),
+    LocksEscalated(LocksEscalated),
+    AgentStarted(AgentStarted),
+    AgentExited(AgentExited),
+    TaskAssigned(TaskAssigned),
+}
+
+impl EventKind {
+    /// The `kind` string of every event, in declaration order.
+    pub const NAMES: &'static [&'static str] = &[
+        "message_routed",
+        "message_rejected",
+        "artifact_routed",
+        "artifact_updated",
+        "artifact_acknowledged",
+        "artifact_stale",
+        "contract_changed",
+        "security_violation",
+        "quota_exceeded",
+        "lock_acquired",
+        "lock_released",
+        "lock_expired",
+        "agent_state_changed",
+        "agent_stale",
+        "agent_recovered",
+        "status_invalid",
+        "locks_reclaimed",
+        "locks_escalated",
+        "agent_started",
+        "agent_exited",
+        "task_assigned",
+    ];
+
+    /// The `kind` string this event is logged under.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MessageRouted(_) => "message_routed",
+            Self::MessageRejected(_) => "message_rejected",
+            Self::ArtifactRouted(_) => "artifact_routed",
+            Self::ArtifactUpdated(_) => "artifact_updated",
+            Self::ArtifactAcknowledged(_) => "artifact_acknowledged",
+            Self::ArtifactStale(_) => "artifact_stale",
+            Self::ContractChanged(_) => "contract_changed",
+            Self::SecurityViolation(_) => "security_violation",
+            Self::QuotaExceeded(_) => "quota_exceeded",
+            Self::LockAcquired(_) => "lock_acquired",
+            Self::LockReleased(_) => "lock_released",
+            Self::LockExpired(_) => "lock_expired",
+            Self::AgentStateChanged(_) => "agent_state_changed",
+            Self::AgentStale(_) => "agent_stale",
+            Self::AgentRecovered(_) => "agent_recovered",
+            Self::StatusInvalid(_) => "status_invalid",
+            Self::LocksReclaimed(_) => "locks_reclaimed",
+            Self::LocksEscalated(_) => "locks_escalated",
+            Self::AgentStarted(_) => "agent_started",
+            Self::AgentExited(_) => "agent_exited",
+            Self::TaskAssigned(_) => "task_assigned",
+        }
+    }
+}
+
+/// A message delivered to its target's inbox.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageRouted {
+    /// Absent on events logged before messages were given ids.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<MessageId>,
+    pub from: String,
+    pub to: String,
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub task: String,
+    pub priority: String,
+    pub artifacts: Vec<String>,
+    /// Size of the message file as read from the outbox.
+    pub size_bytes: usize,
+    #[serde(default)]
+    pub body: String,
+}
+
+/// A parsed message the router refused to deliver.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageRejected {
+    pub message_id: MessageId,
+    pub from: String,
+    pub to: String,
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub task: String,
+    pub reason: String,
+}
+
+/// An artifact, or a bundle of them, copied along with a message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactRouted {
+    pub from: String,
+    pub to: String,
+    pub task: String,
+    /// Artifact name, or the bundle reference for a bundle.
+    pub artifact: String,
+    pub version: u32,
+    /// Bundle members; empty for a single artifact.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<String>,
+}
+
+/// A producer's artifact pushed to a subscriber.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactUpdated {
+    pub producer: String,
+    pub subscriber: String,
+    pub artifact: String,
+    pub version: u32,
+    pub sha256: String,
+    pub summary: String,
+}
+
+/// A domain confirming the artifact version it builds against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactAcknowledged {
+    pub domain: String,
+    pub artifact: String,
+    pub version: u32,
+    pub producer: String,
+}
+
+/// An artifact version left unacknowledged past the staleness window.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactStale {
+    pub domain: String,
+    pub artifact: String,
+    pub producer: String,
+    pub received: u32,
+    pub acknowledged: Option<u32>,
+    pub behind: u32,
+    pub waiting_since: DateTime<Utc>,
+}
+
+/// A structural change to a contract artifact delivered to a consumer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractChanged {
+    pub producer: String,
+    pub consumer: String,
+    pub artifact: String,
+    pub version: u32,
+    pub format: ContractKind,
+    pub breaking: bool,
+    pub breaking_changes: usize,
+    pub changes: Vec<Change>,
+}
+
+/// A message naming an artifact outside the sender's `artifacts/`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityViolation {
+    pub from: String,
+    pub to: String,
+    pub task: String,
+    pub artifact: String,
+    pub reason: String,
+}
+
+/// An artifact rejected for exceeding the target's size limits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaExceeded {
+    pub from: String,
+    pub to: String,
+    pub task: String,
+    pub artifact: String,
+    pub limit: QuotaLimit,
+    pub limit_bytes: u64,
+    pub requested_bytes: u64,
+}
+
+/// An advisory file lock taken by a domain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockAcquired {
+    pub path: String,
+    pub holder: String,
+}
+
+/// An advisory file lock given up by its holder.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockReleased {
+    pub path: String,
+    pub holder: String,
+}
+
+/// An advisory file lock dropped without its holder releasing it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockExpired {
+    pub path: String,
+    pub holder: String,
+    pub acquired_at: DateTime<Utc>,
+    pub reason: String,
+}
+
+/// A domain's agent moving between lifecycle states.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStateChanged {
+    pub domain: String,
+    /// State before the change; absent when first observed.
+    pub previous: Option<AgentState>,
+    pub state: AgentState,
+    pub current_task: Option<String>,
+}
+
+/// An agent whose heartbeat is older than the configured timeout.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStale {
+    pub domain: String,
+    /// State the agent last reported.
+    pub state: AgentState,
+    pub last_heartbeat: DateTime<Utc>,
+    pub timeout_secs: u64,
+    /// Peers blocked on the domain that were notified.
+    pub notified: Vec<String>,
+}
+
+/// A stale agent writing a fresh heartbeat.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentRecovered {
+    pub domain: String,
+    pub state: AgentState,
+    pub last_heartbeat: DateTime<Utc>,
+}
+
+/// A `status.json` update rejected, with the last valid status kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusInvalid {
+    pub domain: String,
+    pub error: String,
+}
+
+/// A stale agent's locks released under the `release` lock policy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocksReclaimed {
+    pub holder: String,
+    pub paths: Vec<String>,
+    /// New holder of each path handed to a waiting domain.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reassigned: BTreeMap<String, String>,
+}
+
+/// A stale agent's locks kept under the `escalate` lock policy, with
+/// the waiting domains told.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocksEscalated {
+    pub holder: String,
+    pub paths: Vec<String>,
+    /// Domains notified, each waiting on at least one of the paths.
+    pub notified: Vec<String>,
+}
+
+/// A supervised agent process launched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStarted {
+    pub domain: String,
+    pub pid: Option<u32>,
+    pub command: String,
+    /// 1 for the first launch, counting up with each restart.
+    pub attempt: u32,
+}
+
+/// A supervised agent process exited.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentExited {
+    pub domain: String,
+    pub pid: Option<u32>,
+    /// Exit code; absent if the process was killed by a signal.
+    pub code: Option<i32>,
+    /// Human-readable exit status.
+    pub status: String,
+    pub uptime_secs: u64,
+}
+
+/// A ready task from the tracker delivered to an idle domain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskAssigned {
+    pub domain: String,
+    pub task: String,
+    pub title: String,
+    /// Tracker priority, 0 (highest) to 4.
+    pub priority: Option<u8>,
+}
@@ -0,0 +1,185 @@
+//! Frontmatter extraction for outbox messages.
+//!
+//! A message starts with a frontmatter block on its very first line:
+//! `---` for YAML or `+++` for TOML, closed by the same delimiter on a
+//! line of its own. Everything after the closing delimiter is the body,
+//! so `---` horizontal rules or document markers in the body are left
+//! alone. Parse errors are reported against line numbers in the
+//! original file.
+
+use anyhow::{anyhow, bail, Result};
+use serde::de::DeserializeOwned;
+
+/// Frontmatter syntax, determined by the opening delimiter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `---` delimited YAML.
+    Yaml,
+    /// `+++` delimited TOML.
+    Toml,
+}
+
+impl Format {
+    fn from_delimiter(line: &str) -> Option<Self> {
+        match line {
+            "---" => Some(Self::Yaml),
+            "+++" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    fn delimiter(self) -> &'static str {
+        match self {
+            Self::Yaml => "---",
+            Self::Toml => "+++",
+        }
+    }
+}
+
+/// A frontmatter block split from its body.
+#[derive(Debug, Clone)]
+pub struct Frontmatter<'a> {
+    pub format: Format,
+    /// Raw frontmatter text between the delimiters.
+    pub raw: &'a str,
+    /// 1-based line number of the first frontmatter line in the file.
+    pub first_line: usize,
+    /// Body text after the closing delimiter, trimmed.
+    pub body: &'a str,
+}
+
+impl Frontmatter<'_> {
+    /// Deserialize the frontmatter, mapping error locations to file lines.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T> {
+        // Pad with the lines above the block so parser-reported
+        // locations line up with the original file.
+        let padded = format!("{}{}", "\n".repeat(self.first_line - 1), self.raw);
+        match self.format {
+            Format::Yaml => serde_yaml::from_str(&padded).map_err(|e| {
+                let line = e.location().map_or(self.first_line, |l| l.line());
+                anyhow!("line {}: invalid YAML frontmatter: {}", line, e)
+            }),
+            Format::Toml => toml::from_str(&padded).map_err(|e| {
+                let line = e
+                    .span()
+                    .map_or(self.first_line, |s| line_of(&padded, s.start));
+                anyhow!("line {}: invalid TOML frontmatter: {}", line, e.message())
+            }),
+        }
+    }
+}
+
+/// Split a frontmatter block from the start of `content`.
+///
+/// The opening delimiter must be the first line (a leading BOM is
+/// ignored) and the closing delimiter must match it on its own line.
+pub fn extract(content: &str) -> Result<Frontmatter<'_>> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+
+    let (first, rest) = split_line(content);
+    let format = Format::from_delimiter(first.trim_end())
+        .ok_or_else(|| anyhow!("line 1: expected `---` or `+++` frontmatter delimiter"))?;
+
+    let mut offset = 0;
+    let mut remaining = rest;
+    let mut line_no = 2;
+    while !remaining.is_empty() {
+        let (line, next) = split_line(remaining);
+        if line.trim_end() == format.delimiter() {
+            return Ok(Frontmatter {
+                format,
+                raw: &rest[..offset],
+                first_line: 2,
+                body: next.trim(),
+            });
+        }
+        offset += remaining.len() - next.len();
+        remaining = next;
+        line_no += 1;
+    }
+
+    bail!(
+        "line {}: unterminated frontmatter, expected closing `{}` opened on line 1",
+        line_no - 1,
+        format.delimiter()
+    );
+}
+
+/// Deserialize a pure-JSON document, reporting the failing line.
+pub fn from_json<T: DeserializeOwned>(content: &str) -> Result<T> {
+    serde_json::from_str(content)
+        .map_err(|e| anyhow!("line {}: invalid JSON message: {}", e.line(), e))
+}
+
+/// Split off the first line, returning it without its terminator.
+fn split_line(s: &str) -> (&str, &str) {
+    match s.find('\n') {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, ""),
+    }
+}
+
+/// 1-based line number of a byte offset within `s`.
+fn line_of(s: &str, offset: usize) -> usize {
+    s[..offset.min(s.len())].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Header {
+        to: String,
+        #[serde(default)]
+        count: u32,
+    }
+
+    #[test]
+    fn leaves_delimiters_in_the_body_alone() {
+        let fm = extract("---\nto: b\n---\nIntro\n\n---\n\nMore\n").unwrap();
+        assert_eq!(fm.format, Format::Yaml);
+        assert_eq!(fm.raw, "to: b\n");
+        assert_eq!(fm.body, "Intro\n\n---\n\nMore");
+        assert_eq!(fm.deserialize::<Header>().unwrap().to, "b");
+    }
+
+    #[test]
+    fn rejects_an_unterminated_block() {
+        let err = extract("---\nto: b\nbody\n").unwrap_err().to_string();
+        assert!(
+            err.starts_with("line 3: unterminated frontmatter"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn parses_toml_frontmatter() {
+        let fm = extract("+++\nto = \"b\"\ncount = 2\n+++\nBody\n").unwrap();
+        assert_eq!(fm.format, Format::Toml);
+        let header: Header = fm.deserialize().unwrap();
+        assert_eq!((header.to.as_str(), header.count), ("b", 2));
+        assert_eq!(fm.body, "Body");
+    }
+
+    #[test]
+    fn requires_the_delimiter_on_the_first_line() {
+        let err = extract("\n---\nto: b\n---\n").unwrap_err().to_string();
+        assert!(err.starts_with("line 1: expected"), "{}", err);
+        assert!(extract("# Title\n---\nto: b\n---\n").is_err());
+    }
+
+    #[test]
+    fn reports_errors_at_file_line_numbers() {
+        let yaml = extract("---\nto: b\ncount: many\n---\n").unwrap();
+        let err = yaml.deserialize::<Header>().unwrap_err().to_string();
+        assert!(err.starts_with("line 3: invalid YAML"), "{}", err);
+
+        let toml = extract("+++\nto = \"b\"\n\ncount = \"many\"\n+++\n").unwrap();
+        let err = toml.deserialize::<Header>().unwrap_err().to_string();
+        assert!(err.starts_with("line 4: invalid TOML"), "{}", err);
+    }
+}
@@ -0,0 +1,530 @@
+//! Git-backed artifact store.
+//!
+//! Every stored artifact version is committed to a dedicated local git
+//! repository (`<state_dir>/artifact-repo/`) at `<domain>/<name>`, one
+//! commit per version, with producer, task, and version recorded as
+//! commit trailers. History can be browsed with `git log` and any
+//! version restored by commit. Each domain still receives a plain copy
+//! in `.orchestrator/artifacts/<name>`.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+
+use crate::artifact::{
+    check_checksum, collect_names, confine, current_versions, install_copy, open_verified,
+    read_verified, sha256_hex, stage_bundle, undo_bundle, update_manifest, verify_tree,
+    ArtifactLimits, ArtifactStore, ArtifactVersion, Staged,
+};
+use crate::bundle::{self, BundleVersion};
+use crate::config::ProjectConfig;
+use crate::manifest::{IntegrityIssue, Manifest, Provenance};
+
+/// Artifact store that commits each version to a local git repository.
+pub struct GitArtifactStore {
+    /// Map of domain name -> domain root path.
+    roots: HashMap<String, PathBuf>,
+    /// Size limits per domain; domains without an entry are unlimited.
+    limits: HashMap<String, ArtifactLimits>,
+    /// Work tree of the artifact repository.
+    repo: PathBuf,
+    /// Serializes git operations; the index is shared.
+    git_lock: Mutex<()>,
+}
+
+impl GitArtifactStore {
+    pub fn new(roots: HashMap<String, PathBuf>, repo: PathBuf) -> Self {
+        Self {
+            roots,
+            limits: HashMap::new(),
+            repo,
+            git_lock: Mutex::new(()),
+        }
+    }
+
+    /// Build a store over every domain in a project config.
+    pub fn from_config(config: &ProjectConfig, repo: PathBuf) -> Self {
+        let roots = config
+            .domains
+            .iter()
+            .map(|(id, dc)| (id.as_str().to_owned(), dc.path.clone()))
+            .collect();
+        Self {
+            limits: ArtifactLimits::for_project(config),
+            ..Self::new(roots, repo)
+        }
+    }
+
+    fn root(&self, domain: &str) -> Result<&PathBuf> {
+        self.roots
+            .get(domain)
+            .ok_or_else(|| anyhow::anyhow!("unknown domain: {}", domain))
+    }
+
+    fn artifacts_dir(&self, domain: &str) -> Result<PathBuf> {
+        Ok(self.root(domain)?.join(".orchestrator/artifacts"))
+    }
+
+    fn manifest_path(&self, domain: &str) -> Result<PathBuf> {
+        Ok(self.root(domain)?.join(".orchestrator/manifest.json"))
+    }
+
+    fn bundles_path(&self, domain: &str) -> Result<PathBuf> {
+        Ok(self.root(domain)?.join(".orchestrator/bundles.json"))
+    }
+
+    /// Path of an artifact relative to the repository root.
+    fn repo_path(&self, domain: &str, name: &str) -> Result<String> {
+        self.root(domain)?;
+        confine(&self.repo.join(domain), name)?;
+        Ok(format!("{}/{}", domain, name))
+    }
+
+    /// Run git in the repository, returning stdout.
+    fn git(&self, args: &[&str]) -> Result<Vec<u8>> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.repo)
+            .args(args)
+            .output()
+            .context("failed to execute `git`")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("git {} failed: {}", args.join(" "), stderr.trim());
+        }
+        Ok(output.stdout)
+    }
+
+    /// Create the repository on first use.
+    fn ensure_repo(&self) -> Result<()> {
+        if self.repo.join(".git").exists() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.repo)
+            .with_context(|| format!("creating artifact repo: {}", self.repo.display()))?;
+        self.git(&["init", "--quiet"])?;
+        self.git(&["config", "user.name", "comm-node"])?;
+        self.git(&["config", "user.email", "comm-node@localhost"])?;
+        self.git(&["config", "commit.gpgsign", "false"])?;
+        tracing::info!(repo = %self.repo.display(), "initialized artifact repository");
+        Ok(())
+    }
+
+    /// Commit staged content as the next version of `name` and deliver
+    /// it to the domain. Callers hold `git_lock`.
+    fn record(
+        &self,
+        domain: &str,
+        name: &str,
+        staged: Staged,
+        provenance: &Provenance,
+        rolled_back_from: Option<u32>,
+    ) -> Result<ArtifactVersion> {
+        let repo_path = self.repo_path(domain, name)?;
+        let history = self.history(&repo_path)?;
+
+        let file = self.repo.join(&repo_path);
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        if rolled_back_from.is_none() {
+            if let Some(latest) = history.last() {
+                if latest.hash == staged.hash {
+                    // Same content: restore the work tree file, the
+                    // domain's copy and its manifest entry in case any
+                    // was removed or edited.
+                    staged.commit(&file)?;
+                    self.install_current(domain, name, &file, latest)?;
+                    return Ok(latest.clone());
+                }
+            }
+        }
+
+        let mut version = ArtifactVersion::next(
+            &history,
+            staged.hash.clone(),
+            staged.size_bytes,
+            provenance,
+            rolled_back_from,
+        );
+
+        staged.commit(&file)?;
+        self.git(&["add", "--", &repo_path])?;
+
+        let message = commit_message(&repo_path, &version);
+        self.git(&[
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "--date",
+            &version.stored_at.to_rfc3339(),
+            "-m",
+            &message,
+            "--",
+            &repo_path,
+        ])?;
+        let commit = String::from_utf8(self.git(&["rev-parse", "HEAD"])?)?;
+        version.commit = Some(commit.trim().to_string());
+
+        self.install_current(domain, name, &file, &version)?;
+
+        Ok(version)
+    }
+
+    /// Copy the work tree `file` to the domain as the current `name`
+    /// and record `version` in its manifest.
+    fn install_current(
+        &self,
+        domain: &str,
+        name: &str,
+        file: &Path,
+        version: &ArtifactVersion,
+    ) -> Result<()> {
+        let current = confine(&self.artifacts_dir(domain)?, name)?;
+        if let Some(parent) = current.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // An independent copy: the work tree file must not change
+        // behind git's back if an agent edits the domain's copy.
+        install_copy(file, &current)?;
+        update_manifest(&self.manifest_path(domain)?, name, version)
+    }
+
+    /// Versions of a repository path, oldest first, read from commit trailers.
+    fn history(&self, repo_path: &str) -> Result<Vec<ArtifactVersion>> {
+        if !self.repo.join(".git").exists() {
+            return Ok(vec![]);
+        }
+        // An empty repository has no HEAD to log from.
+        if self
+            .git(&["rev-parse", "--verify", "--quiet", "HEAD"])
+            .is_err()
+        {
+            return Ok(vec![]);
+        }
+
+        let log = self.git(&[
+            "log",
+            "--reverse",
+            "--format=%H%x1f%(trailers:only,unfold)%x1e",
+            "--",
+            repo_path,
+        ])?;
+        let log = String::from_utf8(log)?;
+
+        let mut versions = Vec::new();
+        for record in log.split('\x1e') {
+            let record = record.trim();
+            if record.is_empty() {
+                continue;
+            }
+            let Some((commit, trailers)) = record.split_once('\x1f') else {
+                continue;
+            };
+            let version = parse_trailers(trailers)
+                .with_context(|| format!("parsing artifact commit {}", commit))?;
+            versions.push(ArtifactVersion {
+                commit: Some(commit.to_string()),
+                ..version
+            });
+        }
+        Ok(versions)
+    }
+
+    /// Stream a committed version of a repository path into a staging file.
+    fn stage_version(&self, commit: &str, repo_path: &str) -> Result<Staged> {
+        let spec = format!("{}:{}", commit, repo_path);
+        let mut child = Command::new("git")
+            .arg("-C")
+            .arg(&self.repo)
+            .args(["cat-file", "blob", &spec])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("failed to execute `git`")?;
+        let mut stdout = child.stdout.take().context("git stdout not captured")?;
+        let staged = Staged::from_reader(&self.repo, &mut stdout, None);
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("git cat-file blob {} failed: {}", spec, stderr.trim());
+        }
+        staged
+    }
+
+    /// Check staged content against a domain's limits, then commit it as
+    /// the next version of `name`.
+    fn ingest(
+        &self,
+        domain: &str,
+        name: &str,
+        staged: Staged,
+        provenance: &Provenance,
+    ) -> Result<ArtifactVersion> {
+        self.check_limits(domain, &[(name, staged.size_bytes)])?;
+        let _guard = self.git_lock.lock().expect("git lock poisoned");
+        self.record(domain, name, staged, provenance, None)
+    }
+
+    fn find_version(&self, domain: &str, name: &str, version: u32) -> Result<ArtifactVersion> {
+        let Some(entry) = self
+            .versions(domain, name)?
+            .into_iter()
+            .find(|v| v.version == version)
+        else {
+            bail!(
+                "artifact `{}` in domain `{}` has no version {}",
+                name,
+                domain,
+                version
+            );
+        };
+        Ok(entry)
+    }
+}
+
+impl ArtifactStore for GitArtifactStore {
+    fn store_reader(
+        &self,
+        domain: &str,
+        name: &str,
+        reader: &mut dyn Read,
+        provenance: &Provenance,
+    ) -> Result<ArtifactVersion> {
+        self.repo_path(domain, name)?;
+        self.ensure_repo()?;
+        let max_bytes = self.limits.get(domain).and_then(|l| l.max_artifact_bytes);
+        let staged = Staged::from_reader(&self.repo, reader, max_bytes)?;
+        self.ingest(domain, name, staged, provenance)
+    }
+
+    fn copy(
+        &self,
+        from: &str,
+        to: &str,
+        name: &str,
+        provenance: &Provenance,
+    ) -> Result<ArtifactVersion> {
+        self.repo_path(to, name)?;
+        self.ensure_repo()?;
+        let source = confine(&self.artifacts_dir(from)?, name)?;
+        let staged = Staged::from_file(&self.repo, &source)?;
+        check_checksum(&self.manifest(from)?, name, &staged.hash)?;
+        self.ingest(to, name, staged, provenance)
+    }
+
+    fn copy_bundle(
+        &self,
+        from: &str,
+        to: &str,
+        bundle: &str,
+        names: &[String],
+        provenance: &Provenance,
+    ) -> Result<BundleVersion> {
+        for name in names {
+            self.repo_path(to, name)?;
+        }
+        self.ensure_repo()?;
+        let staged = stage_bundle(self, from, to, &self.artifacts_dir(from)?, names, |_| {
+            Ok(self.repo.clone())
+        })?;
+
+        let (artifacts_dir, manifest_path) = (self.artifacts_dir(to)?, self.manifest_path(to)?);
+        let previous = current_versions(self, to, names)?;
+        let mut stored = Vec::with_capacity(staged.len());
+        let result = {
+            let _guard = self.git_lock.lock().expect("git lock poisoned");
+            (|| {
+                for (name, staged) in staged {
+                    let version = self.record(to, &name, staged, provenance, None)?;
+                    stored.push((name, version));
+                }
+                bundle::record(&self.bundles_path(to)?, bundle, &stored, provenance)
+            })()
+        };
+        // Undone without `git_lock`, which `rollback` takes.
+        result.inspect_err(|_| {
+            undo_bundle(self, to, &artifacts_dir, &manifest_path, &stored, &previous);
+        })
+    }
+
+    fn bundle_versions(&self, domain: &str, bundle: &str) -> Result<Vec<BundleVersion>> {
+        Ok(bundle::load(&self.bundles_path(domain)?)?
+            .remove(bundle)
+            .unwrap_or_default())
+    }
+
+    fn retrieve(&self, domain: &str, name: &str) -> Result<Vec<u8>> {
+        read_verified(
+            &confine(&self.artifacts_dir(domain)?, name)?,
+            &self.manifest(domain)?,
+            name,
+        )
+    }
+
+    fn open(&self, domain: &str, name: &str) -> Result<Box<dyn Read + Send>> {
+        open_verified(
+            &confine(&self.artifacts_dir(domain)?, name)?,
+            &self.manifest(domain)?,
+            name,
+        )
+    }
+
+    /// Lists nested artifacts by their `/`-separated relative names.
+    fn list(&self, domain: &str) -> Result<Vec<String>> {
+        let dir = self.artifacts_dir(domain)?;
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut names = Vec::new();
+        collect_names(&dir, "", &mut names)?;
+        names.sort();
+        Ok(names)
+    }
+
+    fn versions(&self, domain: &str, name: &str) -> Result<Vec<ArtifactVersion>> {
+        self.history(&self.repo_path(domain, name)?)
+    }
+
+    fn retrieve_version(&self, domain: &str, name: &str, version: u32) -> Result<Vec<u8>> {
+        let entry = self.find_version(domain, name, version)?;
+        let commit = entry.commit.context("artifact version has no commit")?;
+        let spec = format!("{}:{}", commit, self.repo_path(domain, name)?);
+        let data = self
+            .git(&["show", &spec])
+            .with_context(|| format!("reading version {} of artifact `{}`", version, name))?;
+        if sha256_hex(&data) != entry.hash {
+            bail!(
+                "version {} of artifact `{}` does not match its recorded checksum",
+                version,
+                name
+            );
+        }
+        Ok(data)
+    }
+
+    fn rollback(&self, domain: &str, name: &str, version: u32) -> Result<ArtifactVersion> {
+        let _guard = self.git_lock.lock().expect("git lock poisoned");
+        let entry = self.find_version(domain, name, version)?;
+        let commit = entry.commit.context("artifact version has no commit")?;
+        let staged = self.stage_version(&commit, &self.repo_path(domain, name)?)?;
+        if staged.hash != entry.hash {
+            bail!(
+                "version {} of artifact `{}` does not match its recorded checksum",
+                version,
+                name
+            );
+        }
+        let provenance = Provenance::new(entry.producer, entry.task);
+        self.record(domain, name, staged, &provenance, Some(version))
+    }
+
+    fn manifest(&self, domain: &str) -> Result<Manifest> {
+        Manifest::load(&self.manifest_path(domain)?)
+    }
+
+    fn verify(&self, domain: &str) -> Result<Vec<IntegrityIssue>> {
+        verify_tree(&self.artifacts_dir(domain)?, self.manifest(domain)?)
+    }
+
+    fn check_limits(&self, domain: &str, incoming: &[(&str, u64)]) -> Result<()> {
+        let limits = self.limits.get(domain).copied().unwrap_or_default();
+        limits.check(domain, &self.artifacts_dir(domain)?, incoming)
+    }
+}
+
+/// Commit message for a version: a summary line plus trailers that
+/// `history` parses back.
+fn commit_message(repo_path: &str, version: &ArtifactVersion) -> String {
+    let producer = trailer_value(&version.producer);
+    let mut message = format!(
+        "{} v{} from {}\n\nArtifact-Version: {}\nProducer: {}\n",
+        repo_path, version.version, producer, version.version, producer
+    );
+    if !version.task.is_empty() {
+        message.push_str(&format!("Task: {}\n", trailer_value(&version.task)));
+    }
+    message.push_str(&format!(
+        "Sha256: {}\nSize: {}\nStored-At: {}\n",
+        version.hash,
+        version.size_bytes,
+        version.stored_at.to_rfc3339()
+    ));
+    if let Some(from) = version.rolled_back_from {
+        message.push_str(&format!("Rolled-Back-From: {}\n", from));
+    }
+    message
+}
+
+/// A value flattened onto one line, so it cannot add trailers of its own.
+fn trailer_value(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+/// Rebuild an [`ArtifactVersion`] from commit trailers.
+fn parse_trailers(trailers: &str) -> Result<ArtifactVersion> {
+    let fields: HashMap<&str, &str> = trailers
+        .lines()
+        .filter_map(|line| line.split_once(": "))
+        .collect();
+    let field = |key: &str| {
+        fields
+            .get(key)
+            .copied()
+            .with_context(|| format!("missing `{}` trailer", key))
+    };
+
+    Ok(ArtifactVersion {
+        version: field("Artifact-Version")?.parse()?,
+        hash: field("Sha256")?.to_string(),
+        size_bytes: field("Size")?.parse()?,
+        stored_at: field("Stored-At")?.parse::<DateTime<Utc>>()?,
+        producer: field("Producer")?.to_string(),
+        task: fields.get("Task").copied().unwrap_or_default().to_string(),
+        rolled_back_from: fields
+            .get("Rolled-Back-From")
+            .map(|v| v.parse())
+            .transpose()?,
+        commit: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retrieve_version_rejects_content_that_does_not_match_its_checksum() {
+        let dir = std::env::temp_dir().join(format!("comm-node-git-store-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let roots = HashMap::from([("a".to_string(), dir.join("a"))]);
+        let store = GitArtifactStore::new(roots, dir.join("repo"));
+        let provenance = Provenance::new("a", "t1");
+        store
+            .store_reader("a", "spec.md", &mut &b"# Spec"[..], &provenance)
+            .unwrap();
+        assert_eq!(
+            store.retrieve_version("a", "spec.md", 1).unwrap(),
+            b"# Spec"
+        );
+
+        // Rewrite the commit's content but keep its trailers.
+        std::fs::write(dir.join("repo/a/spec.md"), "# Tampered").unwrap();
+        store
+            .git(&["commit", "-q", "-a", "--amend", "--no-edit"])
+            .unwrap();
+        let err = store.retrieve_version("a", "spec.md", 1).unwrap_err();
+        assert!(err.to_string().contains("recorded checksum"), "{:#}", err);
+        assert!(store.rollback("a", "spec.md", 1).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
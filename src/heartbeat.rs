@@ -0,0 +1,571 @@
+//! Agent status and heartbeat tracking.
+//!
+//! Each agent reports its state and a heartbeat timestamp in
+//! `.orchestrator/status.json`. The registry keeps the latest status
+//! per domain so the router can log state transitions, and flags an
+//! agent as *stale* once its heartbeat is older than the configured
+//! timeout, until it writes a newer one. Agents that report `complete`
+//! are not expected to keep a heartbeat.
+//!
+//! Updates are validated before they are recorded: an update that does
+//! not parse, names another domain, or reports `blocked` without
+//! `blocked_on` is rejected and the last valid status kept. The latest
+//! valid statuses are saved to the state dir for `comm-node status`.
+//!
+//! [`StatusMonitor`] turns updates and heartbeat checks into events and
+//! the notices the router delivers to agents' inboxes.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+
+use crate::artifact::write_atomic;
+use crate::event::{Event, EventLog};
+use crate::event_kind::{AgentRecovered, AgentStale, AgentStateChanged, EventKind, StatusInvalid};
+use crate::router::Message;
+use crate::types::{AgentState, AgentStatus, DomainId};
+
+/// Name of the status file in a domain's `.orchestrator/` directory.
+pub const STATUS_FILE: &str = "status.json";
+
+/// Name of the file in the state dir holding the last valid statuses.
+pub const SAVED_STATUS_FILE: &str = "agent_status.json";
+
+/// Parse a `status.json` update from `domain`'s agent, rejecting one
+/// that names another domain or is `blocked` without `blocked_on`.
+pub fn parse_status(domain: &DomainId, json: &str) -> Result<AgentStatus> {
+    let status: AgentStatus = serde_json::from_str(json)?;
+    if status.domain != domain.as_str() {
+        bail!(
+            "`domain` is `{}` but this is the status file of `{}`",
+            status.domain,
+            domain
+        );
+    }
+    let blocked_on = status.blocked_on.as_deref().map(str::trim);
+    if status.status == AgentState::Blocked && blocked_on.is_none_or(str::is_empty) {
+        bail!("status is `blocked` but `blocked_on` does not name a domain");
+    }
+    Ok(status)
+}
+
+/// Read and validate `domain`'s `status.json`.
+pub fn read_valid_status(domain: &DomainId, path: &Path) -> Result<AgentStatus> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("reading agent status: {}", path.display()))?;
+    parse_status(domain, &json).with_context(|| format!("invalid agent status: {}", path.display()))
+}
+
+/// The latest status read for a domain.
+#[derive(Debug, Clone)]
+pub struct AgentRecord {
+    pub status: AgentStatus,
+    /// The heartbeat timed out and no newer one has arrived since.
+    pub stale: bool,
+}
+
+/// Registry of the latest valid status reported by each domain's agent.
+#[derive(Default)]
+pub struct AgentRegistry {
+    agents: Mutex<HashMap<DomainId, AgentRecord>>,
+    /// Where the statuses are saved, if anywhere.
+    path: Option<PathBuf>,
+}
+
+impl AgentRegistry {
+    /// A registry kept in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the statuses saved at `path` (none if it does not exist),
+    /// saving every status observed from now on back to it.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let statuses = read_saved_statuses(&path)?;
+        let agents = statuses
+            .into_iter()
+            .map(|(domain, status)| {
+                let record = AgentRecord {
+                    status,
+                    stale: false,
+                };
+                (DomainId::new(domain), record)
+            })
+            .collect();
+        Ok(Self {
+            agents: Mutex::new(agents),
+            path: Some(path),
+        })
+    }
+
+    /// Record a newly read status for `domain`, returning the previous
+    /// record. A stale agent stays stale unless the heartbeat advanced.
+    pub fn observe(&self, domain: &DomainId, status: AgentStatus) -> Result<Option<AgentRecord>> {
+        let mut agents = self.agents.lock().expect("agent registry lock poisoned");
+        let stale = agents.get(domain).is_some_and(|previous| {
+            previous.stale && status.last_heartbeat <= previous.status.last_heartbeat
+        });
+        let previous = agents.insert(domain.clone(), AgentRecord { status, stale });
+        if let Some(path) = &self.path {
+            let statuses: BTreeMap<&str, &AgentStatus> = agents
+                .iter()
+                .map(|(domain, record)| (domain.as_str(), &record.status))
+                .collect();
+            write_atomic(path, serde_json::to_string_pretty(&statuses)?.as_bytes())?;
+        }
+        Ok(previous)
+    }
+
+    /// The latest record for `domain`, if its status has been read.
+    pub fn get(&self, domain: &DomainId) -> Option<AgentRecord> {
+        let agents = self.agents.lock().expect("agent registry lock poisoned");
+        agents.get(domain).cloned()
+    }
+
+    /// Mark every agent whose heartbeat is older than `timeout` as
+    /// stale, returning the ones that were not already.
+    pub fn mark_stale(
+        &self,
+        now: DateTime<Utc>,
+        timeout: chrono::Duration,
+    ) -> Vec<(DomainId, AgentStatus)> {
+        let mut agents = self.agents.lock().expect("agent registry lock poisoned");
+        let mut newly_stale: Vec<_> = agents
+            .iter_mut()
+            .filter(|(_, record)| {
+                !record.stale
+                    && record.status.status != AgentState::Complete
+                    && now - record.status.last_heartbeat > timeout
+            })
+            .map(|(domain, record)| {
+                record.stale = true;
+                (domain.clone(), record.status.clone())
+            })
+            .collect();
+        newly_stale.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        newly_stale
+    }
+
+    /// Domains whose agents report being idle, leaving out stale ones.
+    pub fn idle(&self) -> Vec<DomainId> {
+        let agents = self.agents.lock().expect("agent registry lock poisoned");
+        let mut idle: Vec<DomainId> = agents
+            .iter()
+            .filter(|(_, record)| !record.stale && record.status.status == AgentState::Idle)
+            .map(|(domain, _)| domain.clone())
+            .collect();
+        idle.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        idle
+    }
+
+    /// Domains whose agents report being blocked on `domain`.
+    pub fn blocked_on(&self, domain: &DomainId) -> Vec<DomainId> {
+        let agents = self.agents.lock().expect("agent registry lock poisoned");
+        let mut blocked: Vec<DomainId> = agents
+            .iter()
+            .filter(|(peer, record)| {
+                *peer != domain
+                    && record.status.status == AgentState::Blocked
+                    && record.status.blocked_on.as_deref() == Some(domain.as_str())
+            })
+            .map(|(peer, _)| peer.clone())
+            .collect();
+        blocked.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        blocked
+    }
+}
+
+/// Delivers a notice to an agent's inbox, under a file name stem.
+pub type Notify<'a> = &'a dyn Fn(&Message, &str) -> Result<()>;
+
+/// Records status updates and checks heartbeats, logging the
+/// transitions and notifying the agents concerned.
+pub struct StatusMonitor {
+    agents: Arc<AgentRegistry>,
+    event_log: Arc<dyn EventLog>,
+    /// The last `status.json` error each domain was sent a notice for,
+    /// cleared by a valid update.
+    errors: Mutex<HashMap<DomainId, String>>,
+}
+
+impl StatusMonitor {
+    pub fn new(agents: Arc<AgentRegistry>, event_log: Arc<dyn EventLog>) -> Self {
+        Self {
+            agents,
+            event_log,
+            errors: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record `json`, read from `domain`'s `status.json`, logging an
+    /// `agent_state_changed` event when the state changed and an
+    /// `agent_recovered` event when a stale agent's heartbeat resumed.
+    ///
+    /// An invalid update is logged as `status_invalid` and answered with
+    /// a notice; the last valid status is kept. Returns the new state if
+    /// the state changed.
+    pub fn record(
+        &self,
+        domain: &DomainId,
+        json: &str,
+        notify: Notify,
+    ) -> Result<Option<AgentState>> {
+        let status = match parse_status(domain, json) {
+            Ok(status) => status,
+            Err(e) => return self.reject(domain, &e, notify).map(|()| None),
+        };
+        self.errors
+            .lock()
+            .expect("status error lock poisoned")
+            .remove(domain);
+        let (state, current_task, last_heartbeat) = (
+            status.status.clone(),
+            status.current_task.clone(),
+            status.last_heartbeat,
+        );
+
+        let previous = self.agents.observe(domain, status)?;
+        let previous_state = previous.as_ref().map(|p| p.status.status.clone());
+        let changed = previous_state.as_ref() != Some(&state);
+        if changed {
+            tracing::info!(
+                domain = %domain,
+                previous = ?previous_state,
+                state = ?state,
+                "agent state changed"
+            );
+            self.log(EventKind::AgentStateChanged(AgentStateChanged {
+                domain: domain.to_string(),
+                previous: previous_state,
+                state: state.clone(),
+                current_task,
+            }));
+        }
+
+        let recovered =
+            previous.is_some_and(|p| p.stale) && self.agents.get(domain).is_some_and(|r| !r.stale);
+        if recovered {
+            tracing::info!(domain = %domain, "agent heartbeat resumed");
+            self.log(EventKind::AgentRecovered(AgentRecovered {
+                domain: domain.to_string(),
+                state: state.clone(),
+                last_heartbeat,
+            }));
+        }
+        Ok(changed.then_some(state))
+    }
+
+    /// Log an invalid `status.json` update and tell the agent why it was
+    /// rejected, unless it was already told about the same error.
+    fn reject(&self, domain: &DomainId, error: &anyhow::Error, notify: Notify) -> Result<()> {
+        let error = format!("{:#}", error);
+        tracing::warn!(domain = %domain, error = %error, "rejected agent status update");
+        self.log(EventKind::StatusInvalid(StatusInvalid {
+            domain: domain.to_string(),
+            error: error.clone(),
+        }));
+
+        let repeated = self
+            .errors
+            .lock()
+            .expect("status error lock poisoned")
+            .get(domain)
+            .is_some_and(|last| *last == error);
+        if repeated {
+            return Ok(());
+        }
+
+        let kept = match self.agents.get(domain) {
+            Some(record) => format!(
+                "Your last valid status (`{}`, heartbeat {}) is kept until you write a valid one.",
+                record.status.status,
+                record.status.last_heartbeat.to_rfc3339()
+            ),
+            None => "No valid status has been recorded for you yet.".to_string(),
+        };
+        let notice = Message {
+            from: domain.clone(),
+            to: domain.clone(),
+            msg_type: "status_invalid".to_string(),
+            task: String::new(),
+            priority: "high".to_string(),
+            artifacts: Vec::new(),
+            body: format!(
+                "Your `.orchestrator/{}` update was rejected: {}\n\n\
+                 `status` must be one of `idle`, `working`, `blocked` or `complete`, \
+                 `domain` must be `{}`, and a `blocked` status must name the domain it \
+                 is blocked on in `blocked_on`. {}",
+                STATUS_FILE, error, domain, kept
+            ),
+        };
+        notify(&notice, "status_invalid")?;
+        self.errors
+            .lock()
+            .expect("status error lock poisoned")
+            .insert(domain.clone(), error);
+        Ok(())
+    }
+
+    /// Mark agents whose heartbeat is older than `timeout` as stale,
+    /// log an `agent_stale` event for each, and notify the peers
+    /// blocked on them. Returns the newly stale domains.
+    pub fn check_heartbeats(
+        &self,
+        now: DateTime<Utc>,
+        timeout: chrono::Duration,
+        notify: Notify,
+    ) -> Result<Vec<DomainId>> {
+        let mut stale = Vec::new();
+        for (domain, status) in self.agents.mark_stale(now, timeout) {
+            let blocked = self.agents.blocked_on(&domain);
+            tracing::warn!(
+                domain = %domain,
+                last_heartbeat = %status.last_heartbeat,
+                blocked_peers = blocked.len(),
+                "agent heartbeat timed out"
+            );
+
+            let event = Event::at(
+                now,
+                EventKind::AgentStale(AgentStale {
+                    domain: domain.to_string(),
+                    state: status.status.clone(),
+                    last_heartbeat: status.last_heartbeat,
+                    timeout_secs: timeout.num_seconds().max(0) as u64,
+                    notified: blocked.iter().map(DomainId::to_string).collect(),
+                }),
+            );
+            if let Err(e) = self.event_log.log(&event) {
+                tracing::error!(error = %e, "failed to log agent stale event");
+            }
+
+            for peer in blocked {
+                let notice = Message {
+                    from: domain.clone(),
+                    to: peer,
+                    msg_type: "agent_stale".to_string(),
+                    task: status.current_task.clone().unwrap_or_default(),
+                    priority: "high".to_string(),
+                    artifacts: Vec::new(),
+                    body: format!(
+                        "Domain `{}`, which you are blocked on, has not updated its heartbeat \
+                         since {} and may have stopped. Consider working on something else \
+                         or asking for help until it resumes.",
+                        domain,
+                        status.last_heartbeat.to_rfc3339()
+                    ),
+                };
+                notify(&notice, &format!("agent_stale-{}", domain))?;
+            }
+            stale.push(domain);
+        }
+        Ok(stale)
+    }
+
+    fn log(&self, kind: EventKind) {
+        let name = kind.name();
+        if let Err(e) = self.event_log.log(&Event::new(kind)) {
+            tracing::error!(error = %e, kind = name, "failed to log agent status event");
+        }
+    }
+}
+
+/// Read the statuses saved by [`AgentRegistry::load`], keyed by domain.
+pub fn read_saved_statuses(path: &Path) -> Result<BTreeMap<String, AgentStatus>> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json)
+            .with_context(|| format!("parsing saved agent statuses: {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    use crate::event::{EventQuery, FileEventLog};
+
+    struct Fixture {
+        dir: PathBuf,
+        agents: Arc<AgentRegistry>,
+        event_log: Arc<FileEventLog>,
+        monitor: StatusMonitor,
+        /// `(to, stem)` of every notice delivered.
+        notices: RefCell<Vec<(String, String)>>,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "comm-node-heartbeat-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            let agents = Arc::new(AgentRegistry::new());
+            let event_log = Arc::new(FileEventLog::new(dir.join("event.log")));
+            let monitor = StatusMonitor::new(agents.clone(), event_log.clone());
+            Self {
+                dir,
+                agents,
+                event_log,
+                monitor,
+                notices: RefCell::new(Vec::new()),
+            }
+        }
+
+        fn record(&self, domain: &str, json: &str) -> Option<AgentState> {
+            self.monitor
+                .record(&DomainId::new(domain), json, &|notice, stem| {
+                    self.notices
+                        .borrow_mut()
+                        .push((notice.to.to_string(), stem.to_string()));
+                    Ok(())
+                })
+                .unwrap()
+        }
+
+        fn check(&self, now: DateTime<Utc>) -> Vec<DomainId> {
+            self.monitor
+                .check_heartbeats(now, chrono::Duration::seconds(60), &|notice, stem| {
+                    self.notices
+                        .borrow_mut()
+                        .push((notice.to.to_string(), stem.to_string()));
+                    Ok(())
+                })
+                .unwrap()
+        }
+
+        fn events(&self, kind: &str) -> Vec<serde_json::Value> {
+            let query = EventQuery {
+                kinds: [kind.to_string()].into(),
+                ..Default::default()
+            };
+            self.event_log
+                .query(&query)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.event.payload)
+                .collect()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// A `status.json` update; a `blocked` agent is blocked on `a`.
+    fn status(domain: &str, state: &str, heartbeat: DateTime<Utc>) -> String {
+        serde_json::json!({
+            "domain": domain,
+            "status": state,
+            "current_task": "task-1",
+            "last_heartbeat": heartbeat,
+            "artifacts_produced": [],
+            "blocked_on": if state == "blocked" { Some("a") } else { None },
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn invalid_status_keeps_the_last_valid_one() {
+        let fx = Fixture::new("invalid");
+        let now = Utc::now();
+        assert_eq!(
+            fx.record("a", &status("a", "working", now)),
+            Some(AgentState::Working)
+        );
+
+        assert_eq!(fx.record("a", r#"{"domain": "a", "status": "busy"}"#), None);
+        assert_eq!(fx.record("a", &status("b", "idle", now)), None);
+        let record = fx.agents.get(&DomainId::new("a")).unwrap();
+        assert_eq!(record.status.status, AgentState::Working);
+        assert_eq!(record.status.last_heartbeat, now);
+
+        let invalid = fx.events("status_invalid");
+        assert_eq!(invalid.len(), 2);
+        assert!(invalid[1]["error"]
+            .as_str()
+            .unwrap()
+            .contains("`domain` is `b`"));
+        assert_eq!(fx.events("agent_state_changed").len(), 1);
+    }
+
+    #[test]
+    fn repeated_error_sends_one_notice() {
+        let fx = Fixture::new("repeated");
+        let notice = ("a".to_string(), "status_invalid".to_string());
+        for _ in 0..3 {
+            fx.record("a", r#"{"domain": "a", "status": "busy"}"#);
+        }
+        assert_eq!(*fx.notices.borrow(), vec![notice.clone()]);
+        assert_eq!(fx.events("status_invalid").len(), 3);
+
+        // A different error is reported again.
+        fx.record("a", "not json");
+        assert_eq!(fx.notices.borrow().len(), 2);
+
+        // A valid update clears the error, so it is reported if it recurs.
+        fx.record("a", &status("a", "idle", Utc::now()));
+        fx.record("a", "not json");
+        assert_eq!(fx.notices.borrow().len(), 3);
+        assert_eq!(fx.notices.borrow()[2], notice);
+    }
+
+    #[test]
+    fn reports_a_stale_agent_once() {
+        let fx = Fixture::new("stale");
+        let now = Utc::now();
+        let old = now - chrono::Duration::seconds(120);
+        fx.record("a", &status("a", "working", old));
+        fx.record("b", &status("b", "working", now));
+        fx.record("c", &status("c", "blocked", now));
+
+        assert_eq!(fx.check(now), vec![DomainId::new("a")]);
+        assert_eq!(fx.check(now + chrono::Duration::seconds(30)), vec![]);
+        assert!(fx.agents.get(&DomainId::new("a")).unwrap().stale);
+
+        let stale = fx.events("agent_stale");
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0]["domain"], "a");
+        assert_eq!(stale[0]["notified"], serde_json::json!(["c"]));
+        assert_eq!(
+            *fx.notices.borrow(),
+            vec![("c".to_string(), "agent_stale-a".to_string())]
+        );
+    }
+
+    #[test]
+    fn logs_agent_recovered_when_the_heartbeat_resumes() {
+        let fx = Fixture::new("recovered");
+        let now = Utc::now();
+        fx.record(
+            "a",
+            &status("a", "working", now - chrono::Duration::seconds(120)),
+        );
+        assert_eq!(fx.check(now), vec![DomainId::new("a")]);
+        assert!(fx.events("agent_recovered").is_empty());
+
+        // The same state with a newer heartbeat is not a state change.
+        assert_eq!(fx.record("a", &status("a", "working", now)), None);
+        assert!(!fx.agents.get(&DomainId::new("a")).unwrap().stale);
+        let recovered = fx.events("agent_recovered");
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0]["domain"], "a");
+        assert_eq!(recovered[0]["state"], "working");
+
+        fx.record(
+            "a",
+            &status("a", "working", now + chrono::Duration::seconds(5)),
+        );
+        assert_eq!(fx.events("agent_recovered").len(), 1);
+    }
+}
@@ -1,11 +1,25 @@
 //! comm-node: FTL coordination for parallel AI agents.
 
 pub mod artifact;
+pub mod bundle;
+pub mod cas;
 pub mod config;
+pub mod contract;
+pub mod dispatch;
 pub mod event;
+pub mod event_kind;
+pub mod frontmatter;
+pub mod git_store;
+pub mod heartbeat;
 pub mod lock;
+pub mod manifest;
 pub mod orchestrator;
+pub mod replay;
 pub mod router;
 pub mod scaffold;
+pub mod sqlite;
+pub mod staleness;
+pub mod subscription;
+pub mod supervisor;
 pub mod types;
 pub mod watcher;
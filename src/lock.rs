@@ -1,15 +1,26 @@
 //! Advisory file lock manager.
 //!
 //! In-memory HashMap tracking which files are locked by which domain.
-//! Snapshots to disk every 30s for crash recovery.
+//! Snapshots to a `LockStore` every 30s for crash recovery: a JSON file
+//! by default, or the SQLite state database. With an event log attached,
+//! every acquire and release is also logged, so the locks can be rebuilt
+//! by replaying the log (see `replay`). Domains refused a lock are
+//! remembered as waiting for it, so that when a holder's agent dies its
+//! locks can be handed on (see `LockManager::reclaim`).
 
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::artifact::write_atomic;
+use crate::config::{ProjectConfig, StateBackend};
+use crate::event::{Event, EventLog};
+use crate::event_kind::{EventKind, LockAcquired, LockExpired, LockReleased};
+use crate::sqlite::SqliteLockStore;
 use crate::types::DomainId;
 
 /// A single advisory file lock.
@@ -20,42 +31,182 @@ pub struct FileLock {
     pub acquired_at: DateTime<Utc>,
 }
 
+/// What became of a lock taken from its holder by `LockManager::reclaim`.
+#[derive(Debug, Clone)]
+pub struct Reclaimed {
+    pub path: PathBuf,
+    /// The longest-waiting domain, which now holds the lock.
+    pub reassigned_to: Option<DomainId>,
+    /// Domains still waiting for the lock.
+    pub waiting: Vec<DomainId>,
+}
+
+/// Persistence backend for lock snapshots.
+pub trait LockStore: Send + Sync {
+    /// Replace the stored snapshot with `locks`.
+    fn save(&self, locks: &[&FileLock]) -> Result<()>;
+
+    /// The most recently saved locks.
+    fn load(&self) -> Result<Vec<FileLock>>;
+
+    /// When the snapshot was last saved, or `None` if there is none.
+    fn saved_at(&self) -> Result<Option<DateTime<Utc>>>;
+}
+
+/// Open the lock store selected by `[locks] backend`.
+pub fn open_store(config: &ProjectConfig, state_dir: &Path) -> Result<Arc<dyn LockStore>> {
+    Ok(match config.locks.backend {
+        StateBackend::File => Arc::new(FileLockStore::new(state_dir.join("locks.json"))),
+        StateBackend::Sqlite => Arc::new(SqliteLockStore::open(&state_dir.join("comm-node.db"))?),
+    })
+}
+
+/// Lock snapshots in a JSON file.
+pub struct FileLockStore {
+    path: PathBuf,
+}
+
+/// A `FileLockStore` snapshot as written.
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    saved_at: DateTime<Utc>,
+    locks: &'a [&'a FileLock],
+}
+
+/// A `FileLockStore` snapshot as read back.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Snapshot {
+    Saved {
+        saved_at: DateTime<Utc>,
+        locks: Vec<FileLock>,
+    },
+    /// Snapshots used to be a bare array, dated by the file's mtime.
+    Legacy(Vec<FileLock>),
+}
+
+impl FileLockStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The stored snapshot and when it was saved, or `None` if there is none.
+    fn read(&self) -> Result<Option<(DateTime<Utc>, Vec<FileLock>)>> {
+        let json = match std::fs::read_to_string(&self.path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let snapshot = serde_json::from_str(&json)
+            .with_context(|| format!("parsing lock snapshot: {}", self.path.display()))?;
+        Ok(Some(match snapshot {
+            Snapshot::Saved { saved_at, locks } => (saved_at, locks),
+            Snapshot::Legacy(locks) => (std::fs::metadata(&self.path)?.modified()?.into(), locks),
+        }))
+    }
+}
+
+impl LockStore for FileLockStore {
+    fn save(&self, locks: &[&FileLock]) -> Result<()> {
+        let snapshot = SnapshotRef {
+            saved_at: Utc::now(),
+            locks,
+        };
+        write_atomic(
+            &self.path,
+            serde_json::to_string_pretty(&snapshot)?.as_bytes(),
+        )
+    }
+
+    fn load(&self) -> Result<Vec<FileLock>> {
+        let (_, locks) = self
+            .read()?
+            .with_context(|| format!("no lock snapshot at {}", self.path.display()))?;
+        Ok(locks)
+    }
+
+    fn saved_at(&self) -> Result<Option<DateTime<Utc>>> {
+        Ok(self.read()?.map(|(saved_at, _)| saved_at))
+    }
+}
+
 /// Manages advisory file locks across domains.
 pub struct LockManager {
     locks: HashMap<PathBuf, FileLock>,
+    /// Domains refused each lock, in the order they asked.
+    waiters: HashMap<PathBuf, Vec<DomainId>>,
+    /// Where acquires and releases are logged, if anywhere.
+    event_log: Option<Arc<dyn EventLog>>,
 }
 
 impl LockManager {
     pub fn new() -> Self {
+        Self::from_locks(Vec::new())
+    }
+
+    /// A manager holding `locks`.
+    pub fn from_locks(locks: impl IntoIterator<Item = FileLock>) -> Self {
         Self {
-            locks: HashMap::new(),
+            locks: locks.into_iter().map(|l| (l.path.clone(), l)).collect(),
+            waiters: HashMap::new(),
+            event_log: None,
         }
     }
 
+    /// Log `lock_acquired`, `lock_released` and `lock_expired` events
+    /// to `event_log`.
+    pub fn with_event_log(mut self, event_log: Arc<dyn EventLog>) -> Self {
+        self.event_log = Some(event_log);
+        self
+    }
+
     /// Acquire an advisory lock on a file path for a domain.
-    /// Returns `Err` if the file is already locked by another domain.
+    /// Returns `Err` if the file is already locked by another domain,
+    /// and records the domain as waiting for it.
     pub fn acquire(&mut self, path: PathBuf, holder: DomainId) -> Result<()> {
         if let Some(existing) = self.locks.get(&path) {
             if existing.holder != holder {
-                anyhow::bail!(
+                let error = anyhow::anyhow!(
                     "file {} already locked by {}",
                     path.display(),
                     existing.holder
                 );
+                let waiting = self.waiters.entry(path).or_default();
+                if !waiting.contains(&holder) {
+                    waiting.push(holder);
+                }
+                return Err(error);
             }
             return Ok(());
         }
 
+        if let Some(waiting) = self.waiters.get_mut(&path) {
+            waiting.retain(|w| w != &holder);
+            if waiting.is_empty() {
+                self.waiters.remove(&path);
+            }
+        }
+        self.grant(path, holder);
+        Ok(())
+    }
+
+    fn grant(&mut self, path: PathBuf, holder: DomainId) {
+        let acquired_at = Utc::now();
+        self.log(
+            acquired_at,
+            EventKind::LockAcquired(LockAcquired {
+                path: path.to_string_lossy().into_owned(),
+                holder: holder.to_string(),
+            }),
+        );
         self.locks.insert(
             path.clone(),
             FileLock {
                 path,
                 holder,
-                acquired_at: Utc::now(),
+                acquired_at,
             },
         );
-
-        Ok(())
     }
 
     /// Release a lock on a file path.
@@ -71,30 +222,111 @@ impl LockManager {
             }
         }
 
-        self.locks.remove(path);
+        if self.locks.remove(path).is_some() {
+            self.log(
+                Utc::now(),
+                EventKind::LockReleased(LockReleased {
+                    path: path.to_string_lossy().into_owned(),
+                    holder: holder.to_string(),
+                }),
+            );
+        }
         Ok(())
     }
 
     /// Snapshot current locks to a JSON file for crash recovery.
     pub fn snapshot(&self, path: &Path) -> Result<()> {
-        let locks: Vec<&FileLock> = self.locks.values().collect();
-        let json = serde_json::to_string_pretty(&locks)?;
-        std::fs::write(path, json)?;
-        Ok(())
+        self.persist(&FileLockStore::new(path.to_path_buf()))
     }
 
     /// Restore locks from a snapshot file.
     pub fn restore(path: &Path) -> Result<Self> {
-        let json = std::fs::read_to_string(path)?;
-        let locks: Vec<FileLock> = serde_json::from_str(&json)?;
-        let map = locks.into_iter().map(|l| (l.path.clone(), l)).collect();
-        Ok(Self { locks: map })
+        Self::load(&FileLockStore::new(path.to_path_buf()))
+    }
+
+    /// Snapshot current locks to `store`.
+    pub fn persist(&self, store: &dyn LockStore) -> Result<()> {
+        store.save(&self.list())
+    }
+
+    /// Restore locks from the snapshot in `store`.
+    pub fn load(store: &dyn LockStore) -> Result<Self> {
+        Ok(Self::from_locks(store.load()?))
     }
 
     /// List all currently held locks.
     pub fn list(&self) -> Vec<&FileLock> {
         self.locks.values().collect()
     }
+
+    /// Locks held by `holder`, ordered by path.
+    pub fn held_by(&self, holder: &DomainId) -> Vec<&FileLock> {
+        let mut held: Vec<&FileLock> = self
+            .locks
+            .values()
+            .filter(|l| &l.holder == holder)
+            .collect();
+        held.sort_by(|a, b| a.path.cmp(&b.path));
+        held
+    }
+
+    /// Domains waiting for the lock on `path`, longest-waiting first.
+    pub fn waiters(&self, path: &Path) -> &[DomainId] {
+        self.waiters.get(path).map_or(&[], Vec::as_slice)
+    }
+
+    /// Take every lock held by `holder` away from it, handing each to
+    /// the domain that has waited longest for it, if any. Each lock is
+    /// logged as `lock_expired` with `reason`, and a handover as
+    /// `lock_acquired`.
+    pub fn reclaim(&mut self, holder: &DomainId, reason: &str) -> Vec<Reclaimed> {
+        let paths: Vec<PathBuf> = self
+            .held_by(holder)
+            .into_iter()
+            .map(|l| l.path.clone())
+            .collect();
+
+        let mut reclaimed = Vec::with_capacity(paths.len());
+        for path in paths {
+            let Some(lock) = self.locks.remove(&path) else {
+                continue;
+            };
+            self.log(
+                Utc::now(),
+                EventKind::LockExpired(LockExpired {
+                    path: path.to_string_lossy().into_owned(),
+                    holder: holder.to_string(),
+                    acquired_at: lock.acquired_at,
+                    reason: reason.to_string(),
+                }),
+            );
+
+            let mut waiting = self.waiters.remove(&path).unwrap_or_default();
+            waiting.retain(|w| w != holder);
+            let reassigned_to = (!waiting.is_empty()).then(|| waiting.remove(0));
+            if let Some(next) = &reassigned_to {
+                self.grant(path.clone(), next.clone());
+            }
+            if !waiting.is_empty() {
+                self.waiters.insert(path.clone(), waiting.clone());
+            }
+            reclaimed.push(Reclaimed {
+                path,
+                reassigned_to,
+                waiting,
+            });
+        }
+        reclaimed
+    }
+
+    fn log(&self, timestamp: DateTime<Utc>, kind: EventKind) {
+        let Some(event_log) = &self.event_log else {
+            return;
+        };
+        if let Err(e) = event_log.log(&Event::at(timestamp, kind)) {
+            tracing::error!(error = %e, "failed to log lock event");
+        }
+    }
 }
 
 impl Default for LockManager {
@@ -3,9 +3,18 @@
 use std::path::PathBuf;
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use chrono::{DateTime, Utc};
+use clap::builder::PossibleValuesParser;
+use clap::{Parser, Subcommand, ValueEnum};
 use tracing_subscriber::EnvFilter;
 
+use comm_node::cas::CasArtifactStore;
+use comm_node::config::{ArtifactBackend, StateBackend};
+use comm_node::event::{EventLog, EventQuery, FileEventLog, LoggedEvent};
+use comm_node::event_kind::EventKind;
+use comm_node::sqlite::SqliteEventLog;
+use comm_node::types::{AgentState, DomainId};
+
 #[derive(Parser)]
 #[command(name = "comm-node", about = "FTL coordination for parallel AI agents")]
 struct Cli {
@@ -29,13 +38,151 @@ enum Command {
         config: PathBuf,
     },
 
+    /// Remove artifact blobs no domain refers to (cas backend only)
+    Gc {
+        /// Path to the project config file
+        #[arg(long, default_value = "comm-node.toml")]
+        config: PathBuf,
+
+        /// Keep unreferenced blobs younger than this many seconds
+        #[arg(long, default_value_t = 600)]
+        grace_secs: u64,
+    },
+
+    /// Import the file event log, including archived segments, into the
+    /// SQLite state database
+    ImportEvents {
+        /// Path to the project config file
+        #[arg(long, default_value = "comm-node.toml")]
+        config: PathBuf,
+    },
+
+    /// Print recent events from the audit trail
+    Events {
+        /// Path to the project config file
+        #[arg(long, default_value = "comm-node.toml")]
+        config: PathBuf,
+
+        /// Only events of this kind (repeatable)
+        #[arg(
+            long = "kind",
+            value_parser = PossibleValuesParser::new(EventKind::NAMES.iter().copied())
+        )]
+        kinds: Vec<String>,
+
+        /// Only events naming this domain (as sender, recipient, holder, ...)
+        #[arg(long)]
+        domain: Option<String>,
+
+        /// Only events for this task
+        #[arg(long)]
+        task: Option<String>,
+
+        /// Only events since this time: RFC 3339, or an age such as `30m`, `2h`, `7d`
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only events whose message body contains these words
+        #[arg(long)]
+        search: Option<String>,
+
+        /// Number of most recent events to print
+        #[arg(long, short = 'n', default_value_t = 20)]
+        limit: usize,
+
+        /// Keep printing new events as they are logged, until ctrl-c
+        #[arg(long, short)]
+        follow: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+
+        #[command(subcommand)]
+        command: Option<EventsCommand>,
+    },
+
+    /// Rebuild orchestrator state from the event log
+    Replay {
+        /// Path to the project config file
+        #[arg(long, default_value = "comm-node.toml")]
+        config: PathBuf,
+
+        /// Replay only events before this time: RFC 3339, or an age such as `30m`
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+    },
+
     /// Show agent states, locks, and metrics
-    Status,
+    Status {
+        /// Path to the project config file
+        #[arg(long, default_value = "comm-node.toml")]
+        config: PathBuf,
+    },
+
+    /// Inspect and manage stored artifacts
+    Artifacts {
+        /// Path to the project config file
+        #[arg(long, default_value = "comm-node.toml")]
+        config: PathBuf,
+
+        #[command(subcommand)]
+        command: ArtifactsCommand,
+    },
 
     /// Graceful shutdown of a running orchestrator
     Stop,
 }
 
+#[derive(Subcommand)]
+enum ArtifactsCommand {
+    /// List stored versions of an artifact (or bundle) in a domain
+    History {
+        /// Domain holding the artifact
+        domain: String,
+        /// Artifact name, or the directory or glob a bundle was routed as
+        name: String,
+    },
+
+    /// Make an earlier version of an artifact current again
+    Rollback {
+        /// Domain holding the artifact
+        domain: String,
+        /// Artifact name
+        name: String,
+        /// Version number or commit to restore (see `artifacts history`)
+        version: String,
+        /// Treat VERSION as a commit hash prefix, even if it is all digits
+        #[arg(long)]
+        commit: bool,
+    },
+
+    /// Check artifacts against their manifest checksums
+    Verify {
+        /// Only verify this domain (default: all domains)
+        domain: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum EventsCommand {
+    /// Check the event hash chain and report the first broken link
+    Verify,
+}
+
+/// How `events` prints each event.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// One aligned line per event
+    Human,
+    /// One JSON object per line
+    Json,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt()
@@ -53,9 +200,7 @@ async fn main() -> Result<()> {
         }
         Command::Start { config } => {
             let project_config = comm_node::config::load(&config)?;
-            let state_dir = dirs::home_dir()
-                .expect("cannot determine home directory")
-                .join(".comm-node/state");
+            let state_dir = state_dir();
             tracing::info!(
                 domains = project_config.domains.len(),
                 state_dir = %state_dir.display(),
@@ -65,8 +210,412 @@ async fn main() -> Result<()> {
                 comm_node::orchestrator::Orchestrator::new(&project_config, state_dir)?;
             orchestrator.run().await?;
         }
-        Command::Status => {
-            println!("comm-node status: not yet implemented");
+        Command::Artifacts { config, command } => {
+            let project_config = comm_node::config::load(&config)?;
+            let store = comm_node::artifact::open_store(&project_config, &state_dir());
+            match command {
+                ArtifactsCommand::History { domain, name } => {
+                    let versions = store.versions(&domain, &name)?;
+                    if versions.is_empty() {
+                        let bundles = store.bundle_versions(&domain, &name)?;
+                        let current = bundles.last().map(|v| v.version);
+                        for v in bundles {
+                            let marker = if Some(v.version) == current { "*" } else { " " };
+                            println!(
+                                "{} v{}  {}  {}  {} members  {} bytes  {}",
+                                marker,
+                                v.version,
+                                v.stored_at.to_rfc3339(),
+                                v.hash.get(..12).unwrap_or(&v.hash),
+                                v.members.len(),
+                                v.size_bytes,
+                                v.producer
+                            );
+                            for (member, version) in &v.members {
+                                println!("      {} v{}", member, version);
+                            }
+                        }
+                    }
+                    let current = versions.last().map(|v| v.version);
+                    for v in versions {
+                        let marker = if Some(v.version) == current { "*" } else { " " };
+                        let rollback = v
+                            .rolled_back_from
+                            .map(|from| format!(" (rollback of v{})", from))
+                            .unwrap_or_default();
+                        let commit = v
+                            .commit
+                            .as_deref()
+                            .map(|c| format!("  commit {}", &c[..c.len().min(12)]))
+                            .unwrap_or_default();
+                        println!(
+                            "{} v{}  {}  {}  {} bytes  {}{}{}",
+                            marker,
+                            v.version,
+                            v.stored_at.to_rfc3339(),
+                            v.hash.get(..12).unwrap_or(&v.hash),
+                            v.size_bytes,
+                            v.producer,
+                            rollback,
+                            commit
+                        );
+                    }
+                }
+                ArtifactsCommand::Rollback {
+                    domain,
+                    name,
+                    version,
+                    commit,
+                } => {
+                    let versions = store.versions(&domain, &name)?;
+                    // A number is a version unless no such version exists,
+                    // in which case it may be an all-digit commit prefix.
+                    let number = version
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|n| !commit && versions.iter().any(|v| v.version == *n));
+                    let version = match number {
+                        Some(number) => number,
+                        None => versions
+                            .iter()
+                            .find(|v| {
+                                v.commit
+                                    .as_deref()
+                                    .is_some_and(|c| c.starts_with(version.as_str()))
+                            })
+                            .map(|v| v.version)
+                            .ok_or_else(|| {
+                                anyhow::anyhow!(
+                                    "no version or commit `{}` for {}/{}",
+                                    version,
+                                    domain,
+                                    name
+                                )
+                            })?,
+                    };
+                    let restored = store.rollback(&domain, &name, version)?;
+                    println!(
+                        "{}/{}: restored v{} as v{}",
+                        domain, name, version, restored.version
+                    );
+                }
+                ArtifactsCommand::Verify { domain } => {
+                    let mut domains: Vec<String> = match domain {
+                        Some(d) => vec![d],
+                        None => project_config
+                            .domains
+                            .keys()
+                            .map(|id| id.as_str().to_owned())
+                            .collect(),
+                    };
+                    domains.sort();
+
+                    let mut problems = 0;
+                    for domain in &domains {
+                        let checked = store.manifest(domain)?.artifacts.len();
+                        let issues = store.verify(domain)?;
+                        for issue in &issues {
+                            println!("{}: {}", domain, issue);
+                        }
+                        println!(
+                            "{}: {} artifacts checked, {} problems",
+                            domain,
+                            checked,
+                            issues.len()
+                        );
+                        problems += issues.len();
+                    }
+
+                    if problems > 0 {
+                        anyhow::bail!("{} artifact integrity problems found", problems);
+                    }
+                }
+            }
+        }
+        Command::Gc { config, grace_secs } => {
+            let project_config = comm_node::config::load(&config)?;
+            if project_config.artifacts.backend != ArtifactBackend::Cas {
+                anyhow::bail!(
+                    "`gc` only applies to the content-addressed (`cas`) artifact backend"
+                );
+            }
+            let store = CasArtifactStore::from_config(&project_config, state_dir());
+            let report = store.gc(std::time::Duration::from_secs(grace_secs))?;
+            println!(
+                "gc: {} live blobs, removed {} ({} bytes), kept {} recent",
+                report.live, report.removed, report.freed_bytes, report.skipped_recent
+            );
+        }
+        Command::ImportEvents { config } => {
+            let project_config = comm_node::config::load(&config)?;
+            let state_dir = state_dir();
+            let source =
+                FileEventLog::from_config(state_dir.join("event.log"), &project_config.events);
+            let events = source.query(&EventQuery::default())?;
+            let db = state_dir.join("comm-node.db");
+            let imported = SqliteEventLog::open(&db)?.import(&events, &source.chain_start()?)?;
+            println!(
+                "imported {} of {} events into {}",
+                imported,
+                events.len(),
+                db.display()
+            );
+            if project_config.events.backend != StateBackend::Sqlite {
+                println!("set `backend = \"sqlite\"` under [events] to log to the database");
+            }
+        }
+        Command::Events {
+            config,
+            kinds,
+            domain,
+            task,
+            since,
+            search,
+            limit,
+            follow,
+            format,
+            command,
+        } => {
+            let project_config = comm_node::config::load(&config)?;
+            let log = comm_node::event::open_log(&project_config, &state_dir())?;
+
+            if let Some(EventsCommand::Verify) = command {
+                let report = log.verify()?;
+                let unchained = if report.unchained > 0 {
+                    format!(" ({} from before chaining not checked)", report.unchained)
+                } else {
+                    String::new()
+                };
+                println!("{} events verified{}", report.verified, unchained);
+                match report.broken {
+                    Some(link) => {
+                        anyhow::bail!("event chain broken at event {}: {}", link.seq, link.reason)
+                    }
+                    None => println!(
+                        "event chain intact through event {}",
+                        report.last_seq.unwrap_or(0)
+                    ),
+                }
+                return Ok(());
+            }
+
+            let filter = EventQuery {
+                kinds: kinds.into_iter().collect(),
+                since: since
+                    .as_deref()
+                    .map(|s| parse_time("--since", s))
+                    .transpose()?,
+                domain,
+                task,
+                text: search,
+                ..EventQuery::default()
+            };
+
+            // Follow from the newest event of any kind, not just the newest
+            // match, taken before listing so that nothing logged meanwhile
+            // is skipped or printed twice.
+            let mut last_seq = log
+                .query(&EventQuery {
+                    limit: Some(1),
+                    reverse: true,
+                    ..EventQuery::default()
+                })?
+                .first()
+                .map_or(0, |e| e.seq);
+            let mut recent = log.query(&EventQuery {
+                limit: Some(limit),
+                reverse: true,
+                ..filter.clone()
+            })?;
+            recent.retain(|e| e.seq <= last_seq);
+            recent.reverse();
+            for logged in &recent {
+                print_event(logged, format)?;
+            }
+
+            if follow {
+                loop {
+                    tokio::select! {
+                        _ = tokio::time::sleep(std::time::Duration::from_millis(500)) => {}
+                        _ = tokio::signal::ctrl_c() => break,
+                    }
+                    let new = log.query(&EventQuery {
+                        after_seq: Some(last_seq),
+                        ..filter.clone()
+                    })?;
+                    for logged in &new {
+                        print_event(logged, format)?;
+                        last_seq = last_seq.max(logged.seq);
+                    }
+                }
+            }
+        }
+        Command::Replay {
+            config,
+            until,
+            format,
+        } => {
+            let project_config = comm_node::config::load(&config)?;
+            let log = comm_node::event::open_log(&project_config, &state_dir())?;
+            let until = until
+                .as_deref()
+                .map(|u| parse_time("--until", u))
+                .transpose()?;
+            let state = comm_node::replay::replay(&*log, until)?;
+
+            if format == OutputFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&state)?);
+                return Ok(());
+            }
+
+            match state.last_seq {
+                Some(seq) => println!("state after event {}", seq),
+                None => println!("no events to replay"),
+            }
+            if state.skipped > 0 {
+                println!("({} events of unknown kinds skipped)", state.skipped);
+            }
+
+            println!("\nlocks:");
+            if state.locks.is_empty() {
+                println!("  none");
+            }
+            for lock in state.locks.values() {
+                println!(
+                    "  {}  held by {} since {}",
+                    lock.path.display(),
+                    lock.holder,
+                    lock.acquired_at
+                        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+                );
+            }
+
+            println!("\nopen questions:");
+            if state.open_questions.is_empty() {
+                println!("  none");
+            }
+            for question in &state.open_questions {
+                println!(
+                    "  {} -> {}  task={}  asked {}",
+                    question.from,
+                    question.to,
+                    question.task,
+                    question
+                        .asked_at
+                        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+                );
+            }
+
+            println!("\ndeliveries:");
+            if state.deliveries.is_empty() {
+                println!("  none");
+            }
+            for (domain, counters) in &state.deliveries {
+                println!(
+                    "  {}: sent={} received={} rejected={} artifacts_received={}",
+                    domain,
+                    counters.sent,
+                    counters.received,
+                    counters.rejected,
+                    counters.artifacts_received
+                );
+            }
+        }
+        Command::Status { config } => {
+            let project_config = comm_node::config::load(&config)?;
+            let state_dir = state_dir();
+            let store = comm_node::artifact::open_store(&project_config, &state_dir);
+            let acks = comm_node::staleness::AckRegistry::load(state_dir.join("acks.json"))?;
+            let saved_statuses = comm_node::heartbeat::read_saved_statuses(
+                &state_dir.join(comm_node::heartbeat::SAVED_STATUS_FILE),
+            )?;
+            let mut domains: Vec<&str> = project_config
+                .domains
+                .keys()
+                .map(|id| id.as_str())
+                .collect();
+            domains.sort();
+
+            let heartbeat_timeout = project_config
+                .agents
+                .heartbeat_timeout_secs
+                .map(|secs| chrono::Duration::seconds(secs as i64));
+            let now = chrono::Utc::now();
+            for &domain in &domains {
+                let path = project_config.domains[&DomainId::new(domain)]
+                    .path
+                    .join(".orchestrator")
+                    .join(comm_node::heartbeat::STATUS_FILE);
+                // Fall back to the last valid status the orchestrator saw.
+                let (status, rejected) =
+                    match comm_node::heartbeat::read_valid_status(&DomainId::new(domain), &path) {
+                        Ok(status) => (status, String::new()),
+                        Err(e) => match saved_statuses.get(domain) {
+                            Some(saved) => (saved.clone(), format!("  [rejected update: {:#}]", e)),
+                            None => {
+                                println!("{}: status unavailable ({:#})", domain, e);
+                                continue;
+                            }
+                        },
+                    };
+                let task = status
+                    .current_task
+                    .as_deref()
+                    .map_or(String::new(), |t| format!(" on {}", t));
+                let blocked = status
+                    .blocked_on
+                    .as_deref()
+                    .map_or(String::new(), |b| format!(" (blocked on {})", b));
+                let age = now - status.last_heartbeat;
+                let stale = if status.status != AgentState::Complete
+                    && heartbeat_timeout.is_some_and(|t| age > t)
+                {
+                    "  [stale]"
+                } else {
+                    ""
+                };
+                println!(
+                    "{}: {}{}{}, heartbeat {}s ago{}{}",
+                    domain,
+                    status.status,
+                    task,
+                    blocked,
+                    age.num_seconds(),
+                    stale,
+                    rejected
+                );
+            }
+
+            let lags = comm_node::staleness::lags(&*store, &acks, domains)?;
+            if lags.is_empty() {
+                println!("artifacts: all current versions acknowledged");
+            }
+            let window = project_config
+                .artifacts
+                .stale_after_secs
+                .map(|secs| chrono::Duration::seconds(secs as i64));
+            for lag in lags {
+                let acknowledged = lag
+                    .acknowledged
+                    .map_or("none".to_string(), |v| format!("v{}", v));
+                let stale = if window.is_some_and(|w| lag.is_stale(now, w)) {
+                    "  [stale]"
+                } else {
+                    ""
+                };
+                println!(
+                    "{} is {} version(s) behind on {} from {} (producer at v{}, received v{}, acknowledged {}, waiting since {}){}",
+                    lag.domain,
+                    lag.behind,
+                    lag.artifact,
+                    lag.producer,
+                    lag.latest,
+                    lag.received,
+                    acknowledged,
+                    lag.waiting_since.to_rfc3339(),
+                    stale
+                );
+            }
         }
         Command::Stop => {
             println!("comm-node stop: not yet implemented");
@@ -75,3 +624,70 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Parse a time given to `flag`: an RFC 3339 time, or an age in
+/// seconds, minutes, hours, or days (`90s`, `30m`, `2h`, `7d`).
+fn parse_time(flag: &str, value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let split = value.len() - value.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| {
+        anyhow::anyhow!(
+            "invalid {} `{}`: expected a time or an age like `2h`",
+            flag,
+            value
+        )
+    })?;
+    let age = match unit {
+        "s" => chrono::Duration::try_seconds(amount),
+        "m" => chrono::Duration::try_minutes(amount),
+        "h" => chrono::Duration::try_hours(amount),
+        "d" => chrono::Duration::try_days(amount),
+        _ => anyhow::bail!("invalid {} `{}`: unit must be s, m, h, or d", flag, value),
+    };
+    age.and_then(|age| Utc::now().checked_sub_signed(age))
+        .ok_or_else(|| anyhow::anyhow!("invalid {} `{}`: age is out of range", flag, value))
+}
+
+/// Print one event in the chosen format.
+fn print_event(logged: &LoggedEvent, format: OutputFormat) -> Result<()> {
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string(logged)?);
+        return Ok(());
+    }
+
+    // Message bodies are left to the JSON output.
+    let fields: Vec<String> = match logged.event.payload.as_object() {
+        Some(payload) => payload
+            .iter()
+            .filter(|(key, _)| key.as_str() != "body")
+            .filter_map(|(key, value)| match value {
+                serde_json::Value::Null => None,
+                serde_json::Value::String(s) if s.is_empty() => None,
+                serde_json::Value::String(s) => Some(format!("{}={}", key, s)),
+                other => Some(format!("{}={}", key, other)),
+            })
+            .collect(),
+        None => vec![logged.event.payload.to_string()],
+    };
+    println!(
+        "{} {:>6}  {:<22} {}",
+        logged
+            .event
+            .timestamp
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        logged.seq,
+        logged.event.kind,
+        fields.join(" ")
+    );
+    Ok(())
+}
+
+/// Orchestrator state directory (`~/.comm-node/state`).
+fn state_dir() -> PathBuf {
+    dirs::home_dir()
+        .expect("cannot determine home directory")
+        .join(".comm-node/state")
+}
@@ -0,0 +1,132 @@
+//! Per-domain artifact manifest.
+//!
+//! `.orchestrator/manifest.json` records, for every artifact the
+//! comm-node has delivered into a domain, its checksum, size, content
+//! type, and provenance. The artifact store uses it to detect files
+//! that were edited or deleted behind its back.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Where an artifact came from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Domain that produced the artifact.
+    pub producer: String,
+    /// Task the artifact was produced for, if known.
+    #[serde(default)]
+    pub task: String,
+}
+
+impl Provenance {
+    pub fn new(producer: impl Into<String>, task: impl Into<String>) -> Self {
+        Self {
+            producer: producer.into(),
+            task: task.into(),
+        }
+    }
+}
+
+/// Manifest record for one artifact.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Hex-encoded SHA-256 of the current content.
+    pub sha256: String,
+    pub size_bytes: u64,
+    pub content_type: String,
+    /// Domain that produced the artifact.
+    pub producer: String,
+    /// Task the artifact was routed for.
+    #[serde(default)]
+    pub task: String,
+    pub routed_at: DateTime<Utc>,
+    /// Current version number in the artifact history.
+    pub version: u32,
+}
+
+/// All manifest records for a domain, keyed by artifact name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub artifacts: BTreeMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    /// Load a manifest, treating a missing file as empty.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write the manifest via a temp file and rename.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// A discrepancy between the manifest and the files on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "problem", rename_all = "snake_case")]
+pub enum IntegrityIssue {
+    /// The manifest lists the artifact but the file is gone.
+    Missing { name: String },
+    /// The file's content no longer matches the recorded checksum.
+    Tampered {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl std::fmt::Display for IntegrityIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing { name } => write!(f, "artifact `{}` is missing", name),
+            Self::Tampered {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "artifact `{}` was modified (expected sha256 {}, found {})",
+                name, expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IntegrityIssue {}
+
+/// Guess a MIME content type from an artifact name's extension.
+pub fn content_type_for(name: &str) -> &'static str {
+    let ext = Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match ext.as_deref() {
+        Some("json") => "application/json",
+        Some("yaml" | "yml") => "application/yaml",
+        Some("toml") => "application/toml",
+        Some("md") => "text/markdown",
+        Some("txt") => "text/plain",
+        Some("html") => "text/html",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("ts") => "text/typescript",
+        Some("proto") => "text/x-protobuf",
+        Some("graphql" | "gql") => "application/graphql",
+        Some("sql") => "application/sql",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        _ => "application/octet-stream",
+    }
+}
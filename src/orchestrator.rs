@@ -1,28 +1,66 @@
 //! Async event loop wiring the filesystem watcher to the message router.
 //!
 //! The orchestrator owns the runtime lifecycle: it watches all outbox
-//! directories, routes messages through the router, and handles
-//! graceful shutdown on ctrl-c.
+//! directories, routes messages through the router, pushes updated
+//! artifacts to subscribers, flags unacknowledged artifact updates,
+//! tracks agent status and heartbeats, assigns ready tasks to idle
+//! agents, supervises the agent processes it is configured to launch,
+//! and handles graceful shutdown on ctrl-c. Advisory locks are
+//! restored on startup (their snapshot plus the lock events logged
+//! since), handled according to the holder's lock policy when its
+//! agent goes stale, and snapshotted again on shutdown.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 
-use crate::artifact::FsArtifactStore;
-use crate::config::ProjectConfig;
-use crate::event::FileEventLog;
-use crate::router::Router;
-use crate::types::DomainId;
-use crate::watcher::OutboxWatcher;
+use crate::artifact;
+use crate::config::{InboxFormat, LockPolicy, ProjectConfig};
+use crate::dispatch::{self, Dispatcher};
+use crate::event::{self, Event, EventLog};
+use crate::event_kind::{EventKind, LocksEscalated, LocksReclaimed};
+use crate::heartbeat::{AgentRegistry, Notify, SAVED_STATUS_FILE};
+use crate::lock::{self, LockManager, LockStore};
+use crate::replay;
+use crate::router::{Message, Router};
+use crate::staleness::AckRegistry;
+use crate::subscription::SubscriptionRegistry;
+use crate::supervisor::Supervisor;
+use crate::types::{AgentState, DomainId};
+use crate::watcher::{ArtifactWatcher, OutboxWatcher, StatusWatcher};
 
 /// The main orchestrator that wires watcher -> router -> event log.
 pub struct Orchestrator {
     router: Arc<Router>,
     watcher: OutboxWatcher,
+    artifact_watcher: ArtifactWatcher,
+    status_watcher: StatusWatcher,
+    /// How long artifact updates may go unacknowledged, if checked at all.
+    stale_after: Option<Duration>,
+    /// How old an agent's heartbeat may get, if checked at all.
+    heartbeat_timeout: Option<Duration>,
+    locks: LockManager,
+    lock_store: Arc<dyn LockStore>,
+    /// What to do with each domain's locks when its agent goes stale.
+    lock_policies: HashMap<DomainId, LockPolicy>,
+    event_log: Arc<dyn EventLog>,
+    supervisor: Supervisor,
+    /// Hands ready tasks to idle agents, if enabled.
+    dispatcher: Option<Dispatcher>,
 }
 
+/// Longest interval between staleness checks.
+const STALENESS_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Longest interval between heartbeat checks.
+const HEARTBEAT_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Interval between offering ready tasks to agents that stay idle.
+const DISPATCH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
 impl Orchestrator {
     /// Build an orchestrator from a project config and state directory.
     ///
@@ -39,28 +77,105 @@ impl Orchestrator {
             .map(|(id, dc)| (id.clone(), dc.path.join(".orchestrator")))
             .collect();
 
-        // Build domain -> root path map for the artifact store.
-        let artifact_roots: HashMap<String, PathBuf> = config
+        let inbox_formats: HashMap<DomainId, InboxFormat> = config
             .domains
             .iter()
-            .map(|(id, dc)| (id.as_str().to_owned(), dc.path.clone()))
+            .map(|(id, dc)| (id.clone(), dc.inbox_format))
             .collect();
 
-        let artifact_store = Arc::new(FsArtifactStore::new(artifact_roots));
-        let event_log = Arc::new(FileEventLog::new(state_dir.join("event.log")));
-        let router = Arc::new(Router::new(domains.clone(), artifact_store, event_log));
+        let artifact_store = artifact::open_store(config, &state_dir);
+        let event_log = event::open_log(config, &state_dir).context("opening event log")?;
+        let supervisor = Supervisor::new(config, &state_dir, event_log.clone())
+            .context("preparing agent supervisor")?;
+        let dispatcher = dispatch::open_tracker(config)
+            .map(|tracker| Dispatcher::new(tracker, event_log.clone()))
+            .transpose()
+            .context("preparing task dispatch")?;
+        let lock_store = lock::open_store(config, &state_dir).context("opening lock store")?;
+        let locks = replay::restore_locks(&*lock_store, &*event_log)
+            .context("restoring locks")?
+            .with_event_log(event_log.clone());
+        let subscriptions = Arc::new(
+            SubscriptionRegistry::load(config, state_dir.join("subscriptions.json"))
+                .context("loading artifact subscriptions")?,
+        );
+        let acks = Arc::new(
+            AckRegistry::load(state_dir.join("acks.json"))
+                .context("loading artifact acknowledgements")?,
+        );
+        let router = Arc::new(Router::new(
+            domains.clone(),
+            inbox_formats,
+            artifact_store,
+            event_log.clone(),
+            subscriptions,
+            acks,
+            Arc::new(
+                AgentRegistry::load(state_dir.join(SAVED_STATUS_FILE))
+                    .context("loading agent statuses")?,
+            ),
+        ));
+        router.load_statuses();
 
         // Collect all outbox directories.
         let outbox_dirs: Vec<PathBuf> = domains.values().map(|d| d.join("outbox")).collect();
 
         let watcher = OutboxWatcher::new(outbox_dirs).context("creating outbox watcher")?;
 
-        Ok(Self { router, watcher })
+        // Collect all artifact directories, creating any that are missing
+        // so they can be watched.
+        let artifact_dirs: Vec<PathBuf> = domains.values().map(|d| d.join("artifacts")).collect();
+        for dir in &artifact_dirs {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("creating artifacts dir: {}", dir.display()))?;
+        }
+
+        let artifact_watcher =
+            ArtifactWatcher::new(artifact_dirs).context("creating artifact watcher")?;
+
+        let status_watcher = StatusWatcher::new(domains.values().cloned().collect())
+            .context("creating status watcher")?;
+
+        Ok(Self {
+            router,
+            watcher,
+            artifact_watcher,
+            status_watcher,
+            stale_after: config.artifacts.stale_after_secs.map(Duration::from_secs),
+            heartbeat_timeout: config
+                .agents
+                .heartbeat_timeout_secs
+                .map(Duration::from_secs),
+            locks,
+            lock_store,
+            lock_policies: config
+                .domains
+                .iter()
+                .map(|(id, dc)| (id.clone(), dc.lock_policy))
+                .collect(),
+            event_log,
+            supervisor,
+            dispatcher,
+        })
     }
 
     /// Run the async event loop until ctrl-c.
     pub async fn run(mut self) -> Result<()> {
         tracing::info!("comm-node started, watching outboxes");
+        self.supervisor.start();
+
+        let check_every = self.stale_after.map_or(STALENESS_CHECK_INTERVAL, |w| {
+            w.clamp(Duration::from_secs(1), STALENESS_CHECK_INTERVAL)
+        });
+        let mut staleness_tick = tokio::time::interval(check_every);
+
+        let check_every = self
+            .heartbeat_timeout
+            .map_or(HEARTBEAT_CHECK_INTERVAL, |t| {
+                t.clamp(Duration::from_secs(1), HEARTBEAT_CHECK_INTERVAL)
+            });
+        let mut heartbeat_tick = tokio::time::interval(check_every);
+        let mut dispatch_tick = tokio::time::interval(DISPATCH_CHECK_INTERVAL);
 
         loop {
             tokio::select! {
@@ -75,6 +190,62 @@ impl Orchestrator {
                         );
                     }
                 }
+                Some(path) = self.artifact_watcher.events.recv() => {
+                    if let Err(e) = self.router.publish_artifact(&path) {
+                        tracing::error!(
+                            path = %path.display(),
+                            error = %e,
+                            "failed to publish artifact to subscribers"
+                        );
+                    }
+                }
+                _ = staleness_tick.tick(), if self.stale_after.is_some() => {
+                    let window = chrono::Duration::from_std(self.stale_after.unwrap_or_default())
+                        .unwrap_or(chrono::Duration::MAX);
+                    if let Err(e) = self.router.check_staleness(window) {
+                        tracing::error!(error = %e, "failed to check artifact staleness");
+                    }
+                }
+                Some(path) = self.status_watcher.events.recv() => {
+                    match self.router.record_status(&path) {
+                        Ok(Some((domain, state))) => {
+                            if let Some(dispatcher) = &mut self.dispatcher {
+                                dispatcher.state_changed(&domain);
+                            }
+                            if state == AgentState::Idle {
+                                self.dispatch_tasks(&[domain]).await;
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => tracing::warn!(
+                            path = %path.display(),
+                            error = %e,
+                            "failed to record agent status"
+                        ),
+                    }
+                }
+                _ = heartbeat_tick.tick(), if self.heartbeat_timeout.is_some() => {
+                    let timeout = chrono::Duration::from_std(self.heartbeat_timeout.unwrap_or_default())
+                        .unwrap_or(chrono::Duration::MAX);
+                    match self.router.check_heartbeats(timeout) {
+                        Ok(stale) => {
+                            for domain in stale {
+                                if let Err(e) = self.handle_stale_locks(&domain) {
+                                    tracing::error!(
+                                        domain = %domain,
+                                        error = %e,
+                                        "failed to handle stale agent's locks"
+                                    );
+                                }
+                            }
+                        }
+                        Err(e) => tracing::error!(error = %e, "failed to check agent heartbeats"),
+                    }
+                }
+                _ = dispatch_tick.tick(), if self.dispatcher.is_some() => {
+                    let idle = self.router.idle_agents();
+                    self.dispatch_tasks(&idle).await;
+                }
                 _ = tokio::signal::ctrl_c() => {
                     tracing::info!("received ctrl-c, shutting down");
                     break;
@@ -82,6 +253,346 @@ impl Orchestrator {
             }
         }
 
-        Ok(())
+        self.supervisor.shutdown().await;
+        self.locks
+            .persist(&*self.lock_store)
+            .context("saving lock snapshot")
+    }
+
+    /// Offer each of the idle `domains` a ready task.
+    async fn dispatch_tasks(&mut self, domains: &[DomainId]) {
+        let Some(dispatcher) = &mut self.dispatcher else {
+            return;
+        };
+        if let Err(e) = dispatcher.dispatch(&self.router, domains).await {
+            tracing::error!(error = %e, "failed to dispatch ready tasks");
+        }
+    }
+
+    /// Apply `domain`'s lock policy now that its agent is stale.
+    fn handle_stale_locks(&mut self, domain: &DomainId) -> Result<()> {
+        let policy = self.lock_policies.get(domain).copied().unwrap_or_default();
+        let router = &self.router;
+        apply_lock_policy(
+            &mut self.locks,
+            &*self.lock_store,
+            &*self.event_log,
+            domain,
+            policy,
+            &|notice, stem| router.deliver_notice(notice, stem),
+        )
+    }
+}
+
+/// Apply `policy` to the locks of `domain`, whose agent is stale:
+/// release them (logging `locks_reclaimed`), keep them, or keep them and
+/// tell the waiting domains (logging `locks_escalated`).
+fn apply_lock_policy(
+    locks: &mut LockManager,
+    lock_store: &dyn LockStore,
+    event_log: &dyn EventLog,
+    domain: &DomainId,
+    policy: LockPolicy,
+    notify: Notify,
+) -> Result<()> {
+    let paths: Vec<String> = locks
+        .held_by(domain)
+        .iter()
+        .map(|l| l.path.display().to_string())
+        .collect();
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    match policy {
+        LockPolicy::Hold => {
+            tracing::info!(domain = %domain, locks = paths.len(), "holding stale agent's locks");
+        }
+        LockPolicy::Release => {
+            let reclaimed = locks.reclaim(domain, "holder's agent is stale");
+            locks.persist(lock_store).context("saving lock snapshot")?;
+            tracing::warn!(domain = %domain, locks = paths.len(), "released stale agent's locks");
+
+            let mut notices: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            let mut reassigned = BTreeMap::new();
+            for r in &reclaimed {
+                let path = r.path.display().to_string();
+                let Some(next) = &r.reassigned_to else {
+                    continue;
+                };
+                reassigned.insert(path.clone(), next.to_string());
+                notices
+                    .entry(next.to_string())
+                    .or_default()
+                    .push(format!("- `{}`: the lock is now yours", path));
+                for waiting in &r.waiting {
+                    notices
+                        .entry(waiting.to_string())
+                        .or_default()
+                        .push(format!(
+                            "- `{}`: granted to `{}`; you are still waiting",
+                            path, next
+                        ));
+                }
+            }
+
+            let event = Event::new(EventKind::LocksReclaimed(LocksReclaimed {
+                holder: domain.to_string(),
+                paths,
+                reassigned,
+            }));
+            if let Err(e) = event_log.log(&event) {
+                tracing::error!(error = %e, "failed to log locks reclaimed event");
+            }
+
+            for (peer, lines) in notices {
+                let body = format!(
+                    "Domain `{}` stopped sending heartbeats, so its file locks were released:\n\n{}",
+                    domain,
+                    lines.join("\n")
+                );
+                notify_lock_waiter(notify, domain, &peer, "locks_reclaimed", body)?;
+            }
+        }
+        LockPolicy::Escalate => {
+            let mut waiting: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for lock in locks.held_by(domain) {
+                for waiter in locks.waiters(&lock.path) {
+                    waiting
+                        .entry(waiter.to_string())
+                        .or_default()
+                        .push(format!("- `{}`", lock.path.display()));
+                }
+            }
+            tracing::warn!(
+                domain = %domain,
+                locks = paths.len(),
+                waiting = waiting.len(),
+                "stale agent holds locks; escalating"
+            );
+
+            let event = Event::new(EventKind::LocksEscalated(LocksEscalated {
+                holder: domain.to_string(),
+                paths,
+                notified: waiting.keys().cloned().collect(),
+            }));
+            if let Err(e) = event_log.log(&event) {
+                tracing::error!(error = %e, "failed to log locks escalated event");
+            }
+
+            for (peer, lines) in waiting {
+                let body = format!(
+                    "Domain `{}` stopped sending heartbeats but still holds file locks you \
+                     are waiting for:\n\n{}\n\nIts locks are kept until it resumes or an \
+                     operator intervenes.",
+                    domain,
+                    lines.join("\n")
+                );
+                notify_lock_waiter(notify, domain, &peer, "locks_escalated", body)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Tell `peer` what became of locks held by the stale `holder`.
+fn notify_lock_waiter(
+    notify: Notify,
+    holder: &DomainId,
+    peer: &str,
+    msg_type: &str,
+    body: String,
+) -> Result<()> {
+    let notice = Message {
+        from: holder.clone(),
+        to: DomainId::new(peer),
+        msg_type: msg_type.to_string(),
+        task: String::new(),
+        priority: "high".to_string(),
+        artifacts: Vec::new(),
+        body,
+    };
+    notify(&notice, &format!("{}-{}", msg_type, holder))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::path::Path;
+
+    use crate::event::{EventQuery, FileEventLog};
+    use crate::lock::FileLockStore;
+
+    struct Fixture {
+        dir: PathBuf,
+        locks: LockManager,
+        lock_store: FileLockStore,
+        event_log: FileEventLog,
+        /// `(to, stem)` of every notice delivered.
+        notices: RefCell<Vec<(String, String)>>,
+    }
+
+    impl Fixture {
+        /// `a` holds `x.rs` and `y.rs`; `b` then `c` wait for `x.rs`,
+        /// and `b` also waits for `y.rs`.
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "comm-node-orchestrator-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+
+            let mut locks = LockManager::new();
+            let a = DomainId::new("a");
+            locks.acquire(PathBuf::from("x.rs"), a.clone()).unwrap();
+            locks.acquire(PathBuf::from("y.rs"), a).unwrap();
+            for (path, waiter) in [("x.rs", "b"), ("x.rs", "c"), ("y.rs", "b")] {
+                assert!(locks
+                    .acquire(PathBuf::from(path), DomainId::new(waiter))
+                    .is_err());
+            }
+            Self {
+                lock_store: FileLockStore::new(dir.join("locks.json")),
+                event_log: FileEventLog::new(dir.join("event.log")),
+                dir,
+                locks,
+                notices: RefCell::new(Vec::new()),
+            }
+        }
+
+        fn apply(&mut self, policy: LockPolicy) {
+            let notices = &self.notices;
+            apply_lock_policy(
+                &mut self.locks,
+                &self.lock_store,
+                &self.event_log,
+                &DomainId::new("a"),
+                policy,
+                &|notice, stem| {
+                    notices
+                        .borrow_mut()
+                        .push((notice.to.to_string(), stem.to_string()));
+                    Ok(())
+                },
+            )
+            .unwrap();
+        }
+
+        fn holder(&self, path: &str) -> Option<String> {
+            self.locks
+                .list()
+                .into_iter()
+                .find(|l| l.path == Path::new(path))
+                .map(|l| l.holder.to_string())
+        }
+
+        fn events(&self, kind: &str) -> Vec<serde_json::Value> {
+            let query = EventQuery {
+                kinds: [kind.to_string()].into(),
+                ..Default::default()
+            };
+            self.event_log
+                .query(&query)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.event.payload)
+                .collect()
+        }
+
+        fn notified(&self) -> Vec<(String, String)> {
+            self.notices.borrow().clone()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn notice(to: &str, stem: &str) -> (String, String) {
+        (to.to_string(), stem.to_string())
+    }
+
+    #[test]
+    fn release_hands_each_lock_to_its_first_waiter() {
+        let mut fx = Fixture::new("release");
+        fx.apply(LockPolicy::Release);
+
+        assert!(fx.locks.held_by(&DomainId::new("a")).is_empty());
+        assert_eq!(fx.holder("x.rs").as_deref(), Some("b"));
+        assert_eq!(fx.holder("y.rs").as_deref(), Some("b"));
+        assert_eq!(fx.locks.waiters(Path::new("x.rs")), [DomainId::new("c")]);
+        let saved = fx.lock_store.load().unwrap();
+        assert!(saved.iter().all(|l| l.holder.as_str() == "b"));
+
+        let reclaimed = fx.events("locks_reclaimed");
+        assert_eq!(reclaimed.len(), 1);
+        assert_eq!(reclaimed[0]["holder"], "a");
+        assert_eq!(reclaimed[0]["paths"], serde_json::json!(["x.rs", "y.rs"]));
+        assert_eq!(
+            reclaimed[0]["reassigned"],
+            serde_json::json!({"x.rs": "b", "y.rs": "b"})
+        );
+        assert_eq!(
+            fx.notified(),
+            vec![
+                notice("b", "locks_reclaimed-a"),
+                notice("c", "locks_reclaimed-a")
+            ]
+        );
+    }
+
+    #[test]
+    fn release_frees_locks_nobody_is_waiting_for() {
+        let mut fx = Fixture::new("release-free");
+        fx.locks
+            .acquire(PathBuf::from("z.rs"), DomainId::new("a"))
+            .unwrap();
+        fx.apply(LockPolicy::Release);
+
+        assert_eq!(fx.holder("z.rs"), None);
+        let reclaimed = fx.events("locks_reclaimed");
+        assert_eq!(
+            reclaimed[0]["paths"],
+            serde_json::json!(["x.rs", "y.rs", "z.rs"])
+        );
+        assert!(reclaimed[0]["reassigned"].get("z.rs").is_none());
+    }
+
+    #[test]
+    fn hold_keeps_the_locks_quietly() {
+        let mut fx = Fixture::new("hold");
+        fx.apply(LockPolicy::Hold);
+
+        assert_eq!(fx.locks.held_by(&DomainId::new("a")).len(), 2);
+        assert!(fx.events("locks_reclaimed").is_empty());
+        assert!(fx.events("locks_escalated").is_empty());
+        assert!(fx.notified().is_empty());
+    }
+
+    #[test]
+    fn escalate_keeps_the_locks_and_notifies_each_waiter_once() {
+        let mut fx = Fixture::new("escalate");
+        fx.apply(LockPolicy::Escalate);
+
+        assert_eq!(fx.holder("x.rs").as_deref(), Some("a"));
+        assert_eq!(fx.holder("y.rs").as_deref(), Some("a"));
+        assert!(fx.events("locks_reclaimed").is_empty());
+
+        let escalated = fx.events("locks_escalated");
+        assert_eq!(escalated.len(), 1);
+        assert_eq!(escalated[0]["paths"], serde_json::json!(["x.rs", "y.rs"]));
+        assert_eq!(escalated[0]["notified"], serde_json::json!(["b", "c"]));
+        assert_eq!(
+            fx.notified(),
+            vec![
+                notice("b", "locks_escalated-a"),
+                notice("c", "locks_escalated-a")
+            ]
+        );
     }
 }
@@ -0,0 +1,392 @@
+//! Rebuilding orchestrator state from the event log.
+//!
+//! Replay folds logged events, oldest first, into a [`ReplayState`]:
+//! the advisory locks held, the questions still waiting for an answer,
+//! and per-domain delivery counters. On startup it brings the lock
+//! snapshot up to date with the lock events logged after it;
+//! `comm-node replay --until <time>` prints the state as of any moment
+//! for post-mortems.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::event::{EventLog, EventQuery, LoggedEvent};
+use crate::event_kind::EventKind;
+use crate::lock::{FileLock, LockManager, LockStore};
+use crate::types::{DomainId, MessageId};
+
+/// A `question` message with no reply yet.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenQuestion {
+    pub message_id: Option<MessageId>,
+    pub from: String,
+    pub to: String,
+    pub task: String,
+    pub asked_at: DateTime<Utc>,
+}
+
+/// Messages and artifacts that passed through one domain.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeliveryCounters {
+    /// Messages routed from the domain's outbox.
+    pub sent: u64,
+    /// Messages delivered to the domain's inbox.
+    pub received: u64,
+    /// Messages from the domain the router refused.
+    pub rejected: u64,
+    /// Artifacts (or bundles) copied into the domain.
+    pub artifacts_received: u64,
+}
+
+/// Orchestrator state as of the last replayed event.
+#[derive(Debug, Default, Serialize)]
+pub struct ReplayState {
+    /// Held locks, keyed by path.
+    pub locks: BTreeMap<PathBuf, FileLock>,
+    /// Unanswered questions, oldest first.
+    pub open_questions: Vec<OpenQuestion>,
+    /// Counters keyed by domain.
+    pub deliveries: BTreeMap<String, DeliveryCounters>,
+    /// Sequence number of the last event applied.
+    pub last_seq: Option<u64>,
+    /// Events of kinds this build does not know, which were skipped.
+    pub skipped: u64,
+}
+
+impl ReplayState {
+    /// Apply one event on top of the current state.
+    pub fn apply(&mut self, logged: &LoggedEvent) {
+        self.last_seq = Some(logged.seq);
+        let timestamp = logged.event.timestamp;
+        let kind = match logged.event.typed() {
+            Ok(kind) => kind,
+            Err(e) => {
+                tracing::debug!(seq = logged.seq, error = %e, "skipping event during replay");
+                self.skipped += 1;
+                return;
+            }
+        };
+
+        match kind {
+            EventKind::MessageRouted(m) => {
+                self.deliveries.entry(m.from.clone()).or_default().sent += 1;
+                self.deliveries.entry(m.to.clone()).or_default().received += 1;
+                // Any reply on the same task answers the sender's questions.
+                self.open_questions
+                    .retain(|q| !(q.from == m.to && q.to == m.from && q.task == m.task));
+                if m.msg_type == "question" {
+                    self.open_questions.push(OpenQuestion {
+                        message_id: m.message_id,
+                        from: m.from,
+                        to: m.to,
+                        task: m.task,
+                        asked_at: timestamp,
+                    });
+                }
+            }
+            EventKind::MessageRejected(m) => {
+                self.deliveries.entry(m.from).or_default().rejected += 1;
+            }
+            EventKind::ArtifactRouted(a) => {
+                self.deliveries.entry(a.to).or_default().artifacts_received += 1;
+            }
+            EventKind::LockAcquired(l) => {
+                let path = PathBuf::from(l.path);
+                self.locks.insert(
+                    path.clone(),
+                    FileLock {
+                        path,
+                        holder: DomainId::new(l.holder),
+                        acquired_at: timestamp,
+                    },
+                );
+            }
+            EventKind::LockReleased(l) => {
+                self.locks.remove(&PathBuf::from(l.path));
+            }
+            EventKind::LockExpired(l) => {
+                self.locks.remove(&PathBuf::from(l.path));
+            }
+            EventKind::LocksReclaimed(r) => {
+                // The holder's locks go to their new holders, or are freed.
+                for path in r.paths {
+                    let lock = self.locks.get(&PathBuf::from(&path));
+                    match r.reassigned.get(&path) {
+                        Some(next) if lock.is_none_or(|l| l.holder.as_str() != next) => {
+                            let path = PathBuf::from(path);
+                            self.locks.insert(
+                                path.clone(),
+                                FileLock {
+                                    path,
+                                    holder: DomainId::new(next),
+                                    acquired_at: timestamp,
+                                },
+                            );
+                        }
+                        None if lock.is_some_and(|l| l.holder.as_str() == r.holder) => {
+                            self.locks.remove(&PathBuf::from(path));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            EventKind::ArtifactUpdated(_)
+            | EventKind::ArtifactAcknowledged(_)
+            | EventKind::ArtifactStale(_)
+            | EventKind::ContractChanged(_)
+            | EventKind::SecurityViolation(_)
+            | EventKind::QuotaExceeded(_)
+            | EventKind::AgentStateChanged(_)
+            | EventKind::AgentStale(_)
+            | EventKind::AgentRecovered(_)
+            | EventKind::StatusInvalid(_)
+            | EventKind::LocksEscalated(_)
+            | EventKind::AgentStarted(_)
+            | EventKind::AgentExited(_)
+            | EventKind::TaskAssigned(_) => {}
+        }
+    }
+}
+
+/// Replay every event in `log` logged before `until` (all of them if
+/// `None`).
+pub fn replay(log: &dyn EventLog, until: Option<DateTime<Utc>>) -> Result<ReplayState> {
+    let mut state = ReplayState::default();
+    for logged in log.query(&EventQuery {
+        until,
+        ..EventQuery::default()
+    })? {
+        state.apply(&logged);
+    }
+    Ok(state)
+}
+
+/// Restore locks from the snapshot in `store` and apply the lock events
+/// logged after it was saved (every lock event if there is none). An
+/// updated set is saved back to `store`.
+pub fn restore_locks(store: &dyn LockStore, log: &dyn EventLog) -> Result<LockManager> {
+    let saved_at = store.saved_at()?;
+    // Re-applying an event the snapshot already reflects is harmless, so
+    // ones logged at the moment it was saved are included.
+    let newer = log.query(&EventQuery {
+        kinds: [
+            "lock_acquired",
+            "lock_released",
+            "lock_expired",
+            "locks_reclaimed",
+        ]
+        .into_iter()
+        .map(str::to_owned)
+        .collect(),
+        since: saved_at,
+        ..EventQuery::default()
+    })?;
+
+    let snapshot = match saved_at {
+        Some(_) => store.load()?,
+        None => Vec::new(),
+    };
+    if newer.is_empty() {
+        return Ok(LockManager::from_locks(snapshot));
+    }
+
+    let mut state = ReplayState {
+        locks: snapshot.into_iter().map(|l| (l.path.clone(), l)).collect(),
+        ..ReplayState::default()
+    };
+    for logged in &newer {
+        state.apply(logged);
+    }
+    tracing::info!(
+        locks = state.locks.len(),
+        events = newer.len(),
+        snapshot = ?saved_at,
+        "applied lock events newer than the snapshot"
+    );
+    let locks = LockManager::from_locks(state.locks.into_values());
+    locks.persist(store)?;
+    Ok(locks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::event::{Event, FileEventLog};
+    use crate::event_kind::{LockAcquired, LockExpired, LockReleased, LocksReclaimed};
+    use crate::lock::FileLockStore;
+
+    /// A scratch event log and lock snapshot, removed on drop.
+    struct Fixture {
+        dir: PathBuf,
+        log: FileEventLog,
+        store: FileLockStore,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "comm-node-replay-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self {
+                log: FileEventLog::new(dir.join("event.log")),
+                store: FileLockStore::new(dir.join("locks.json")),
+                dir,
+            }
+        }
+
+        fn log(&self, at: DateTime<Utc>, kind: EventKind) {
+            self.log.log(&Event::at(at, kind)).unwrap();
+        }
+
+        fn acquired(&self, at: DateTime<Utc>, path: &str, holder: &str) {
+            self.log(
+                at,
+                EventKind::LockAcquired(LockAcquired {
+                    path: path.to_string(),
+                    holder: holder.to_string(),
+                }),
+            );
+        }
+
+        /// Save a snapshot of `locks` (path, holder) and return its time.
+        fn snapshot(&self, locks: &[(&str, &str)]) -> DateTime<Utc> {
+            let locks: Vec<FileLock> = locks.iter().map(|(p, h)| lock(p, h)).collect();
+            self.store.save(&locks.iter().collect::<Vec<_>>()).unwrap();
+            self.store.saved_at().unwrap().unwrap()
+        }
+
+        /// Restored locks as (path, holder), ordered by path.
+        fn restore(&self) -> Vec<(String, String)> {
+            let manager = restore_locks(&self.store, &self.log).unwrap();
+            let mut locks: Vec<(String, String)> = manager
+                .list()
+                .into_iter()
+                .map(|l| (l.path.display().to_string(), l.holder.to_string()))
+                .collect();
+            locks.sort();
+            locks
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn lock(path: &str, holder: &str) -> FileLock {
+        FileLock {
+            path: PathBuf::from(path),
+            holder: DomainId::new(holder),
+            acquired_at: Utc::now(),
+        }
+    }
+
+    fn pairs(locks: &[(&str, &str)]) -> Vec<(String, String)> {
+        locks
+            .iter()
+            .map(|(p, h)| (p.to_string(), h.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn applies_lock_events_logged_after_the_snapshot() {
+        let fixture = Fixture::new("after");
+        let saved_at = fixture.snapshot(&[("a.rs", "a"), ("b.rs", "b"), ("c.rs", "c")]);
+        let later = saved_at + chrono::Duration::seconds(1);
+
+        fixture.acquired(later, "d.rs", "d");
+        fixture.log(
+            later,
+            EventKind::LockReleased(LockReleased {
+                path: "a.rs".to_string(),
+                holder: "a".to_string(),
+            }),
+        );
+        fixture.log(
+            later,
+            EventKind::LockExpired(LockExpired {
+                path: "b.rs".to_string(),
+                holder: "b".to_string(),
+                acquired_at: saved_at,
+                reason: "agent stale".to_string(),
+            }),
+        );
+        // `c` went stale: c.rs went to a waiting `a`, d.rs stays with `d`.
+        fixture.acquired(later, "e.rs", "c");
+        fixture.log(
+            later,
+            EventKind::LocksReclaimed(LocksReclaimed {
+                holder: "c".to_string(),
+                paths: vec!["c.rs".to_string(), "e.rs".to_string()],
+                reassigned: BTreeMap::from([("c.rs".to_string(), "a".to_string())]),
+            }),
+        );
+
+        assert_eq!(fixture.restore(), pairs(&[("c.rs", "a"), ("d.rs", "d")]));
+        // The result is saved as the new snapshot.
+        let saved: Vec<String> = fixture
+            .store
+            .load()
+            .unwrap()
+            .into_iter()
+            .map(|l| l.path.display().to_string())
+            .collect();
+        assert_eq!(saved.len(), 2);
+    }
+
+    #[test]
+    fn ignores_events_older_than_the_snapshot() {
+        let fixture = Fixture::new("older");
+        let earlier = Utc::now() - chrono::Duration::minutes(5);
+        fixture.acquired(earlier, "old.rs", "a");
+        fixture.acquired(earlier, "kept.rs", "b");
+        let saved_at = fixture.snapshot(&[("kept.rs", "b")]);
+        fixture.acquired(saved_at + chrono::Duration::seconds(1), "new.rs", "c");
+
+        assert_eq!(
+            fixture.restore(),
+            pairs(&[("kept.rs", "b"), ("new.rs", "c")])
+        );
+    }
+
+    #[test]
+    fn replays_every_lock_event_without_a_snapshot() {
+        let fixture = Fixture::new("missing");
+        let now = Utc::now();
+        fixture.acquired(now, "a.rs", "a");
+        fixture.acquired(now, "b.rs", "b");
+        fixture.log(
+            now,
+            EventKind::LockReleased(LockReleased {
+                path: "a.rs".to_string(),
+                holder: "a".to_string(),
+            }),
+        );
+
+        assert_eq!(fixture.store.saved_at().unwrap(), None);
+        assert_eq!(fixture.restore(), pairs(&[("b.rs", "b")]));
+        assert!(fixture.store.saved_at().unwrap().is_some());
+    }
+
+    #[test]
+    fn reads_a_legacy_snapshot_dated_by_its_mtime() {
+        let fixture = Fixture::new("legacy");
+        let legacy = serde_json::to_string(&[lock("a.rs", "a")]).unwrap();
+        std::fs::write(fixture.dir.join("locks.json"), legacy).unwrap();
+
+        assert!(fixture.store.saved_at().unwrap().is_some());
+        assert_eq!(fixture.store.load().unwrap().len(), 1);
+        assert_eq!(fixture.restore(), pairs(&[("a.rs", "a")]));
+    }
+}
@@ -1,6 +1,6 @@
 //! Message routing between agent domains.
 //!
-//! Parses YAML frontmatter from outbox messages, validates fields,
+//! Parses frontmatter (or JSON) from outbox messages, validates fields,
 //! routes artifacts, handles completion signals, and delivers
 //! messages to the target domain's inbox.
 
@@ -8,12 +8,28 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::artifact::ArtifactStore;
+use crate::artifact::{self, ArtifactStore, QuotaExceeded, UnsafeArtifactName};
+use crate::bundle;
+use crate::config::InboxFormat;
+use crate::contract::{self, ContractDiff};
 use crate::event::{Event, EventLog};
-use crate::types::DomainId;
+use crate::event_kind::{
+    self, ArtifactAcknowledged, ArtifactRouted, ArtifactStale, ArtifactUpdated, ContractChanged,
+    EventKind, MessageRejected, MessageRouted, SecurityViolation,
+};
+use crate::frontmatter;
+use crate::heartbeat::{self, AgentRegistry, StatusMonitor, STATUS_FILE};
+use crate::manifest::Provenance;
+use crate::staleness::{self, AckRegistry};
+use crate::subscription::{self, Subscription, SubscriptionRegistry};
+use crate::types::{AgentState, DomainId, MessageId};
+
+/// Largest artifact the router reads into memory to summarize or
+/// structurally diff an update; bigger ones are reported by size only.
+const MAX_DIFF_BYTES: u64 = 4 * 1024 * 1024;
 
 /// A parsed inter-agent message.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,26 +49,82 @@ pub struct Message {
     pub body: String,
 }
 
+impl Message {
+    /// Render the message for delivery in the given inbox format.
+    pub fn render(&self, format: InboxFormat) -> Result<String> {
+        match format {
+            InboxFormat::Markdown => {
+                let frontmatter = serde_yaml::to_string(self)?;
+                Ok(format!("---\n{}---\n\n{}\n", frontmatter, self.body))
+            }
+            InboxFormat::Json => {
+                let wire = JsonMessage {
+                    message: self.clone(),
+                    body: self.body.clone(),
+                };
+                Ok(serde_json::to_string_pretty(&wire)? + "\n")
+            }
+        }
+    }
+}
+
+/// JSON wire form of a [`Message`], with the body inline.
+#[derive(Serialize, Deserialize)]
+struct JsonMessage {
+    #[serde(flatten)]
+    message: Message,
+    #[serde(default)]
+    body: String,
+}
+
+impl JsonMessage {
+    fn into_message(self) -> Message {
+        Message {
+            body: self.body,
+            ..self.message
+        }
+    }
+}
+
 /// Routes messages between domain inboxes.
 pub struct Router {
     /// Base paths for each domain's `.orchestrator/` directory.
     domains: HashMap<DomainId, PathBuf>,
+    /// Inbox rendering per domain (defaults to markdown when absent).
+    inbox_formats: HashMap<DomainId, InboxFormat>,
     /// Artifact store for cross-domain work products.
     artifact_store: Arc<dyn ArtifactStore>,
     /// Event log for routing audit trail.
     event_log: Arc<dyn EventLog>,
+    /// Which domains receive which peer artifacts automatically.
+    subscriptions: Arc<SubscriptionRegistry>,
+    /// Which artifact versions each domain has acknowledged.
+    acks: Arc<AckRegistry>,
+    /// Latest status reported by each domain's agent.
+    agents: Arc<AgentRegistry>,
+    /// Validates status updates and tracks heartbeats.
+    status: StatusMonitor,
 }
 
 impl Router {
     pub fn new(
         domains: HashMap<DomainId, PathBuf>,
+        inbox_formats: HashMap<DomainId, InboxFormat>,
         artifact_store: Arc<dyn ArtifactStore>,
         event_log: Arc<dyn EventLog>,
+        subscriptions: Arc<SubscriptionRegistry>,
+        acks: Arc<AckRegistry>,
+        agents: Arc<AgentRegistry>,
     ) -> Self {
         Self {
             domains,
+            inbox_formats,
             artifact_store,
+            status: StatusMonitor::new(agents.clone(), event_log.clone()),
             event_log,
+            subscriptions,
+            acks,
+            agents,
         }
     }
 
@@ -64,21 +136,44 @@ impl Router {
     /// 3. Validate `from` field matches source domain
     /// 4. Validate target domain exists
     /// 5. Check for completion signal -> call `bd close`
-    /// 6. Route artifacts if present
-    /// 7. Copy message to target inbox, remove from source outbox
+    /// 6. Register subscriptions (`type: subscribe`), record
+    ///    acknowledgements (`type: ack`), or route artifacts
+    /// 7. Deliver message to target inbox in its configured format,
+    ///    remove from source outbox
     /// 8. Log routing event
+    ///
+    /// A message that parses but fails a later step is left in the
+    /// outbox and logged as a `message_rejected` event.
     pub async fn route(&self, message_path: &Path) -> Result<()> {
         let raw_content = std::fs::read(message_path)
             .with_context(|| format!("reading message: {}", message_path.display()))?;
         let size_bytes = raw_content.len();
 
         let message = Self::parse(message_path)?;
+        let message_id = MessageId::new();
 
+        let routed = self
+            .route_message(message_path, &message, &message_id, size_bytes)
+            .await;
+        if let Err(e) = &routed {
+            self.log_rejection(&message, &message_id, e);
+        }
+        routed
+    }
+
+    /// Steps 2-8 of [`Router::route`] for a parsed message.
+    async fn route_message(
+        &self,
+        message_path: &Path,
+        message: &Message,
+        message_id: &MessageId,
+        size_bytes: usize,
+    ) -> Result<()> {
         // Resolve which domain's outbox this file lives in.
         let source_domain = self.resolve_source_domain(message_path)?;
 
         // Validate `from` field matches the actual source domain.
-        self.validate_from(&message, &source_domain)?;
+        self.validate_from(message, &source_domain)?;
 
         // Validate target domain exists.
         if !self.domains.contains_key(&message.to) {
@@ -96,24 +191,43 @@ impl Router {
             }
         }
 
-        // Route artifacts if present (failure = route failure).
-        if !message.artifacts.is_empty() {
-            self.route_artifacts(&message)?;
+        // A subscribe message lists patterns, not artifacts to copy.
+        if message.msg_type == "subscribe" {
+            self.register_subscriptions(message)?;
+        } else if message.msg_type == "ack" {
+            // An ack names artifacts the sender holds, not ones to copy.
+            self.acknowledge_artifacts(message)?;
+        } else if !message.artifacts.is_empty() {
+            // Route artifacts if present (failure = route failure).
+            self.route_artifacts(message)?;
         }
 
-        // Copy message to target inbox.
+        // Deliver message to target inbox, re-rendering only when the
+        // source and target formats differ.
+        let target_format = self
+            .inbox_formats
+            .get(&message.to)
+            .copied()
+            .unwrap_or_default();
         let target_dir = &self.domains[&message.to];
-        let inbox = target_dir.join("inbox");
-        let dest = inbox.join(
-            message_path
-                .file_name()
-                .context("message path has no filename")?,
-        );
+        let dest = target_dir
+            .join("inbox")
+            .join(
+                message_path
+                    .file_name()
+                    .context("message path has no filename")?,
+            )
+            .with_extension(target_format.extension());
 
-        std::fs::copy(message_path, &dest)?;
+        if Self::source_format(message_path) == target_format {
+            std::fs::copy(message_path, &dest)?;
+        } else {
+            std::fs::write(&dest, message.render(target_format)?)?;
+        }
         std::fs::remove_file(message_path)?;
 
         tracing::info!(
+            message_id = %message_id,
             from = %message.from,
             to = %message.to,
             msg_type = %message.msg_type,
@@ -122,11 +236,437 @@ impl Router {
         );
 
         // Log routing event.
-        self.log_routing_event(&message, size_bytes);
+        self.log_routing_event(message, message_id, size_bytes);
+
+        Ok(())
+    }
+
+    /// Push a producer's newly written artifact to its subscribers.
+    ///
+    /// Called by the orchestrator when a file changes under a domain's
+    /// `artifacts/` directory. Content the comm-node itself delivered
+    /// (recorded in the domain's manifest) is not re-published, and a
+    /// subscriber that already holds identical content is skipped. The
+    /// new checksum is recorded as a publication, so consumers that
+    /// have not received it yet count as behind.
+    pub fn publish_artifact(&self, artifact_path: &Path) -> Result<()> {
+        let Some((producer, name)) = self.resolve_artifact(artifact_path) else {
+            return Ok(());
+        };
+        if artifact::validate_name(&name).is_err() {
+            return Ok(());
+        }
+
+        if self
+            .artifact_store
+            .manifest(producer.as_str())?
+            .artifacts
+            .contains_key(&name)
+        {
+            return Ok(());
+        }
+
+        let sha256 = staleness::current_hash(&*self.artifact_store, producer.as_str(), &name)?;
+        self.acks
+            .record_publication(producer.as_str(), &name, &sha256)?;
+
+        for subscriber in self.subscriptions.subscribers(&producer, &name) {
+            self.push_artifact(&producer, &subscriber, &name)?;
+        }
+        Ok(())
+    }
+
+    /// Deliver the producer's current version of an artifact to a
+    /// subscriber with an `artifact_updated` notice in its inbox.
+    fn push_artifact(&self, producer: &DomainId, subscriber: &DomainId, name: &str) -> Result<()> {
+        let previous = self
+            .artifact_store
+            .manifest(subscriber.as_str())?
+            .artifacts
+            .remove(name);
+        let old = self.previous_content(subscriber, name)?;
+
+        let copied = self.artifact_store.copy(
+            producer.as_str(),
+            subscriber.as_str(),
+            name,
+            &Provenance::new(producer.as_str(), ""),
+        );
+        let version = match copied {
+            Ok(version) => version,
+            // A subscriber over its quota just misses this update.
+            Err(e) if self.reject_over_quota(producer, subscriber, "", &e) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        // The subscriber already held identical content.
+        if previous
+            .as_ref()
+            .is_some_and(|p| p.version == version.version)
+        {
+            return Ok(());
+        }
+
+        let new = if version.size_bytes <= MAX_DIFF_BYTES {
+            Some(self.artifact_store.retrieve(subscriber.as_str(), name)?)
+        } else {
+            None
+        };
+        let summary = match (&previous, &old, &new) {
+            (Some(_), Some(old), Some(new)) => subscription::diff_summary(Some(old), new),
+            (Some(p), _, _) => format!(
+                "content changed ({} -> {} bytes)",
+                p.size_bytes, version.size_bytes
+            ),
+            (None, _, _) => format!("new artifact ({} bytes)", version.size_bytes),
+        };
+        let contract_diff = match (&old, &new) {
+            (Some(old), Some(new)) => contract::diff(name, old, new),
+            _ => None,
+        };
+
+        let mut body = format!(
+            "Artifact `{}` from `{}` updated to v{}: {}.",
+            name, producer, version.version, summary
+        );
+        if let Some(diff) = &contract_diff {
+            body.push_str("\n\n");
+            body.push_str(&diff.summary());
+        }
+        let breaking = contract_diff.as_ref().is_some_and(|d| d.is_breaking());
+
+        let notice = Message {
+            from: producer.clone(),
+            to: subscriber.clone(),
+            msg_type: "artifact_updated".to_string(),
+            task: String::new(),
+            priority: if breaking { "high" } else { "medium" }.to_string(),
+            artifacts: vec![name.to_string()],
+            body,
+        };
+        self.deliver_notice(&notice, &format!("artifact_updated-v{}", version.version))?;
+
+        tracing::info!(
+            artifact = %name,
+            producer = %producer,
+            subscriber = %subscriber,
+            version = version.version,
+            "pushed artifact update"
+        );
+
+        let event = Event::new(EventKind::ArtifactUpdated(ArtifactUpdated {
+            producer: producer.to_string(),
+            subscriber: subscriber.to_string(),
+            artifact: name.to_string(),
+            version: version.version,
+            sha256: version.hash.clone(),
+            summary,
+        }));
+        if let Err(e) = self.event_log.log(&event) {
+            tracing::error!(error = %e, "failed to log artifact update event");
+        }
+
+        if let Some(diff) = &contract_diff {
+            self.log_contract_change(producer, subscriber, name, version.version, diff);
+        }
+
+        Ok(())
+    }
 
+    /// Current content of `name` as last delivered to `domain`, if any
+    /// and no larger than [`MAX_DIFF_BYTES`].
+    fn previous_content(&self, domain: &DomainId, name: &str) -> Result<Option<Vec<u8>>> {
+        let previous = self
+            .artifact_store
+            .manifest(domain.as_str())?
+            .artifacts
+            .get(name)
+            .filter(|entry| entry.size_bytes <= MAX_DIFF_BYTES)
+            .map(|entry| entry.version);
+        match previous {
+            Some(version) => Ok(Some(self.artifact_store.retrieve_version(
+                domain.as_str(),
+                name,
+                version,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Write a `contract_changed` event for a structural contract diff.
+    fn log_contract_change(
+        &self,
+        producer: &DomainId,
+        consumer: &DomainId,
+        name: &str,
+        version: u32,
+        diff: &ContractDiff,
+    ) {
+        tracing::info!(
+            artifact = %name,
+            producer = %producer,
+            consumer = %consumer,
+            breaking = diff.is_breaking(),
+            changes = diff.changes.len(),
+            "contract changed"
+        );
+
+        let event = Event::new(EventKind::ContractChanged(ContractChanged {
+            producer: producer.to_string(),
+            consumer: consumer.to_string(),
+            artifact: name.to_string(),
+            version,
+            format: diff.kind,
+            breaking: diff.is_breaking(),
+            breaking_changes: diff.breaking_count(),
+            changes: diff.changes.clone(),
+        }));
+        if let Err(e) = self.event_log.log(&event) {
+            tracing::error!(error = %e, "failed to log contract change event");
+        }
+    }
+
+    /// Record that the sender of an `ack` message uses the versions of
+    /// the listed artifacts it currently holds.
+    fn acknowledge_artifacts(&self, message: &Message) -> Result<()> {
+        let manifest = self.artifact_store.manifest(message.from.as_str())?;
+        for name in &message.artifacts {
+            let Some(entry) = manifest.artifacts.get(name) else {
+                tracing::warn!(
+                    domain = %message.from,
+                    artifact = %name,
+                    "ack for an artifact the domain has not received"
+                );
+                continue;
+            };
+            if !self
+                .acks
+                .acknowledge(message.from.as_str(), name, entry.version)?
+            {
+                continue;
+            }
+
+            tracing::info!(
+                domain = %message.from,
+                artifact = %name,
+                version = entry.version,
+                "artifact acknowledged"
+            );
+
+            let event = Event::new(EventKind::ArtifactAcknowledged(ArtifactAcknowledged {
+                domain: message.from.to_string(),
+                artifact: name.clone(),
+                version: entry.version,
+                producer: entry.producer.clone(),
+            }));
+            if let Err(e) = self.event_log.log(&event) {
+                tracing::error!(error = %e, "failed to log artifact acknowledgement event");
+            }
+        }
         Ok(())
     }
 
+    /// Notify each domain holding an artifact version it has left
+    /// unacknowledged for longer than `window`, once per version, and
+    /// log an `artifact_stale` event.
+    pub fn check_staleness(&self, window: chrono::Duration) -> Result<()> {
+        let now = chrono::Utc::now();
+        let domains = self.domains.keys().map(DomainId::as_str);
+        for lag in staleness::lags(&*self.artifact_store, &self.acks, domains)? {
+            if !lag.is_stale(now, window)
+                || !self
+                    .acks
+                    .mark_notified(&lag.domain, &lag.artifact, lag.received)?
+            {
+                continue;
+            }
+
+            tracing::warn!(
+                domain = %lag.domain,
+                artifact = %lag.artifact,
+                received = lag.received,
+                acknowledged = ?lag.acknowledged,
+                "artifact update left unacknowledged"
+            );
+
+            let event = Event::at(
+                now,
+                EventKind::ArtifactStale(ArtifactStale {
+                    domain: lag.domain.clone(),
+                    artifact: lag.artifact.clone(),
+                    producer: lag.producer.clone(),
+                    received: lag.received,
+                    acknowledged: lag.acknowledged,
+                    behind: lag.behind,
+                    waiting_since: lag.waiting_since,
+                }),
+            );
+            if let Err(e) = self.event_log.log(&event) {
+                tracing::error!(error = %e, "failed to log artifact stale event");
+            }
+
+            let acknowledged = lag
+                .acknowledged
+                .map_or("none".to_string(), |v| format!("v{}", v));
+            let notice = Message {
+                from: DomainId::new(&lag.producer),
+                to: DomainId::new(&lag.domain),
+                msg_type: "artifact_stale".to_string(),
+                task: String::new(),
+                priority: "medium".to_string(),
+                artifacts: vec![lag.artifact.clone()],
+                body: format!(
+                    "You are {} version(s) behind on artifact `{}` from `{}`: you hold v{} \
+                     but last acknowledged {}, and updates have been waiting since {}. \
+                     Update to it, then send a `type: ack` message listing the artifact.",
+                    lag.behind,
+                    lag.artifact,
+                    lag.producer,
+                    lag.received,
+                    acknowledged,
+                    lag.waiting_since.to_rfc3339()
+                ),
+            };
+            self.deliver_notice(&notice, &format!("artifact_stale-v{}", lag.received))?;
+        }
+        Ok(())
+    }
+
+    /// Read every domain's `status.json` into the agent registry
+    /// without logging transitions, so that only changes made while
+    /// the orchestrator runs are reported. Invalid statuses are skipped.
+    pub fn load_statuses(&self) {
+        for (domain, orch_dir) in &self.domains {
+            let status = heartbeat::read_valid_status(domain, &orch_dir.join(STATUS_FILE))
+                .and_then(|status| self.agents.observe(domain, status));
+            if let Err(e) = status {
+                tracing::warn!(domain = %domain, error = %e, "ignoring agent status");
+            }
+        }
+    }
+
+    /// Record an update to a domain's `status.json` (see
+    /// [`StatusMonitor::record`]), delivering any notice it produces.
+    /// Returns the domain and its new state if the state changed.
+    pub fn record_status(&self, status_path: &Path) -> Result<Option<(DomainId, AgentState)>> {
+        let Some(domain) = self
+            .domains
+            .iter()
+            .find(|(_, orch_dir)| status_path == orch_dir.join(STATUS_FILE))
+            .map(|(domain, _)| domain.clone())
+        else {
+            return Ok(None);
+        };
+        let json = std::fs::read_to_string(status_path)
+            .with_context(|| format!("reading agent status: {}", status_path.display()))?;
+        let state = self.status.record(&domain, &json, &|notice, stem| {
+            self.deliver_notice(notice, stem)
+        })?;
+        Ok(state.map(|state| (domain, state)))
+    }
+
+    /// Domains whose agents last reported `idle` and are not stale.
+    pub fn idle_agents(&self) -> Vec<DomainId> {
+        self.agents.idle()
+    }
+
+    /// Mark agents whose heartbeat is older than `timeout` as stale,
+    /// log an `agent_stale` event for each, and notify the peers
+    /// blocked on them. Returns the newly stale domains.
+    pub fn check_heartbeats(&self, timeout: chrono::Duration) -> Result<Vec<DomainId>> {
+        self.status
+            .check_heartbeats(chrono::Utc::now(), timeout, &|notice, stem| {
+                self.deliver_notice(notice, stem)
+            })
+    }
+
+    /// Record the patterns in a `subscribe` message and push any
+    /// already-published artifacts that match them.
+    fn register_subscriptions(&self, message: &Message) -> Result<()> {
+        for pattern in &message.artifacts {
+            glob::Pattern::new(pattern)
+                .with_context(|| format!("invalid subscription pattern `{}`", pattern))?;
+
+            let subscription = Subscription {
+                subscriber: message.from.clone(),
+                producer: message.to.clone(),
+                artifact: pattern.clone(),
+            };
+            if !self.subscriptions.subscribe(subscription.clone())? {
+                continue;
+            }
+
+            tracing::info!(
+                subscriber = %message.from,
+                producer = %message.to,
+                artifact = %pattern,
+                "registered artifact subscription"
+            );
+
+            let producer_manifest = self.artifact_store.manifest(message.to.as_str())?;
+            for name in self.artifact_store.list(message.to.as_str())? {
+                if subscription.matches(&message.to, &name)
+                    && !producer_manifest.artifacts.contains_key(&name)
+                {
+                    self.push_artifact(&message.to, &message.from, &name)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a comm-node generated message into its target's inbox.
+    ///
+    /// The file is named `<stem>-<timestamp>` with the extension of the
+    /// target's inbox format.
+    pub fn deliver_notice(&self, message: &Message, stem: &str) -> Result<()> {
+        let format = self
+            .inbox_formats
+            .get(&message.to)
+            .copied()
+            .unwrap_or_default();
+        let orch_dir = self
+            .domains
+            .get(&message.to)
+            .with_context(|| format!("unknown target domain: {}", message.to))?;
+        let file_name = format!(
+            "{}-{}.{}",
+            stem,
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+            format.extension()
+        );
+        std::fs::write(
+            orch_dir.join("inbox").join(file_name),
+            message.render(format)?,
+        )?;
+        Ok(())
+    }
+
+    /// Map a path under some domain's `artifacts/` directory to the
+    /// domain and `/`-separated artifact name.
+    fn resolve_artifact(&self, path: &Path) -> Option<(DomainId, String)> {
+        self.domains.iter().find_map(|(domain_id, orch_dir)| {
+            let relative = path.strip_prefix(orch_dir.join("artifacts")).ok()?;
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_str())
+                .collect::<Option<Vec<_>>>()?
+                .join("/");
+            Some((domain_id.clone(), name))
+        })
+    }
+
+    /// Format of an outbox file, judged by its extension.
+    fn source_format(path: &Path) -> InboxFormat {
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        if is_json {
+            InboxFormat::Json
+        } else {
+            InboxFormat::Markdown
+        }
+    }
+
     /// Reverse-lookup which domain's outbox a file lives in.
     fn resolve_source_domain(&self, path: &Path) -> Result<DomainId> {
         for (domain_id, orch_dir) in &self.domains {
@@ -154,41 +694,337 @@ impl Router {
     }
 
     /// Route artifacts referenced in the message from source to target domain.
+    ///
+    /// A reference naming a directory, or a glob such as `proto/*.proto`,
+    /// is routed as one bundle. All references are validated and
+    /// expanded, and all sizes checked against the target's limits,
+    /// before anything is copied. An unsafe name (or a symlink escape
+    /// detected by the store) fails the route and is logged as a
+    /// `security_violation` event; artifacts over the target's limits
+    /// fail it with a `quota_exceeded` event and a rejection reply to
+    /// the sender.
     fn route_artifacts(&self, message: &Message) -> Result<()> {
-        for artifact_name in &message.artifacts {
-            let content = self
-                .artifact_store
-                .retrieve(message.from.as_str(), artifact_name)
+        let source_dir = self.domains[&message.from].join("artifacts");
+        let mut references = Vec::with_capacity(message.artifacts.len());
+        for reference in &message.artifacts {
+            let reference = reference.trim_end_matches('/');
+            // Confining (not just validating) catches a reference that
+            // reaches through a symlink, which listing would silently skip.
+            if let Err(e) = artifact::confine(&source_dir, reference) {
+                self.log_security_violation(message, &e);
+                return Err(e);
+            }
+            references.push(reference);
+        }
+
+        let available = self.artifact_store.list(message.from.as_str())?;
+        let mut routes = Vec::with_capacity(references.len());
+        for reference in references {
+            let members = bundle::expand(reference, &available)
+                .with_context(|| format!("resolving artifacts from domain `{}`", message.from))?;
+            routes.push((reference, members));
+        }
+
+        let names: Vec<&str> = routes
+            .iter()
+            .flat_map(|(reference, members)| match members {
+                Some(members) => members.iter().map(String::as_str).collect(),
+                None => vec![*reference],
+            })
+            .collect();
+        if let Err(e) = self.check_artifact_limits(message, &names) {
+            self.log_security_violation(message, &e);
+            self.reject_over_quota(&message.from, &message.to, &message.task, &e);
+            return Err(e);
+        }
+
+        for (reference, members) in &routes {
+            let routed = match members {
+                Some(members) => self.route_bundle(message, reference, members),
+                None => self.route_artifact(message, reference),
+            };
+            if let Err(e) = routed {
+                self.log_security_violation(message, &e);
+                self.reject_over_quota(&message.from, &message.to, &message.task, &e);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the sizes of artifacts, as they sit in the sender's
+    /// `artifacts/`, against the target domain's limits.
+    fn check_artifact_limits(&self, message: &Message, names: &[&str]) -> Result<()> {
+        let source_dir = self.domains[&message.from].join("artifacts");
+        let mut incoming = Vec::with_capacity(names.len());
+        for &artifact_name in names {
+            let path = artifact::confine(&source_dir, artifact_name)?;
+            let size = std::fs::metadata(&path)
                 .with_context(|| {
                     format!(
-                        "retrieving artifact `{}` from domain `{}`",
+                        "reading artifact `{}` from domain `{}`",
                         artifact_name, message.from
                     )
-                })?;
+                })?
+                .len();
+            incoming.push((artifact_name, size));
+        }
+        self.artifact_store
+            .check_limits(message.to.as_str(), &incoming)
+    }
 
-            self.artifact_store
-                .store(message.to.as_str(), artifact_name, &content)
-                .with_context(|| {
-                    format!(
-                        "storing artifact `{}` to domain `{}`",
-                        artifact_name, message.to
-                    )
-                })?;
+    /// Copy a single artifact from the source to the target domain.
+    fn route_artifact(&self, message: &Message, artifact_name: &str) -> Result<()> {
+        let to = &message.to;
+        let old = if contract::is_candidate(artifact_name) {
+            self.previous_content(to, artifact_name)?
+        } else {
+            None
+        };
 
-            tracing::info!(
-                artifact = %artifact_name,
-                from = %message.from,
-                to = %message.to,
-                "routed artifact"
-            );
+        let version = self
+            .artifact_store
+            .copy(
+                message.from.as_str(),
+                message.to.as_str(),
+                artifact_name,
+                &Provenance::new(message.from.as_str(), &message.task),
+            )
+            .with_context(|| {
+                format!(
+                    "copying artifact `{}` from domain `{}` to domain `{}`",
+                    artifact_name, message.from, message.to
+                )
+            })?;
+
+        tracing::info!(
+            artifact = %artifact_name,
+            from = %message.from,
+            to = %message.to,
+            version = version.version,
+            "routed artifact"
+        );
+        self.log_artifact_routed(message, artifact_name, version.version, &[]);
+        self.record_publications(message, &[artifact_name.to_string()]);
+
+        if let Some(old) = old {
+            self.notify_contract_change(message, artifact_name, version.version, &old)?;
         }
         Ok(())
     }
 
-    /// Detect completion signal filenames like `completion-bd-XXX.md`.
-    /// Returns the task ID (e.g. `bd-XXX`) if matched.
+    /// Copy a set of artifacts from the source to the target domain as
+    /// one bundle, recorded under `bundle`.
+    fn route_bundle(&self, message: &Message, bundle: &str, members: &[String]) -> Result<()> {
+        let to = &message.to;
+        let mut olds = Vec::new();
+        for name in members.iter().filter(|name| contract::is_candidate(name)) {
+            if let Some(old) = self.previous_content(to, name)? {
+                olds.push((name, old));
+            }
+        }
+
+        let version = self
+            .artifact_store
+            .copy_bundle(
+                message.from.as_str(),
+                message.to.as_str(),
+                bundle,
+                members,
+                &Provenance::new(message.from.as_str(), &message.task),
+            )
+            .with_context(|| {
+                format!(
+                    "copying bundle `{}` from domain `{}` to domain `{}`",
+                    bundle, message.from, message.to
+                )
+            })?;
+
+        tracing::info!(
+            bundle = %bundle,
+            from = %message.from,
+            to = %message.to,
+            version = version.version,
+            members = members.len(),
+            "routed artifact bundle"
+        );
+        self.log_artifact_routed(message, bundle, version.version, members);
+        self.record_publications(message, members);
+
+        for (name, old) in olds {
+            self.notify_contract_change(message, name, version.members[name], &old)?;
+        }
+        Ok(())
+    }
+
+    /// Record the checksums `message` delivered as its sender's latest
+    /// publications, covering content the watcher never saw change.
+    fn record_publications(&self, message: &Message, names: &[String]) {
+        let recorded = self
+            .artifact_store
+            .manifest(message.to.as_str())
+            .and_then(|manifest| {
+                for name in names {
+                    if let Some(entry) = manifest.artifacts.get(name) {
+                        self.acks
+                            .record_publication(message.from.as_str(), name, &entry.sha256)?;
+                    }
+                }
+                Ok(())
+            });
+        if let Err(e) = recorded {
+            tracing::error!(error = %e, "failed to record artifact publications");
+        }
+    }
+
+    /// Write an `artifact_routed` event for an artifact or bundle
+    /// copied along with `message`.
+    fn log_artifact_routed(&self, message: &Message, name: &str, version: u32, members: &[String]) {
+        let event = Event::new(EventKind::ArtifactRouted(ArtifactRouted {
+            from: message.from.to_string(),
+            to: message.to.to_string(),
+            task: message.task.clone(),
+            artifact: name.to_string(),
+            version,
+            members: members.to_vec(),
+        }));
+        if let Err(e) = self.event_log.log(&event) {
+            tracing::error!(error = %e, "failed to log artifact routed event");
+        }
+    }
+
+    /// Tell the consumer what changed structurally in a republished
+    /// contract, now at `version`, compared with its `old` content.
+    fn notify_contract_change(
+        &self,
+        message: &Message,
+        artifact_name: &str,
+        version: u32,
+        old: &[u8],
+    ) -> Result<()> {
+        let to = &message.to;
+        let too_large = self
+            .artifact_store
+            .manifest(to.as_str())?
+            .artifacts
+            .get(artifact_name)
+            .is_some_and(|entry| entry.size_bytes > MAX_DIFF_BYTES);
+        if too_large {
+            return Ok(());
+        }
+        let content = self.artifact_store.retrieve(to.as_str(), artifact_name)?;
+        if let Some(diff) = contract::diff(artifact_name, old, &content) {
+            let notice = Message {
+                from: message.from.clone(),
+                to: to.clone(),
+                msg_type: "contract_changed".to_string(),
+                task: message.task.clone(),
+                priority: if diff.is_breaking() { "high" } else { "medium" }.to_string(),
+                artifacts: vec![artifact_name.to_string()],
+                body: format!(
+                    "Artifact `{}` from `{}` is now v{}.\n\n{}",
+                    artifact_name,
+                    message.from,
+                    version,
+                    diff.summary()
+                ),
+            };
+            self.deliver_notice(&notice, &format!("contract_changed-v{}", version))?;
+            self.log_contract_change(&message.from, to, artifact_name, version, &diff);
+        }
+        Ok(())
+    }
+
+    /// Log a `security_violation` event if `error` stems from an unsafe
+    /// artifact name. Other errors are left to the caller.
+    fn log_security_violation(&self, message: &Message, error: &anyhow::Error) {
+        let Some(violation) = error.downcast_ref::<UnsafeArtifactName>() else {
+            return;
+        };
+
+        tracing::warn!(
+            from = %message.from,
+            to = %message.to,
+            artifact = %violation.name,
+            reason = violation.reason,
+            "rejected unsafe artifact name"
+        );
+
+        let event = Event::new(EventKind::SecurityViolation(SecurityViolation {
+            from: message.from.to_string(),
+            to: message.to.to_string(),
+            task: message.task.clone(),
+            artifact: violation.name.clone(),
+            reason: violation.reason.to_string(),
+        }));
+
+        if let Err(e) = self.event_log.log(&event) {
+            tracing::error!(error = %e, "failed to log security violation event");
+        }
+    }
+
+    /// If `error` stems from a size limit, log a `quota_exceeded` event
+    /// and tell `sender` its artifact was rejected. Returns whether it did.
+    fn reject_over_quota(
+        &self,
+        sender: &DomainId,
+        target: &DomainId,
+        task: &str,
+        error: &anyhow::Error,
+    ) -> bool {
+        let Some(exceeded) = error.downcast_ref::<QuotaExceeded>() else {
+            return false;
+        };
+
+        tracing::warn!(
+            from = %sender,
+            to = %target,
+            artifact = %exceeded.name,
+            limit = ?exceeded.limit,
+            limit_bytes = exceeded.limit_bytes,
+            requested_bytes = exceeded.requested_bytes,
+            "rejected artifact over size limit"
+        );
+
+        let event = Event::new(EventKind::QuotaExceeded(event_kind::QuotaExceeded {
+            from: sender.to_string(),
+            to: target.to_string(),
+            task: task.to_string(),
+            artifact: exceeded.name.clone(),
+            limit: exceeded.limit,
+            limit_bytes: exceeded.limit_bytes,
+            requested_bytes: exceeded.requested_bytes,
+        }));
+        if let Err(e) = self.event_log.log(&event) {
+            tracing::error!(error = %e, "failed to log quota exceeded event");
+        }
+
+        let reply = Message {
+            from: target.clone(),
+            to: sender.clone(),
+            msg_type: "quota_exceeded".to_string(),
+            task: task.to_string(),
+            priority: "high".to_string(),
+            artifacts: vec![exceeded.name.clone()],
+            body: format!(
+                "Artifact `{}` was not delivered to `{}`: {}. \
+                 Shrink or split the artifact, or raise the limit in comm-node.toml.",
+                exceeded.name, target, exceeded
+            ),
+        };
+        if let Err(e) = self.deliver_notice(&reply, "quota_exceeded") {
+            tracing::error!(error = %e, "failed to deliver quota rejection");
+        }
+        true
+    }
+
+    /// Detect completion signal filenames like `completion-bd-XXX.md`
+    /// (or `.json`). Returns the task ID (e.g. `bd-XXX`) if matched.
     fn parse_completion_signal(filename: &str) -> Option<String> {
-        let stem = filename.strip_suffix(".md")?;
+        let (stem, ext) = filename.rsplit_once('.')?;
+        if !ext.eq_ignore_ascii_case("md") && !ext.eq_ignore_ascii_case("json") {
+            return None;
+        }
         let task_id = stem.strip_prefix("completion-")?;
         if task_id.is_empty() {
             return None;
@@ -213,21 +1049,34 @@ impl Router {
         Ok(())
     }
 
+    /// Write a `message_rejected` event for a message that failed to route.
+    fn log_rejection(&self, message: &Message, message_id: &MessageId, error: &anyhow::Error) {
+        let event = Event::new(EventKind::MessageRejected(MessageRejected {
+            message_id: message_id.clone(),
+            from: message.from.to_string(),
+            to: message.to.to_string(),
+            msg_type: message.msg_type.clone(),
+            task: message.task.clone(),
+            reason: format!("{:#}", error),
+        }));
+        if let Err(e) = self.event_log.log(&event) {
+            tracing::error!(error = %e, "failed to log message rejection event");
+        }
+    }
+
     /// Write a routing event to the event log.
-    fn log_routing_event(&self, message: &Message, size_bytes: usize) {
-        let event = Event {
-            timestamp: chrono::Utc::now(),
-            kind: "message_routed".to_string(),
-            payload: serde_json::json!({
-                "from": message.from.as_str(),
-                "to": message.to.as_str(),
-                "type": message.msg_type,
-                "task": message.task,
-                "priority": message.priority,
-                "artifacts": message.artifacts,
-                "size_bytes": size_bytes,
-            }),
-        };
+    fn log_routing_event(&self, message: &Message, message_id: &MessageId, size_bytes: usize) {
+        let event = Event::new(EventKind::MessageRouted(MessageRouted {
+            message_id: Some(message_id.clone()),
+            from: message.from.to_string(),
+            to: message.to.to_string(),
+            msg_type: message.msg_type.clone(),
+            task: message.task.clone(),
+            priority: message.priority.clone(),
+            artifacts: message.artifacts.clone(),
+            size_bytes,
+            body: message.body.clone(),
+        }));
 
         if let Err(e) = self.event_log.log(&event) {
             tracing::error!(error = %e, "failed to log routing event");
@@ -235,21 +1084,192 @@ impl Router {
     }
 
     /// Parse a message file into a Message struct.
+    ///
+    /// `.json` files are parsed as a single JSON object carrying the
+    /// message fields plus `body`. Everything else must open with a
+    /// YAML (`---`) or TOML (`+++`) frontmatter block.
     fn parse(path: &Path) -> Result<Message> {
-        let content = std::fs::read_to_string(path)?;
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("reading message: {}", path.display()))?;
+
+        let message = if Self::source_format(path) == InboxFormat::Json {
+            frontmatter::from_json::<JsonMessage>(&content).map(JsonMessage::into_message)
+        } else {
+            frontmatter::extract(&content).and_then(|fm| {
+                let mut message: Message = fm.deserialize()?;
+                message.body = fm.body.to_string();
+                Ok(message)
+            })
+        };
+
+        message.map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+}
 
-        // Split YAML frontmatter from body.
-        let parts: Vec<&str> = content.splitn(3, "---").collect();
-        if parts.len() < 3 {
-            bail!("message missing YAML frontmatter: {}", path.display());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::FsArtifactStore;
+    use crate::config::ProjectConfig;
+    use crate::event::{EventQuery, FileEventLog};
+
+    /// A router over scratch domains `a` and `b`, removed on drop.
+    struct Fixture {
+        dir: PathBuf,
+        router: Router,
+        event_log: Arc<FileEventLog>,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "comm-node-router-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            let mut domains = HashMap::new();
+            let mut roots = HashMap::new();
+            for domain in ["a", "b"] {
+                let orch_dir = dir.join(domain).join(".orchestrator");
+                std::fs::create_dir_all(orch_dir.join("artifacts")).unwrap();
+                std::fs::create_dir_all(orch_dir.join("inbox")).unwrap();
+                domains.insert(DomainId::new(domain), orch_dir);
+                roots.insert(domain.to_string(), dir.join(domain));
+            }
+
+            let config: ProjectConfig = toml::from_str("[domains]").unwrap();
+            let event_log = Arc::new(FileEventLog::new(dir.join("event.log")));
+            let router = Router::new(
+                domains,
+                HashMap::new(),
+                Arc::new(FsArtifactStore::new(roots)),
+                event_log.clone(),
+                Arc::new(SubscriptionRegistry::load(&config, dir.join("subs.json")).unwrap()),
+                Arc::new(AckRegistry::load(dir.join("acks.json")).unwrap()),
+                Arc::new(AgentRegistry::new()),
+            );
+            Self {
+                dir,
+                router,
+                event_log,
+            }
         }
 
-        let frontmatter = parts[1].trim();
-        let body = parts[2].trim().to_string();
+        fn message(&self, artifacts: &[&str]) -> Message {
+            Message {
+                from: DomainId::new("a"),
+                to: DomainId::new("b"),
+                msg_type: "handoff".to_string(),
+                task: "t1".to_string(),
+                priority: String::new(),
+                artifacts: artifacts.iter().map(|a| a.to_string()).collect(),
+                body: String::new(),
+            }
+        }
+
+        /// The `artifact` field of every logged `security_violation`.
+        fn violations(&self) -> Vec<String> {
+            let query = EventQuery {
+                kinds: ["security_violation".to_string()].into(),
+                ..Default::default()
+            };
+            self.event_log
+                .query(&query)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.event.payload["artifact"].as_str().unwrap().to_string())
+                .collect()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
 
-        let mut message: Message = serde_yaml::from_str(frontmatter)?;
-        message.body = body;
+    #[test]
+    fn logs_a_security_violation_for_an_unsafe_name() {
+        let fixture = Fixture::new("unsafe-name");
+        let message = fixture.message(&["../secrets.env"]);
 
-        Ok(message)
+        let err = fixture.router.route_artifacts(&message).unwrap_err();
+        assert!(err.downcast_ref::<UnsafeArtifactName>().is_some());
+        assert_eq!(fixture.violations(), ["../secrets.env"]);
+        assert!(!fixture.dir.join("b/.orchestrator/secrets.env").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn logs_a_security_violation_for_a_symlink_escape() {
+        let fixture = Fixture::new("symlink");
+        let outside = fixture.dir.join("outside");
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("secret"), "hunter2").unwrap();
+        std::os::unix::fs::symlink(&outside, fixture.dir.join("a/.orchestrator/artifacts/leak"))
+            .unwrap();
+        let message = fixture.message(&["leak/secret"]);
+
+        let err = fixture.router.route_artifacts(&message).unwrap_err();
+        assert!(err.downcast_ref::<UnsafeArtifactName>().is_some());
+        assert_eq!(fixture.violations(), ["leak/secret"]);
+        assert!(!fixture
+            .dir
+            .join("b/.orchestrator/artifacts/leak/secret")
+            .exists());
+    }
+
+    #[test]
+    fn routes_a_safe_artifact_without_a_violation() {
+        let fixture = Fixture::new("safe");
+        std::fs::write(
+            fixture.dir.join("a/.orchestrator/artifacts/spec.md"),
+            "# Spec",
+        )
+        .unwrap();
+        let message = fixture.message(&["spec.md"]);
+
+        fixture.router.route_artifacts(&message).unwrap();
+        assert!(fixture.violations().is_empty());
+        assert_eq!(
+            std::fs::read_to_string(fixture.dir.join("b/.orchestrator/artifacts/spec.md")).unwrap(),
+            "# Spec"
+        );
+    }
+
+    #[test]
+    fn records_routed_checksums_as_publications() {
+        let fixture = Fixture::new("publications");
+        std::fs::write(
+            fixture.dir.join("a/.orchestrator/artifacts/spec.md"),
+            "# Spec",
+        )
+        .unwrap();
+
+        fixture
+            .router
+            .route_artifacts(&fixture.message(&["spec.md"]))
+            .unwrap();
+        let published = fixture.router.acks.publications("a", "spec.md");
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].sha256, artifact::sha256_hex(b"# Spec"));
+    }
+
+    #[test]
+    fn completion_signal_extension_is_case_insensitive() {
+        for name in [
+            "completion-bd-7.md",
+            "completion-bd-7.MD",
+            "completion-bd-7.Json",
+        ] {
+            assert_eq!(
+                Router::parse_completion_signal(name).as_deref(),
+                Some("bd-7")
+            );
+        }
+        assert_eq!(Router::parse_completion_signal("completion-bd-7.txt"), None);
+        assert_eq!(Router::parse_completion_signal("completion-.md"), None);
+        assert_eq!(Router::parse_completion_signal("notes-bd-7.md"), None);
     }
 }
@@ -161,6 +161,8 @@ Other domains in this project (communicate via outbox only):
 ```
 .orchestrator/
   artifacts/      # Cross-domain work products (read)
+  artifact_versions/  # Version history of received artifacts (comm-node managed)
+  manifest.json   # Checksums and provenance of received artifacts (read)
   inbox/          # Incoming messages from comm-node (read, delete after processing)
   outbox/         # Outgoing messages to comm-node (write)
   registry.json   # Peer discovery — all domain names and descriptions (read)
@@ -175,7 +177,7 @@ Other domains in this project (communicate via outbox only):
 | `.orchestrator/inbox/*.md` | comm-node | Messages from other agents |
 | `.orchestrator/registry.json` | comm-node | Peer domain names and descriptions |
 | `.orchestrator/PROTOCOL.md` | comm-node | Communication rules and format reference |
-| `.orchestrator/artifacts/*` | comm-node | Cross-domain work products |
+| `.orchestrator/artifacts/*` | comm-node | Cross-domain work products (may be read-only; copy before editing) |
 
 ## What You Write
 
@@ -194,7 +196,7 @@ All outbox messages **must** include YAML frontmatter:
 ---
 from: {domain_name}
 to: <target-domain>
-type: artifact_ready | blocked | question | completion | status
+type: artifact_ready | blocked | question | completion | status | subscribe | ack
 task: bd-XXX
 priority: high | medium | low
 artifacts: []
@@ -217,6 +219,11 @@ Human-readable message body. Keep concise.
 ```
 
 Update `status.json` whenever you start a task, finish a task, or become blocked.
+While working, refresh `last_heartbeat` regularly: an agent whose heartbeat
+falls behind is reported as stale. Set `blocked_on` to the domain you are
+waiting for, so you are told if it stops responding; it is required when
+`status` is `blocked`. An update that does not match this schema is rejected
+with a `type: status_invalid` inbox message, and your previous status is kept.
 
 ## Reference
 
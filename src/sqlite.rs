@@ -0,0 +1,472 @@
+//! SQLite-backed event log and lock store.
+//!
+//! Both live in one embedded database in the state directory
+//! (`comm-node.db`). Events are indexed by kind, time, task, message id
+//! and the domains their payload names, and message bodies are
+//! searchable through an FTS5 table.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, TransactionBehavior};
+use uuid::Uuid;
+
+use crate::event::{
+    verify_chain, ChainHead, ChainReport, ChainStart, Event, EventLog, EventQuery, LoggedEvent,
+    GENESIS_HASH,
+};
+use crate::lock::{FileLock, LockStore};
+use crate::types::DomainId;
+
+/// Schema version stored in `PRAGMA user_version`.
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    seq        INTEGER PRIMARY KEY AUTOINCREMENT,
+    id         TEXT NOT NULL,
+    timestamp  TEXT NOT NULL,
+    ts_micros  INTEGER NOT NULL,
+    kind       TEXT NOT NULL,
+    task       TEXT,
+    message_id TEXT,
+    payload    TEXT NOT NULL,
+    prev_hash  TEXT
+);
+CREATE INDEX IF NOT EXISTS events_kind_ts ON events (kind, ts_micros);
+CREATE INDEX IF NOT EXISTS events_ts ON events (ts_micros);
+CREATE INDEX IF NOT EXISTS events_task ON events (task);
+CREATE INDEX IF NOT EXISTS events_message_id ON events (message_id);
+
+CREATE TABLE IF NOT EXISTS event_domains (
+    domain TEXT NOT NULL,
+    seq    INTEGER NOT NULL REFERENCES events (seq),
+    PRIMARY KEY (domain, seq)
+) WITHOUT ROWID;
+
+CREATE VIRTUAL TABLE IF NOT EXISTS event_text USING fts5 (body);
+
+CREATE TABLE IF NOT EXISTS chain_head (
+    id              INTEGER PRIMARY KEY CHECK (id = 1),
+    seq             INTEGER NOT NULL,
+    hash            TEXT NOT NULL,
+    expired_through INTEGER NOT NULL DEFAULT 0,
+    expired_hash    TEXT
+);
+
+CREATE TABLE IF NOT EXISTS locks (
+    path        TEXT PRIMARY KEY,
+    holder      TEXT NOT NULL,
+    acquired_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS lock_snapshot (
+    id       INTEGER PRIMARY KEY CHECK (id = 1),
+    saved_at TEXT NOT NULL
+);
+";
+
+/// Columns read back into a `LoggedEvent`.
+const SELECT_EVENTS: &str = "SELECT seq, id, timestamp, kind, payload, prev_hash FROM events";
+
+/// Open (creating if needed) the database at `path`.
+fn connect(path: &Path) -> Result<Connection> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("creating {}", parent.display()))?;
+    }
+    let conn =
+        Connection::open(path).with_context(|| format!("opening database: {}", path.display()))?;
+    conn.busy_timeout(std::time::Duration::from_secs(5))?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "database {} has schema version {}, newer than this comm-node supports ({})",
+            path.display(),
+            version,
+            SCHEMA_VERSION
+        );
+    }
+    conn.execute_batch(SCHEMA)
+        .with_context(|| format!("creating schema in {}", path.display()))?;
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    Ok(conn)
+}
+
+/// Event log stored in SQLite.
+pub struct SqliteEventLog {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteEventLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            conn: Mutex::new(connect(path)?),
+        })
+    }
+
+    /// Copy events from another log, keeping their sequence numbers,
+    /// ids, timestamps and chain hashes. Events already present (same
+    /// sequence number and id) are skipped, so an interrupted import can
+    /// be re-run; a different event under the same sequence number fails
+    /// the whole import. An empty database takes over `start`, where the
+    /// source's chain begins. Returns the number of events added.
+    pub fn import(&self, events: &[LoggedEvent], start: &ChainStart) -> Result<usize> {
+        let mut conn = self.conn.lock().expect("event database lock poisoned");
+        let tx = conn.transaction()?;
+        let was_empty = head(&tx)?.is_none();
+        let mut imported = 0;
+        for logged in events {
+            let existing: Option<String> = tx
+                .query_row(
+                    "SELECT id FROM events WHERE seq = ?1",
+                    params![logged.seq as i64],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            match existing {
+                Some(id) if id == logged.id.to_string() => continue,
+                Some(id) => anyhow::bail!(
+                    "the database already holds a different event {} ({}, importing {}); \
+                     import into a new database",
+                    logged.seq,
+                    id,
+                    logged.id
+                ),
+                None => {}
+            }
+            insert(&tx, logged)?;
+            imported += 1;
+        }
+        if let Some(last) = events.last() {
+            let head = head(&tx)?;
+            if head.as_ref().is_none_or(|h| h.seq < last.seq) {
+                set_head(&tx, last)?;
+            }
+        }
+        if was_empty && start.expired_through > 0 {
+            set_start(&tx, start)?;
+        }
+        tx.commit()?;
+        Ok(imported)
+    }
+}
+
+/// Where the chain starts, as carried over by `import`.
+fn start(conn: &Connection) -> Result<ChainStart> {
+    Ok(conn
+        .query_row(
+            "SELECT expired_through, expired_hash FROM chain_head WHERE id = 1",
+            [],
+            |row| {
+                Ok(ChainStart {
+                    expired_through: row.get::<_, i64>(0)? as u64,
+                    expired_hash: row.get(1)?,
+                })
+            },
+        )
+        .optional()?
+        .unwrap_or_default())
+}
+
+/// Record where the chain starts. With no events yet, the last expired
+/// one also becomes the head, so that logging continues after it.
+fn set_start(conn: &Connection, start: &ChainStart) -> Result<()> {
+    conn.execute(
+        "INSERT INTO chain_head (id, seq, hash, expired_through, expired_hash)
+         VALUES (1, ?1, ?2, ?1, ?3)
+         ON CONFLICT (id) DO UPDATE
+             SET expired_through = excluded.expired_through, expired_hash = excluded.expired_hash",
+        params![
+            start.expired_through as i64,
+            start.expired_hash.as_deref().unwrap_or(GENESIS_HASH),
+            start.expired_hash,
+        ],
+    )?;
+    Ok(())
+}
+
+/// The recorded newest event.
+fn head(conn: &Connection) -> Result<Option<ChainHead>> {
+    Ok(conn
+        .query_row("SELECT seq, hash FROM chain_head WHERE id = 1", [], |row| {
+            Ok(ChainHead {
+                seq: row.get::<_, i64>(0)? as u64,
+                hash: row.get(1)?,
+            })
+        })
+        .optional()?)
+}
+
+fn set_head(conn: &Connection, logged: &LoggedEvent) -> Result<()> {
+    conn.execute(
+        "INSERT INTO chain_head (id, seq, hash) VALUES (1, ?1, ?2)
+         ON CONFLICT (id) DO UPDATE SET seq = excluded.seq, hash = excluded.hash",
+        params![logged.seq as i64, logged.hash()?],
+    )?;
+    Ok(())
+}
+
+/// Insert one event under its own sequence number.
+fn insert(conn: &Connection, logged: &LoggedEvent) -> Result<()> {
+    let event = &logged.event;
+    let seq = logged.seq as i64;
+    conn.execute(
+        "INSERT INTO events
+             (seq, id, timestamp, ts_micros, kind, task, message_id, payload, prev_hash)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            seq,
+            logged.id.to_string(),
+            event.timestamp.to_rfc3339(),
+            event.timestamp.timestamp_micros(),
+            event.kind,
+            event.field("task"),
+            event.field("message_id"),
+            serde_json::to_string(&event.payload)?,
+            logged.prev_hash,
+        ],
+    )?;
+
+    for domain in event.domains() {
+        conn.execute(
+            "INSERT OR IGNORE INTO event_domains (domain, seq) VALUES (?1, ?2)",
+            params![domain, seq],
+        )?;
+    }
+    if let Some(body) = event.field("body") {
+        conn.execute(
+            "INSERT INTO event_text (rowid, body) VALUES (?1, ?2)",
+            params![seq, body],
+        )?;
+    }
+    Ok(())
+}
+
+/// Quote each word of a search so FTS5 treats it literally.
+fn fts_query(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl EventLog for SqliteEventLog {
+    fn log(&self, event: &Event) -> Result<()> {
+        let mut conn = self.conn.lock().expect("event database lock poisoned");
+        // Take the write lock up front so the chain head can't move underneath us.
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let last = select_events(
+            &tx,
+            &format!("{} ORDER BY seq DESC LIMIT 1", SELECT_EVENTS),
+            Vec::new(),
+        )?
+        .pop();
+        // An empty log continues after the events an import left expired.
+        let (seq, prev_hash) = match &last {
+            Some(e) => (e.seq + 1, e.hash()?),
+            None => {
+                let start = start(&tx)?;
+                let prev_hash = start
+                    .expired_hash
+                    .unwrap_or_else(|| GENESIS_HASH.to_string());
+                (start.expired_through + 1, prev_hash)
+            }
+        };
+        let logged = LoggedEvent {
+            seq,
+            id: Uuid::new_v4(),
+            prev_hash: Some(prev_hash),
+            event: event.clone(),
+        };
+        insert(&tx, &logged)?;
+        set_head(&tx, &logged)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn query(&self, query: &EventQuery) -> Result<Vec<LoggedEvent>> {
+        let mut clauses: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if !query.kinds.is_empty() {
+            let placeholders = vec!["?"; query.kinds.len()].join(", ");
+            clauses.push(format!("kind IN ({})", placeholders));
+            values.extend(query.kinds.iter().cloned().map(Value::Text));
+        }
+        if let Some(after) = query.after_seq {
+            clauses.push("seq > ?".to_string());
+            values.push(Value::Integer(after.min(i64::MAX as u64) as i64));
+        }
+        if let Some(since) = query.since {
+            clauses.push("ts_micros >= ?".to_string());
+            values.push(Value::Integer(since.timestamp_micros()));
+        }
+        if let Some(until) = query.until {
+            clauses.push("ts_micros < ?".to_string());
+            values.push(Value::Integer(until.timestamp_micros()));
+        }
+        if let Some(domain) = &query.domain {
+            clauses.push("seq IN (SELECT seq FROM event_domains WHERE domain = ?)".to_string());
+            values.push(Value::Text(domain.clone()));
+        }
+        if let Some(task) = &query.task {
+            clauses.push("task = ?".to_string());
+            values.push(Value::Text(task.clone()));
+        }
+        if let Some(message_id) = &query.message_id {
+            clauses.push("message_id = ?".to_string());
+            values.push(Value::Text(message_id.clone()));
+        }
+        if let Some(text) = query.text.as_deref().filter(|t| !t.trim().is_empty()) {
+            clauses
+                .push("seq IN (SELECT rowid FROM event_text WHERE event_text MATCH ?)".to_string());
+            values.push(Value::Text(fts_query(text)));
+        }
+
+        let mut sql = SELECT_EVENTS.to_string();
+        if !clauses.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&clauses.join(" AND "));
+        }
+        sql.push_str(if query.reverse {
+            " ORDER BY seq DESC"
+        } else {
+            " ORDER BY seq ASC"
+        });
+        if let Some(limit) = query.limit {
+            sql.push_str(" LIMIT ?");
+            values.push(Value::Integer(limit.min(i64::MAX as usize) as i64));
+        }
+
+        let conn = self.conn.lock().expect("event database lock poisoned");
+        select_events(&conn, &sql, values)
+    }
+
+    fn verify(&self) -> Result<ChainReport> {
+        let (head, start) = {
+            let conn = self.conn.lock().expect("event database lock poisoned");
+            (head(&conn)?, start(&conn)?)
+        };
+        let events = self.query(&EventQuery::default())?;
+        verify_chain(
+            &events,
+            start.expired_through + 1,
+            start.expired_hash.as_deref(),
+            head.as_ref(),
+        )
+    }
+}
+
+/// Run a query built on `SELECT_EVENTS` and read back its events.
+fn select_events(conn: &Connection, sql: &str, values: Vec<Value>) -> Result<Vec<LoggedEvent>> {
+    let mut statement = conn.prepare(sql)?;
+    let rows = statement.query_map(params_from_iter(values), |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, Option<String>>(5)?,
+        ))
+    })?;
+
+    let mut events = Vec::new();
+    for row in rows {
+        let (seq, id, timestamp, kind, payload, prev_hash) = row?;
+        events.push(LoggedEvent {
+            seq: seq as u64,
+            id: id
+                .parse()
+                .with_context(|| format!("event {} has an invalid id", seq))?,
+            prev_hash,
+            event: Event {
+                timestamp: timestamp
+                    .parse::<DateTime<Utc>>()
+                    .with_context(|| format!("event {} has an invalid timestamp", seq))?,
+                kind,
+                payload: serde_json::from_str(&payload)
+                    .with_context(|| format!("event {} has an invalid payload", seq))?,
+            },
+        });
+    }
+    Ok(events)
+}
+
+/// Lock snapshots stored in SQLite.
+pub struct SqliteLockStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteLockStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            conn: Mutex::new(connect(path)?),
+        })
+    }
+}
+
+impl LockStore for SqliteLockStore {
+    fn save(&self, locks: &[&FileLock]) -> Result<()> {
+        let mut conn = self.conn.lock().expect("lock database lock poisoned");
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM locks", [])?;
+        for lock in locks {
+            tx.execute(
+                "INSERT INTO locks (path, holder, acquired_at) VALUES (?1, ?2, ?3)",
+                params![
+                    lock.path.to_string_lossy(),
+                    lock.holder.as_str(),
+                    lock.acquired_at.to_rfc3339(),
+                ],
+            )?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO lock_snapshot (id, saved_at) VALUES (1, ?1)",
+            params![Utc::now().to_rfc3339()],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Vec<FileLock>> {
+        let conn = self.conn.lock().expect("lock database lock poisoned");
+        let mut statement = conn.prepare("SELECT path, holder, acquired_at FROM locks")?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+
+        let mut locks = Vec::new();
+        for row in rows {
+            let (path, holder, acquired_at) = row?;
+            locks.push(FileLock {
+                acquired_at: acquired_at
+                    .parse()
+                    .with_context(|| format!("lock on {} has an invalid timestamp", path))?,
+                path: path.into(),
+                holder: DomainId::new(holder),
+            });
+        }
+        Ok(locks)
+    }
+
+    fn saved_at(&self) -> Result<Option<DateTime<Utc>>> {
+        let conn = self.conn.lock().expect("lock database lock poisoned");
+        let saved_at: Option<String> = conn
+            .query_row("SELECT saved_at FROM lock_snapshot", [], |row| row.get(0))
+            .optional()?;
+        saved_at
+            .map(|s| s.parse().context("lock snapshot has an invalid timestamp"))
+            .transpose()
+    }
+}
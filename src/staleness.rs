@@ -0,0 +1,361 @@
+//! Artifact acknowledgement and staleness tracking.
+//!
+//! The manifest records which version of each artifact a domain last
+//! received; a domain acknowledges the version it is building against
+//! with a `type: ack` message listing the artifacts. The registry also
+//! keeps the checksums each producer has published, so a domain whose
+//! acknowledged version trails the producer's current one is *behind*
+//! by every version it received since plus every publication not yet
+//! delivered, and once the first unacknowledged version it received has
+//! waited longer than the configured window it is *stale*.
+//! Acknowledgements and publications persist in the state directory.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::artifact::{write_atomic, ArtifactStore};
+
+/// Publications remembered per producer artifact; a consumer further
+/// behind than this is reported as this many versions behind.
+const MAX_PUBLICATIONS: usize = 64;
+
+/// The artifact version a domain has confirmed it uses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Acknowledgement {
+    pub version: u32,
+    pub acknowledged_at: DateTime<Utc>,
+}
+
+/// A version of an artifact its producer published.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Publication {
+    pub sha256: String,
+    pub published_at: DateTime<Utc>,
+}
+
+/// Persisted acknowledgement state, keyed by domain then artifact name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AckState {
+    #[serde(default)]
+    acks: BTreeMap<String, BTreeMap<String, Acknowledgement>>,
+    /// Latest received version each domain has been told is stale.
+    #[serde(default)]
+    notified: BTreeMap<String, BTreeMap<String, u32>>,
+    /// Checksums each producer has published, oldest first.
+    #[serde(default)]
+    published: BTreeMap<String, BTreeMap<String, Vec<Publication>>>,
+}
+
+/// Registry of artifact acknowledgements.
+pub struct AckRegistry {
+    state: Mutex<AckState>,
+    path: PathBuf,
+}
+
+impl AckRegistry {
+    /// Restore acknowledgements from `path` if it exists.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let state = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .with_context(|| format!("parsing acknowledgements: {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => AckState::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            state: Mutex::new(state),
+            path,
+        })
+    }
+
+    /// Record that `domain` uses `version` of `name`. Returns `false`
+    /// if that version (or a later one) was already acknowledged.
+    pub fn acknowledge(&self, domain: &str, name: &str, version: u32) -> Result<bool> {
+        let mut state = self.state.lock().expect("ack lock poisoned");
+        let acks = state.acks.entry(domain.to_string()).or_default();
+        if acks.get(name).is_some_and(|a| a.version >= version) {
+            return Ok(false);
+        }
+        acks.insert(
+            name.to_string(),
+            Acknowledgement {
+                version,
+                acknowledged_at: Utc::now(),
+            },
+        );
+        self.save(&state)?;
+        Ok(true)
+    }
+
+    /// The version of `name` that `domain` last acknowledged, if any.
+    pub fn acknowledged(&self, domain: &str, name: &str) -> Option<Acknowledgement> {
+        let state = self.state.lock().expect("ack lock poisoned");
+        state.acks.get(domain)?.get(name).cloned()
+    }
+
+    /// Remember that `domain` was told `version` of `name` is waiting.
+    /// Returns `false` if it already was.
+    pub fn mark_notified(&self, domain: &str, name: &str, version: u32) -> Result<bool> {
+        let mut state = self.state.lock().expect("ack lock poisoned");
+        let notified = state.notified.entry(domain.to_string()).or_default();
+        if notified.get(name).is_some_and(|&v| v >= version) {
+            return Ok(false);
+        }
+        notified.insert(name.to_string(), version);
+        self.save(&state)?;
+        Ok(true)
+    }
+
+    /// Record that `producer`'s copy of `name` now has checksum `sha256`.
+    /// Returns `false` if that was already its latest publication.
+    pub fn record_publication(&self, producer: &str, name: &str, sha256: &str) -> Result<bool> {
+        let mut state = self.state.lock().expect("ack lock poisoned");
+        let history = state
+            .published
+            .entry(producer.to_string())
+            .or_default()
+            .entry(name.to_string())
+            .or_default();
+        if history.last().is_some_and(|p| p.sha256 == sha256) {
+            return Ok(false);
+        }
+        history.push(Publication {
+            sha256: sha256.to_string(),
+            published_at: Utc::now(),
+        });
+        if history.len() > MAX_PUBLICATIONS {
+            history.drain(..history.len() - MAX_PUBLICATIONS);
+        }
+        self.save(&state)?;
+        Ok(true)
+    }
+
+    /// What `producer` has published of `name`, oldest first.
+    pub fn publications(&self, producer: &str, name: &str) -> Vec<Publication> {
+        let state = self.state.lock().expect("ack lock poisoned");
+        state
+            .published
+            .get(producer)
+            .and_then(|names| names.get(name))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn save(&self, state: &AckState) -> Result<()> {
+        write_atomic(&self.path, serde_json::to_string_pretty(state)?.as_bytes())
+    }
+}
+
+/// A domain whose acknowledged version of an artifact trails the
+/// producer's current one.
+#[derive(Debug, Clone, Serialize)]
+pub struct Lag {
+    pub domain: String,
+    pub artifact: String,
+    /// Domain that produced the latest received version.
+    pub producer: String,
+    /// Latest version received.
+    pub received: u32,
+    /// Latest version acknowledged, if any.
+    pub acknowledged: Option<u32>,
+    /// The producer's current version in the domain's numbering:
+    /// `received` plus each publication not delivered yet.
+    pub latest: u32,
+    /// Number of versions between the acknowledged one and `latest`.
+    pub behind: u32,
+    /// When the first unacknowledged version arrived, or was published
+    /// if it is still with the producer.
+    pub waiting_since: DateTime<Utc>,
+}
+
+impl Lag {
+    /// Whether a received version is unacknowledged, rather than only
+    /// one the producer has not delivered yet.
+    pub fn has_unacknowledged(&self) -> bool {
+        self.acknowledged.is_none_or(|v| v < self.received)
+    }
+
+    /// Whether the oldest unacknowledged version received has waited
+    /// longer than `window`.
+    pub fn is_stale(&self, now: DateTime<Utc>, window: chrono::Duration) -> bool {
+        self.has_unacknowledged() && now - self.waiting_since > window
+    }
+}
+
+/// Every artifact in `domains` whose acknowledged version trails the
+/// producer's current one, ordered by domain and artifact name.
+///
+/// Undelivered versions are those the producer published after the
+/// checksum in the domain's manifest; nothing is rehashed.
+pub fn lags<'a>(
+    store: &dyn ArtifactStore,
+    acks: &AckRegistry,
+    domains: impl IntoIterator<Item = &'a str>,
+) -> Result<Vec<Lag>> {
+    let now = Utc::now();
+    let mut lags = Vec::new();
+    for domain in domains {
+        for (name, entry) in store.manifest(domain)?.artifacts {
+            let published = acks.publications(&entry.producer, &name);
+            let delivered = published.iter().rposition(|p| p.sha256 == entry.sha256);
+            let pending = &published[delivered.map_or(0, |i| i + 1)..];
+            let latest = entry.version + pending.len() as u32;
+
+            let acknowledged = acks.acknowledged(domain, &name).map(|a| a.version);
+            let behind = latest.saturating_sub(acknowledged.unwrap_or(0));
+            if behind == 0 {
+                continue;
+            }
+
+            let first_unacked = acknowledged.unwrap_or(0) + 1;
+            let waiting_since = if first_unacked <= entry.version {
+                store
+                    .versions(domain, &name)?
+                    .into_iter()
+                    .find(|v| v.version >= first_unacked)
+                    .map_or(entry.routed_at, |v| v.stored_at)
+            } else {
+                pending.first().map_or(now, |p| p.published_at)
+            };
+
+            lags.push(Lag {
+                domain: domain.to_string(),
+                artifact: name,
+                producer: entry.producer,
+                received: entry.version,
+                acknowledged,
+                latest,
+                behind,
+                waiting_since,
+            });
+        }
+    }
+    lags.sort_by(|a, b| (&a.domain, &a.artifact).cmp(&(&b.domain, &b.artifact)));
+    Ok(lags)
+}
+
+/// Hex-encoded SHA-256 of `domain`'s current copy of `name`.
+pub(crate) fn current_hash(store: &dyn ArtifactStore, domain: &str, name: &str) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut store.open(domain, name)?, &mut hasher)
+        .with_context(|| format!("hashing {}/{}", domain, name))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::artifact::FsArtifactStore;
+    use crate::manifest::Provenance;
+
+    /// A store over scratch domains `a` (producer) and `b` (consumer),
+    /// removed on drop.
+    struct Fixture {
+        dir: PathBuf,
+        store: FsArtifactStore,
+        acks: AckRegistry,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "comm-node-staleness-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(dir.join("a/.orchestrator/artifacts")).unwrap();
+            let roots = ["a", "b"]
+                .into_iter()
+                .map(|d| (d.to_string(), dir.join(d)))
+                .collect::<HashMap<_, _>>();
+            let acks = AckRegistry::load(dir.join("acks.json")).unwrap();
+            Self {
+                store: FsArtifactStore::new(roots),
+                acks,
+                dir,
+            }
+        }
+
+        /// Write a new version of `spec.md` in the producer and record it.
+        fn publish(&self, content: &str) {
+            std::fs::write(self.dir.join("a/.orchestrator/artifacts/spec.md"), content).unwrap();
+            let hash = current_hash(&self.store, "a", "spec.md").unwrap();
+            self.acks.record_publication("a", "spec.md", &hash).unwrap();
+        }
+
+        /// Deliver the producer's current `spec.md` to the consumer.
+        fn deliver(&self) {
+            let provenance = Provenance::new("a", "t1");
+            self.store.copy("a", "b", "spec.md", &provenance).unwrap();
+        }
+
+        fn lag(&self) -> Option<Lag> {
+            lags(&self.store, &self.acks, ["b"]).unwrap().pop()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn counts_every_unacknowledged_received_version() {
+        let fixture = Fixture::new("received");
+        for content in ["v1", "v2", "v3", "v4"] {
+            fixture.publish(content);
+            fixture.deliver();
+        }
+        fixture.acks.acknowledge("b", "spec.md", 1).unwrap();
+
+        let lag = fixture.lag().unwrap();
+        assert_eq!((lag.received, lag.latest, lag.behind), (4, 4, 3));
+        assert!(lag.has_unacknowledged());
+
+        fixture.acks.acknowledge("b", "spec.md", 4).unwrap();
+        assert!(fixture.lag().is_none());
+    }
+
+    #[test]
+    fn counts_every_publication_not_yet_delivered() {
+        let fixture = Fixture::new("undelivered");
+        fixture.publish("v1");
+        fixture.deliver();
+        fixture.acks.acknowledge("b", "spec.md", 1).unwrap();
+        assert!(fixture.lag().is_none());
+
+        for content in ["v2", "v3", "v4"] {
+            fixture.publish(content);
+        }
+        let lag = fixture.lag().unwrap();
+        assert_eq!((lag.received, lag.latest, lag.behind), (1, 4, 3));
+        assert!(!lag.has_unacknowledged());
+        let first_pending = &fixture.acks.publications("a", "spec.md")[1];
+        assert_eq!(lag.waiting_since, first_pending.published_at);
+
+        fixture.deliver();
+        let lag = fixture.lag().unwrap();
+        assert_eq!((lag.received, lag.latest, lag.behind), (2, 2, 1));
+    }
+
+    #[test]
+    fn does_not_rehash_unpublished_changes() {
+        let fixture = Fixture::new("unpublished");
+        fixture.publish("v1");
+        fixture.deliver();
+        fixture.acks.acknowledge("b", "spec.md", 1).unwrap();
+
+        // Changed on disk but not yet seen by the watcher.
+        std::fs::write(fixture.dir.join("a/.orchestrator/artifacts/spec.md"), "v2").unwrap();
+        assert!(fixture.lag().is_none());
+    }
+}
@@ -0,0 +1,185 @@
+//! Artifact subscriptions between domains.
+//!
+//! A subscriber declares which of a producer's artifacts it depends on,
+//! either statically in `comm-node.toml` or at runtime with a
+//! `type: subscribe` message. When the producer writes a new version
+//! of a matching artifact, the orchestrator pushes it to every
+//! subscriber. Runtime subscriptions persist in the state directory.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::artifact::write_atomic;
+use crate::config::ProjectConfig;
+use crate::types::DomainId;
+
+/// How artifact patterns match names: `*` stays within one directory
+/// and `**` crosses them.
+pub(crate) const PATTERN_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// One domain's interest in a producer's artifacts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subscription {
+    /// Domain that receives the updates.
+    pub subscriber: DomainId,
+    /// Domain whose artifacts are watched.
+    pub producer: DomainId,
+    /// Artifact name or glob pattern (e.g. `openapi.yaml`, `proto/*.proto`).
+    pub artifact: String,
+}
+
+impl Subscription {
+    /// Whether this subscription covers `name` published by `producer`.
+    pub fn matches(&self, producer: &DomainId, name: &str) -> bool {
+        if &self.producer != producer {
+            return false;
+        }
+        match glob::Pattern::new(&self.artifact) {
+            Ok(pattern) => pattern.matches_with(name, PATTERN_OPTIONS),
+            Err(_) => self.artifact == name,
+        }
+    }
+}
+
+/// Registry of static (config) and runtime (message) subscriptions.
+pub struct SubscriptionRegistry {
+    /// Subscriptions declared in the project config.
+    configured: Vec<Subscription>,
+    /// Subscriptions added by `subscribe` messages, persisted to `path`.
+    runtime: Mutex<Vec<Subscription>>,
+    path: PathBuf,
+}
+
+impl SubscriptionRegistry {
+    /// Build a registry from the project config, restoring runtime
+    /// subscriptions from `path` if it exists.
+    pub fn load(config: &ProjectConfig, path: PathBuf) -> Result<Self> {
+        let configured = config
+            .domains
+            .iter()
+            .flat_map(|(id, dc)| {
+                dc.subscriptions.iter().map(move |s| Subscription {
+                    subscriber: id.clone(),
+                    producer: s.domain.clone(),
+                    artifact: s.artifact.clone(),
+                })
+            })
+            .collect();
+
+        let runtime = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .with_context(|| format!("parsing subscriptions: {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            configured,
+            runtime: Mutex::new(runtime),
+            path,
+        })
+    }
+
+    /// Add a runtime subscription. Returns `false` if it already existed.
+    pub fn subscribe(&self, subscription: Subscription) -> Result<bool> {
+        if self.configured.contains(&subscription) {
+            return Ok(false);
+        }
+
+        let mut runtime = self.runtime.lock().expect("subscription lock poisoned");
+        if runtime.contains(&subscription) {
+            return Ok(false);
+        }
+        runtime.push(subscription);
+
+        let json = serde_json::to_string_pretty(&*runtime)?;
+        write_atomic(&self.path, json.as_bytes())?;
+        Ok(true)
+    }
+
+    /// Domains subscribed to `name` published by `producer`.
+    pub fn subscribers(&self, producer: &DomainId, name: &str) -> Vec<DomainId> {
+        let runtime = self.runtime.lock().expect("subscription lock poisoned");
+        let mut subscribers: Vec<DomainId> = self
+            .configured
+            .iter()
+            .chain(runtime.iter())
+            .filter(|s| s.matches(producer, name))
+            .map(|s| s.subscriber.clone())
+            .collect();
+        subscribers.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        subscribers.dedup();
+        subscribers
+    }
+}
+
+/// Summarize how `new` differs from `old` for an update notice.
+///
+/// Text content gets added/removed line counts; anything that is not
+/// UTF-8 is reported by size only.
+pub fn diff_summary(old: Option<&[u8]>, new: &[u8]) -> String {
+    let Some(old) = old else {
+        return format!("new artifact ({} bytes)", new.len());
+    };
+
+    let (Ok(old_text), Ok(new_text)) = (std::str::from_utf8(old), std::str::from_utf8(new)) else {
+        return format!(
+            "binary content changed ({} -> {} bytes)",
+            old.len(),
+            new.len()
+        );
+    };
+
+    let mut remaining: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+    for line in old_text.lines() {
+        *remaining.entry(line).or_default() += 1;
+    }
+
+    let mut added = 0;
+    for line in new_text.lines() {
+        match remaining.get_mut(line) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => added += 1,
+        }
+    }
+    let removed: usize = remaining.values().sum();
+
+    format!(
+        "{} lines added, {} lines removed ({} -> {} bytes)",
+        added,
+        removed,
+        old.len(),
+        new.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscription(artifact: &str) -> Subscription {
+        Subscription {
+            subscriber: DomainId::new("b"),
+            producer: DomainId::new("a"),
+            artifact: artifact.to_string(),
+        }
+    }
+
+    #[test]
+    fn patterns_match_like_bundle_references() {
+        let a = DomainId::new("a");
+        assert!(subscription("proto/*.proto").matches(&a, "proto/users.proto"));
+        assert!(!subscription("proto/*.proto").matches(&a, "proto/v2/users.proto"));
+        assert!(subscription("proto/**/*.proto").matches(&a, "proto/v2/users.proto"));
+        assert!(!subscription("*.yaml").matches(&a, "api/openapi.yaml"));
+        assert!(subscription("openapi.yaml").matches(&a, "openapi.yaml"));
+        assert!(!subscription("openapi.yaml").matches(&DomainId::new("c"), "openapi.yaml"));
+    }
+}
@@ -0,0 +1,287 @@
+//! Supervision of domain agent processes.
+//!
+//! A domain with a `command` in its config has its agent launched by
+//! `comm-node start`. Each agent runs in its own task: stdout and
+//! stderr are appended to `<state dir>/agents/<domain>.log`, and when
+//! the process exits it is restarted according to the domain's restart
+//! policy, after a delay that doubles with each consecutive restart.
+//! Launches and exits are logged as `agent_started` and `agent_exited`
+//! events. On shutdown each agent's process group is sent SIGTERM,
+//! then killed if it has not exited within a grace period.
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::config::{ProjectConfig, RestartPolicy};
+use crate::event::{Event, EventLog};
+use crate::event_kind::{AgentExited, AgentStarted, EventKind};
+use crate::types::DomainId;
+
+/// Default delay before the first restart.
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Default longest delay between restarts.
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How long an agent has to exit after SIGTERM before it is killed.
+const STOP_GRACE: Duration = Duration::from_secs(5);
+
+/// How to launch one domain's agent.
+#[derive(Debug, Clone)]
+struct AgentSpec {
+    domain: DomainId,
+    program: String,
+    args: Vec<String>,
+    env: HashMap<String, String>,
+    working_dir: PathBuf,
+    restart: RestartPolicy,
+    log_path: PathBuf,
+}
+
+impl AgentSpec {
+    /// The command line, for logs and events.
+    fn command_line(&self) -> String {
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Launches and restarts the configured agent processes.
+pub struct Supervisor {
+    agents: Vec<AgentSpec>,
+    backoff: Duration,
+    max_backoff: Duration,
+    event_log: Arc<dyn EventLog>,
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Supervisor {
+    /// Prepare to supervise every domain with a `command`, creating the
+    /// agent log directory under `state_dir`.
+    pub fn new(
+        config: &ProjectConfig,
+        state_dir: &Path,
+        event_log: Arc<dyn EventLog>,
+    ) -> Result<Self> {
+        let log_dir = state_dir.join("agents");
+        let mut agents = Vec::new();
+        for (domain, dc) in &config.domains {
+            let Some(program) = &dc.command else {
+                continue;
+            };
+            agents.push(AgentSpec {
+                domain: domain.clone(),
+                program: program.clone(),
+                args: dc.args.clone(),
+                env: dc.env.clone(),
+                working_dir: dc
+                    .working_dir
+                    .as_ref()
+                    .map_or_else(|| dc.path.clone(), |dir| dc.path.join(dir)),
+                restart: dc.restart,
+                log_path: log_dir.join(format!("{}.log", domain)),
+            });
+        }
+        if !agents.is_empty() {
+            std::fs::create_dir_all(&log_dir)
+                .with_context(|| format!("creating agent log dir: {}", log_dir.display()))?;
+        }
+        agents.sort_by(|a, b| a.domain.as_str().cmp(b.domain.as_str()));
+
+        Ok(Self {
+            agents,
+            backoff: config
+                .agents
+                .restart_backoff_secs
+                .map_or(DEFAULT_BACKOFF, Duration::from_secs),
+            max_backoff: config
+                .agents
+                .restart_backoff_max_secs
+                .map_or(DEFAULT_MAX_BACKOFF, Duration::from_secs),
+            event_log,
+            shutdown: watch::channel(false).0,
+            tasks: Vec::new(),
+        })
+    }
+
+    /// Launch every agent, each supervised by its own task.
+    pub fn start(&mut self) {
+        for spec in &self.agents {
+            tracing::info!(
+                domain = %spec.domain,
+                command = %spec.command_line(),
+                log = %spec.log_path.display(),
+                "supervising agent"
+            );
+            let supervised = Supervised {
+                spec: spec.clone(),
+                backoff: self.backoff,
+                max_backoff: self.max_backoff,
+                event_log: self.event_log.clone(),
+                shutdown: self.shutdown.subscribe(),
+            };
+            self.tasks.push(tokio::spawn(supervised.run()));
+        }
+    }
+
+    /// Kill the running agents and wait for their tasks to finish.
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(true);
+        for task in self.tasks {
+            if let Err(e) = task.await {
+                tracing::error!(error = %e, "agent supervisor task failed");
+            }
+        }
+    }
+}
+
+/// One agent's supervision loop.
+struct Supervised {
+    spec: AgentSpec,
+    backoff: Duration,
+    max_backoff: Duration,
+    event_log: Arc<dyn EventLog>,
+    shutdown: watch::Receiver<bool>,
+}
+
+impl Supervised {
+    async fn run(mut self) {
+        let domain = self.spec.domain.clone();
+        let mut delay = self.backoff;
+        let mut attempt = 0u32;
+        loop {
+            attempt += 1;
+            let started = Instant::now();
+            let success = match self.spawn() {
+                Ok(child) => match self.wait(child, attempt).await {
+                    Some(success) => success,
+                    // Shut down while running.
+                    None => return,
+                },
+                Err(e) => {
+                    tracing::error!(domain = %domain, error = %e, "failed to launch agent");
+                    false
+                }
+            };
+
+            let restart = match self.spec.restart {
+                RestartPolicy::Never => false,
+                RestartPolicy::OnFailure => !success,
+                RestartPolicy::Always => true,
+            };
+            if !restart {
+                tracing::info!(domain = %domain, "agent stopped; not restarting");
+                return;
+            }
+
+            // A process that stayed up long enough starts the backoff over.
+            if started.elapsed() >= self.max_backoff {
+                delay = self.backoff;
+            }
+            tracing::info!(domain = %domain, delay_secs = delay.as_secs_f64(), "restarting agent");
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = self.shutdown.changed() => return,
+            }
+            delay = (delay * 2).min(self.max_backoff);
+        }
+    }
+
+    /// Launch the agent process with its output appended to its log.
+    fn spawn(&self) -> Result<tokio::process::Child> {
+        let spec = &self.spec;
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&spec.log_path)
+            .with_context(|| format!("opening agent log: {}", spec.log_path.display()))?;
+        let stderr = log.try_clone()?;
+
+        let mut command = tokio::process::Command::new(&spec.program);
+        // Its own process group, so that stopping it reaches any
+        // processes it started and a terminal ctrl-c does not.
+        #[cfg(unix)]
+        command.process_group(0);
+        command
+            .args(&spec.args)
+            .envs(&spec.env)
+            .current_dir(&spec.working_dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::from(log))
+            .stderr(Stdio::from(stderr))
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("running `{}`", spec.command_line()))
+    }
+
+    /// Wait for `child` to exit, logging its start and exit. Returns
+    /// whether it exited successfully, or `None` if shutdown killed it.
+    async fn wait(&mut self, mut child: tokio::process::Child, attempt: u32) -> Option<bool> {
+        let domain = self.spec.domain.clone();
+        let pid = child.id();
+        let started = Instant::now();
+        tracing::info!(domain = %domain, pid = ?pid, attempt, "agent started");
+        self.log(EventKind::AgentStarted(AgentStarted {
+            domain: domain.to_string(),
+            pid,
+            command: self.spec.command_line(),
+            attempt,
+        }));
+
+        let (status, shutting_down) = tokio::select! {
+            status = child.wait() => (status, false),
+            _ = self.shutdown.changed() => (stop(&mut child).await, true),
+        };
+
+        let (code, description, success) = match &status {
+            Ok(status) => (status.code(), status.to_string(), status.success()),
+            Err(e) => (None, format!("wait failed: {}", e), false),
+        };
+        tracing::info!(domain = %domain, pid = ?pid, status = %description, "agent exited");
+        self.log(EventKind::AgentExited(AgentExited {
+            domain: domain.to_string(),
+            pid,
+            code,
+            status: description,
+            uptime_secs: started.elapsed().as_secs(),
+        }));
+
+        (!shutting_down).then_some(success)
+    }
+
+    fn log(&self, kind: EventKind) {
+        if let Err(e) = self.event_log.log(&Event::new(kind)) {
+            tracing::error!(error = %e, "failed to log agent process event");
+        }
+    }
+}
+
+/// Ask an agent to exit, killing it after [`STOP_GRACE`].
+async fn stop(child: &mut tokio::process::Child) -> std::io::Result<std::process::ExitStatus> {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // The agent leads its own process group; signal all of it.
+        let group = pid as libc::pid_t;
+        // SAFETY: killpg only sends a signal; it has no memory effects.
+        unsafe { libc::killpg(group, libc::SIGTERM) };
+        if let Ok(status) = tokio::time::timeout(STOP_GRACE, child.wait()).await {
+            return status;
+        }
+        // SAFETY: as above.
+        unsafe { libc::killpg(group, libc::SIGKILL) };
+        return child.wait().await;
+    }
+    child.start_kill()?;
+    child.wait().await
+}
@@ -56,6 +56,18 @@ pub enum AgentState {
     Complete,
 }
 
+impl std::fmt::Display for AgentState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Idle => "idle",
+            Self::Working => "working",
+            Self::Blocked => "blocked",
+            Self::Complete => "complete",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 /// Agent status written to `.orchestrator/status.json`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentStatus {
@@ -1,15 +1,19 @@
-//! Filesystem watcher for agent outbox directories.
+//! Filesystem watchers for agent outbox and artifact directories and
+//! agent status files.
 //!
 //! Uses the `notify` crate (inotify on Linux, FSEvents on macOS)
-//! to detect new messages written by agents, then feeds them
-//! to the router for processing.
+//! to detect new messages and artifacts written by agents, then
+//! feeds them to the router for processing.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use tokio::sync::mpsc;
 
+use crate::artifact;
+use crate::heartbeat::STATUS_FILE;
+
 /// Watches outbox directories for new messages from agents.
 pub struct OutboxWatcher {
     _watcher: RecommendedWatcher,
@@ -19,7 +23,7 @@ pub struct OutboxWatcher {
 impl OutboxWatcher {
     /// Create a new watcher that monitors the given outbox directories.
     ///
-    /// Only forwards `Create` events for `.md` files — ignores modify,
+    /// Only forwards `Create` events for `.md` and `.json` files — ignores modify,
     /// remove, access, directories, and temp files.
     pub fn new(outbox_dirs: Vec<PathBuf>) -> Result<Self> {
         let (tx, rx) = mpsc::channel(256);
@@ -33,12 +37,8 @@ impl OutboxWatcher {
                     }
 
                     for path in event.paths {
-                        // Only forward .md files.
-                        let is_md = path
-                            .extension()
-                            .is_some_and(|ext| ext.eq_ignore_ascii_case("md"));
-
-                        if is_md {
+                        // Only forward .md and .json files.
+                        if is_message_file(&path) {
                             if let Err(e) = tx.blocking_send(path) {
                                 tracing::error!(error = %e, "failed to send watcher event");
                             }
@@ -61,3 +61,126 @@ impl OutboxWatcher {
         })
     }
 }
+
+/// Whether a path has a message extension (`.md` or `.json`).
+fn is_message_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("json"))
+}
+
+/// Watches domain `artifacts/` directories for newly written artifacts.
+pub struct ArtifactWatcher {
+    _watcher: RecommendedWatcher,
+    pub events: mpsc::Receiver<PathBuf>,
+}
+
+impl ArtifactWatcher {
+    /// Create a new watcher that recursively monitors the given artifact directories.
+    ///
+    /// Forwards completed writes to regular files, skipping in-flight
+    /// temp files.
+    pub fn new(artifact_dirs: Vec<PathBuf>) -> Result<Self> {
+        let (tx, rx) = mpsc::channel(256);
+
+        let mut watcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+                Ok(event) => {
+                    if !is_write_complete(&event.kind) {
+                        return;
+                    }
+
+                    for path in event.paths {
+                        let temp = path
+                            .file_name()
+                            .and_then(|f| f.to_str())
+                            .is_none_or(artifact::is_temp_name);
+                        if temp || !path.is_file() {
+                            continue;
+                        }
+                        if let Err(e) = tx.blocking_send(path) {
+                            tracing::error!(error = %e, "failed to send artifact watcher event");
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "filesystem watch error");
+                }
+            })?;
+
+        for dir in &artifact_dirs {
+            watcher.watch(dir, RecursiveMode::Recursive)?;
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            events: rx,
+        })
+    }
+}
+
+/// Whether an event marks a file as fully written.
+///
+/// On Linux this is close-after-write or a rename into place, so a file
+/// is not picked up while still empty or half written. Other platforms
+/// have no close events and fall back to create/modify.
+fn is_write_complete(kind: &EventKind) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        use notify::event::{AccessKind, AccessMode, ModifyKind};
+        matches!(
+            kind,
+            EventKind::Access(AccessKind::Close(AccessMode::Write))
+                | EventKind::Modify(ModifyKind::Name(_))
+        )
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        matches!(kind, EventKind::Create(_) | EventKind::Modify(_))
+    }
+}
+
+/// Watches domain `.orchestrator/` directories for `status.json` updates.
+pub struct StatusWatcher {
+    _watcher: RecommendedWatcher,
+    pub events: mpsc::Receiver<PathBuf>,
+}
+
+impl StatusWatcher {
+    /// Create a new watcher over the given `.orchestrator/` directories.
+    ///
+    /// Forwards completed writes to `status.json`, including a file
+    /// renamed into place.
+    pub fn new(orchestrator_dirs: Vec<PathBuf>) -> Result<Self> {
+        let (tx, rx) = mpsc::channel(64);
+
+        let mut watcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+                Ok(event) => {
+                    if !is_write_complete(&event.kind) {
+                        return;
+                    }
+
+                    for path in event.paths {
+                        if path.file_name().is_none_or(|f| f != STATUS_FILE) || !path.is_file() {
+                            continue;
+                        }
+                        if let Err(e) = tx.blocking_send(path) {
+                            tracing::error!(error = %e, "failed to send status watcher event");
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "filesystem watch error");
+                }
+            })?;
+
+        for dir in &orchestrator_dirs {
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            events: rx,
+        })
+    }
+}